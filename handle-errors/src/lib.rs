@@ -3,11 +3,12 @@ use warp::{
         body::BodyDeserializeError,
         cors::CorsForbidden,
     }, 
-    reject::Reject,
+    reject::{InvalidQuery, PayloadTooLarge, Reject},
     Rejection, 
     Reply, 
-    http::StatusCode,
+    http::{Method, StatusCode},
 };
+use serde::Serialize;
 use tracing::{event, Level, instrument};
 use reqwest::Error as ReqwestError;
 use reqwest_middleware::Error as MiddlewareReqwestError;
@@ -15,15 +16,60 @@ use reqwest_middleware::Error as MiddlewareReqwestError;
 pub enum Error {
     ParseError(std::num::ParseIntError),
     MissingParameters,
+    InvalidParameter(String),
+    /// The query string is not valid percent-encoded UTF-8
+    InvalidQuery(String),
+    Validation(Vec<FieldError>),
+    /// Every problem `Config::validate` found with the settings
+    InvalidConfig(Vec<FieldError>),
+    /// Question content below the configured minimum length
+    ContentTooShort(usize),
+    JsonLimitExceeded(String),
+    /// The body isn't JSON at all, as opposed to JSON of the wrong shape
+    MalformedJson(String),
+    InvalidBody(String),
+    /// A JSON body carried a field the endpoint doesn't know, refused when
+    /// strict JSON is on
+    UnknownField(String),
+    /// The body's content type isn't one the endpoint accepts
+    UnsupportedMediaType(String),
+    /// The body grew past the endpoint's limit while it was read
+    BodyTooLarge,
     WrongPassword,
     CannotDecryptToken,
     Unauthorized,
+    MethodNotAllowed(Vec<Method>),
+    ServiceOverloaded,
+    /// Too many requests from one client; seconds until it may retry
+    TooManyRequests(u64),
+    /// The route is switched off in this deployment; answered with the
+    /// configured status
+    RouteDisabled(StatusCode),
     ArgonLibraryError(argon2::Error),
+    BcryptLibraryError(bcrypt::BcryptError),
     QuestionNotFound,
+    AccountNotFound,
+    AnswerNotFound,
+    ApiTokenNotFound,
+    DuplicateAnswer,
+    DuplicateAccount,
+    BlockedEmailDomain,
+    RegistrationClosed,
+    EmailNotVerified,
+    AccountSuspended,
+    /// The account is younger than the configured minimum age for posting
+    AccountTooNew,
+    EditWindowExpired,
+    PreconditionFailed,
+    DeletionNotScheduled,
+    InvalidVerificationToken,
+    InvalidResetToken,
     DatabaseQueryError(sqlx::Error),
     MigrationError(sqlx::migrate::MigrateError),
     ReqwestAPIError(ReqwestError),
     MiddlewareReqwestError(MiddlewareReqwestError),
+    /// The bad words API didn't answer within the configured timeout
+    ProfanityCheckTimedOut,
     ClientError(APILayerError),
     ServerError(APILayerError),
 }
@@ -33,6 +79,29 @@ pub struct APILayerError {
     pub message: String,
 }
 
+/// A single failed check on an input field
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// JSON body for errors clients are expected to branch on by `code`
+#[derive(Serialize, Debug)]
+pub struct ErrorBody {
+    pub error: String,
+    pub code: &'static str,
+}
+
 impl std::fmt::Display for APILayerError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "Status: {}, Message: {}", self.status, self.message)
@@ -45,16 +114,93 @@ impl std::fmt::Display for Error {
                 write!(f, "Cannot parse parameter: {}", err)
             }
             Error::MissingParameters => write!(f, "Missing parameter"),
+            Error::InvalidParameter(err) => {
+                write!(f, "Invalid parameter: {}", err)
+            }
+            Error::InvalidQuery(err) => {
+                write!(f, "Invalid query string: {}", err)
+            }
+            Error::Validation(errors) => {
+                let fields = errors
+                    .iter()
+                    .map(|e| format!("{}: {}", e.field, e.message))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "Invalid input: {}", fields)
+            }
+            Error::InvalidConfig(errors) => {
+                let fields = errors
+                    .iter()
+                    .map(|e| format!("{}: {}", e.field, e.message))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "Invalid configuration: {}", fields)
+            }
+            Error::JsonLimitExceeded(err) => {
+                write!(f, "Request body too complex: {}", err)
+            }
+            Error::UnknownField(field) => write!(f, "Unknown field `{}`", field),
+            Error::MalformedJson(err) => write!(f, "Malformed JSON: {}", err),
+            Error::UnsupportedMediaType(content_type) => {
+                write!(f, "Unsupported content type `{}`", content_type)
+            }
+            Error::BodyTooLarge => write!(f, "Request body too large"),
+            Error::InvalidBody(err) => {
+                write!(f, "Request body deserialize error: {}", err)
+            }
+            Error::ContentTooShort(min) => {
+                write!(f, "Content must be at least {} characters long", min)
+            }
             Error::WrongPassword => write!(f, "Wrong password"),
             Error::CannotDecryptToken => {
                 write!(f, "Cannot decrypt error")
             }
             Error::Unauthorized => write!(f, 
                 "No permisssion to change the underlying rsource"),
+            Error::MethodNotAllowed(_) => write!(f, "Method not allowed"),
+            Error::ServiceOverloaded => write!(f, "Server is busy"),
+            Error::TooManyRequests(retry_after) => {
+                write!(f, "Too many requests, retry in {} seconds", retry_after)
+            }
+            Error::RouteDisabled(_) => write!(f, "Route is disabled"),
             Error::ArgonLibraryError(_) => {
                 write!(f, "Connot verify password")
             }
+            Error::BcryptLibraryError(_) => {
+                write!(f, "Cannot verify password")
+            }
             Error::QuestionNotFound => write!(f, "Question not found"),
+            Error::AccountNotFound => write!(f, "Account not found"),
+            Error::AnswerNotFound => write!(f, "Answer not found"),
+            Error::ApiTokenNotFound => write!(f, "API token not found"),
+            Error::DuplicateAnswer => {
+                write!(f, "Answer already posted to this question")
+            }
+            Error::DuplicateAccount => write!(f, "Account already exists"),
+            Error::BlockedEmailDomain => {
+                write!(f, "Registrations from this email domain are not allowed")
+            }
+            Error::RegistrationClosed => write!(f, "Registration is closed"),
+            Error::EmailNotVerified => {
+                write!(f, "Email address has not been verified")
+            }
+            Error::AccountSuspended => write!(f, "Account is suspended"),
+            Error::AccountTooNew => write!(f, "Account is too new to post yet"),
+            Error::DeletionNotScheduled => {
+                write!(f, "No account deletion is scheduled")
+            }
+            Error::EditWindowExpired => {
+                write!(f, "Question can no longer be edited")
+            }
+            Error::PreconditionFailed => {
+                write!(f, "Question was modified since")
+            }
+            Error::InvalidVerificationToken => {
+                write!(f, "Verification token is invalid or expired")
+            }
+            Error::InvalidResetToken => {
+                write!(f, "Password reset token is invalid or expired")
+            }
             Error::DatabaseQueryError(_) => {
                 write!(f, "Cannot update, invalid data.")
             }
@@ -65,6 +211,9 @@ impl std::fmt::Display for Error {
             Error::MiddlewareReqwestError(err) => {
                 write!(f, "External API Error: {}", err)
             }
+            Error::ProfanityCheckTimedOut => {
+                write!(f, "Content check timed out")
+            }
             Error::ClientError(err) => {
                 write!(f, "External Client error: {}", err)
             }
@@ -90,19 +239,22 @@ pub async fn return_error(r: Rejection) -> Result<impl Reply, Rejection> {
                     Ok(warp::reply::with_status(
                             "Account already exists".to_string(),
                             StatusCode::UNPROCESSABLE_ENTITY,
-                        ))
+                    )
+                    .into_response())
                 } else {
                     Ok(warp::reply::with_status(
                         "Cannot update data".to_string(),
                         StatusCode::UNPROCESSABLE_ENTITY,
-                    ))
+                    )
+                    .into_response())
                 }
             }
             _ => {
                 Ok(warp::reply::with_status(
                     "Cannot not update data".to_string(),
                     StatusCode::UNPROCESSABLE_ENTITY,
-                ))  
+                )
+                .into_response())  
             }
         }
         
@@ -111,60 +263,331 @@ pub async fn return_error(r: Rejection) -> Result<impl Reply, Rejection> {
         Ok(warp::reply::with_status(
             "Internal Server Error".to_string(),
             StatusCode::INTERNAL_SERVER_ERROR, 
-        ))
+        )
+        .into_response())
     } else if let Some(crate::Error::Unauthorized) = r.find() {
         event!(Level::ERROR, "Not matching account id");
         Ok(warp::reply::with_status(
             "No permission to changing underlying resource".to_string(),
             StatusCode::UNAUTHORIZED, 
-        ))
+        )
+        .into_response())
+    } else if let Some(crate::Error::MethodNotAllowed(allowed)) = r.find() {
+        event!(Level::WARN, "Method not allowed, supported: {:?}", allowed);
+        let allow = allowed
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        Ok(warp::reply::with_header(
+            warp::reply::with_status(
+                "Method not allowed".to_string(),
+                StatusCode::METHOD_NOT_ALLOWED,
+            ),
+            "Allow",
+            allow,
+        )
+        .into_response())
+    } else if let Some(crate::Error::TooManyRequests(retry_after)) = r.find() {
+        event!(Level::WARN, "Rate limited, retry in {} seconds", retry_after);
+        Ok(warp::reply::with_header(
+            warp::reply::with_status(
+                format!("Too many requests, retry in {} seconds", retry_after),
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+            "Retry-After",
+            retry_after.to_string(),
+        )
+        .into_response())
+    } else if let Some(crate::Error::ServiceOverloaded) = r.find() {
+        event!(Level::WARN, "Shedding request, concurrency limit reached");
+        Ok(warp::reply::with_status(
+            "Server is busy, try again later".to_string(),
+            StatusCode::SERVICE_UNAVAILABLE,
+        )
+        .into_response())
+    } else if let Some(crate::Error::RouteDisabled(status)) = r.find() {
+        event!(Level::INFO, "Request to a disabled route");
+        let message = if *status == StatusCode::NOT_FOUND {
+            "Route not found"
+        } else {
+            "Route is disabled"
+        };
+        Ok(warp::reply::with_status(message.to_string(), *status).into_response())
+    } else if let Some(crate::Error::ApiTokenNotFound) = r.find() {
+        event!(Level::WARN, "API token not found");
+        Ok(warp::reply::with_status(
+            "API token not found".to_string(),
+            StatusCode::NOT_FOUND,
+        )
+        .into_response())
+    } else if let Some(crate::Error::AccountNotFound) = r.find() {
+        event!(Level::WARN, "Account not found");
+        Ok(warp::reply::with_status(
+            "Account not found".to_string(),
+            StatusCode::NOT_FOUND,
+        )
+        .into_response())
+    } else if let Some(crate::Error::AnswerNotFound) = r.find() {
+        event!(Level::WARN, "Answer not found");
+        Ok(warp::reply::with_status(
+            "Answer not found".to_string(),
+            StatusCode::NOT_FOUND,
+        )
+        .into_response())
+    } else if let Some(crate::Error::QuestionNotFound) = r.find() {
+        event!(Level::WARN, "Question not found");
+        Ok(warp::reply::with_status(
+            "Question not found".to_string(),
+            StatusCode::NOT_FOUND,
+        )
+        .into_response())
+    } else if let Some(crate::Error::InvalidParameter(e)) = r.find() {
+        event!(Level::ERROR, "Invalid parameter: {}", e);
+        Ok(warp::reply::with_status(
+            format!("Invalid parameter: {}", e),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response())
+    } else if let Some(crate::Error::InvalidQuery(e)) = r.find() {
+        event!(Level::WARN, "Invalid query string: {}", e);
+        Ok(warp::reply::with_status(
+            format!("Invalid query string: {}", e),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response())
+    } else if let Some(error) = r.find::<InvalidQuery>() {
+        event!(Level::WARN, "{}", error);
+        Ok(warp::reply::with_status(
+            "Invalid query string: expected percent-encoded key=value pairs".to_string(),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response())
+    } else if let Some(crate::Error::Validation(errors)) = r.find() {
+        event!(Level::WARN, "Invalid input: {:?}", errors);
+        Ok(warp::reply::with_status(
+            warp::reply::json(errors),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+        .into_response())
+    } else if let Some(crate::Error::JsonLimitExceeded(e)) = r.find() {
+        event!(Level::ERROR, "Request body over the JSON limits: {}", e);
+        Ok(warp::reply::with_status(
+            format!("Request body too complex: {}", e),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response())
+    } else if let Some(crate::Error::UnknownField(field)) = r.find() {
+        event!(Level::WARN, "Request body with unknown field `{}`", field);
+        Ok(warp::reply::with_status(
+            format!("Unknown field `{}`", field),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response())
+    } else if let Some(crate::Error::UnsupportedMediaType(content_type)) = r.find() {
+        event!(Level::WARN, "Request body of content type `{}`", content_type);
+        Ok(warp::reply::with_status(
+            format!("Unsupported content type `{}`", content_type),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        )
+        .into_response())
+    } else if let Some(crate::Error::BodyTooLarge) = r.find() {
+        event!(Level::WARN, "Request body over the size limit");
+        Ok(warp::reply::with_status(
+            "Request body too large".to_string(),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        )
+        .into_response())
+    } else if let Some(crate::Error::MalformedJson(e)) = r.find() {
+        event!(Level::WARN, "Request body is not JSON: {}", e);
+        Ok(warp::reply::with_status(
+            format!("Malformed JSON: {}", e),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response())
+    } else if let Some(crate::Error::InvalidBody(e)) = r.find() {
+        event!(Level::ERROR, "Cannot deserizalize request body: {}", e);
+        Ok(warp::reply::with_status(
+            format!("Request body deserialize error: {}", e),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+        .into_response())
+    } else if let Some(crate::Error::ContentTooShort(min)) = r.find() {
+        event!(Level::WARN, "Question content too short");
+        Ok(warp::reply::with_status(
+            format!("Content must be at least {} characters long", min),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response())
+    } else if let Some(crate::Error::DuplicateAnswer) = r.find() {
+        event!(Level::ERROR, "Duplicate answer from the same account");
+        Ok(warp::reply::with_status(
+            "Answer already posted to this question".to_string(),
+            StatusCode::CONFLICT,
+        )
+        .into_response())
+    } else if let Some(crate::Error::DuplicateAccount) = r.find() {
+        event!(Level::ERROR, "Account already exists");
+        Ok(warp::reply::with_status(
+            "Account already exists".to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+        .into_response())
+    } else if let Some(crate::Error::BlockedEmailDomain) = r.find() {
+        event!(Level::WARN, "Registration from a blocked email domain");
+        Ok(warp::reply::with_status(
+            "Registrations from this email domain are not allowed".to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+        .into_response())
+    } else if let Some(crate::Error::EmailNotVerified) = r.find() {
+        event!(Level::WARN, "Write from an account with an unverified email");
+        Ok(warp::reply::with_status(
+            "Verify your email address before posting".to_string(),
+            StatusCode::FORBIDDEN,
+        )
+        .into_response())
+    } else if let Some(crate::Error::RegistrationClosed) = r.find() {
+        event!(Level::WARN, "Registration over the account limit");
+        Ok(warp::reply::with_status(
+            "Registration is closed".to_string(),
+            StatusCode::FORBIDDEN,
+        )
+        .into_response())
+    } else if let Some(crate::Error::AccountSuspended) = r.find() {
+        event!(Level::WARN, "Write from a suspended account");
+        Ok(warp::reply::with_status(
+            "Account is suspended".to_string(),
+            StatusCode::FORBIDDEN,
+        )
+        .into_response())
+    } else if let Some(crate::Error::AccountTooNew) = r.find() {
+        event!(Level::WARN, "Post from an account below the minimum age");
+        Ok(warp::reply::with_status(
+            "Account is too new to post yet".to_string(),
+            StatusCode::FORBIDDEN,
+        )
+        .into_response())
+    } else if let Some(crate::Error::DeletionNotScheduled) = r.find() {
+        Ok(warp::reply::with_status(
+            "No account deletion is scheduled".to_string(),
+            StatusCode::CONFLICT,
+        )
+        .into_response())
+    } else if let Some(crate::Error::EditWindowExpired) = r.find() {
+        event!(Level::WARN, "Edit after the question edit window closed");
+        Ok(warp::reply::with_status(
+            "Question can no longer be edited".to_string(),
+            StatusCode::FORBIDDEN,
+        )
+        .into_response())
+    } else if let Some(crate::Error::PreconditionFailed) = r.find() {
+        event!(Level::WARN, "Update of a question modified since If-Unmodified-Since");
+        Ok(warp::reply::with_status(
+            "Question was modified since".to_string(),
+            StatusCode::PRECONDITION_FAILED,
+        )
+        .into_response())
+    } else if let Some(crate::Error::InvalidVerificationToken) = r.find() {
+        event!(Level::WARN, "Invalid or expired verification token");
+        Ok(warp::reply::with_status(
+            "Verification token is invalid or expired".to_string(),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response())
+    } else if let Some(crate::Error::InvalidResetToken) = r.find() {
+        event!(Level::WARN, "Invalid or expired password reset token");
+        Ok(warp::reply::with_status(
+            "Password reset token is invalid or expired".to_string(),
+            StatusCode::BAD_REQUEST,
+        )
+        .into_response())
     } else if let Some(crate::Error::WrongPassword) = r.find() {
         event!(Level::ERROR, "Entered wrong password");
         Ok(warp::reply::with_status(
             "Wrong E-Mail/Password combination".to_string(),
             StatusCode::UNAUTHORIZED, 
-        ))
+        )
+        .into_response())
+    } else if let Some(crate::Error::ArgonLibraryError(e)) = r.find() {
+        event!(Level::ERROR, "Cannot verify argon2 password hash: {}", e);
+        Ok(warp::reply::with_status(
+            "Internal Server Error".to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response())
+    } else if let Some(crate::Error::BcryptLibraryError(e)) = r.find() {
+        event!(Level::ERROR, "Cannot verify bcrypt password hash: {}", e);
+        Ok(warp::reply::with_status(
+            "Internal Server Error".to_string(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+        .into_response())
     } else if let Some(crate::Error::MiddlewareReqwestError(e)) = r.find() {
         event!(Level::ERROR, "{}", e);
         Ok(warp::reply::with_status(
             "Internal Server Error".to_string(),
             StatusCode::INTERNAL_SERVER_ERROR, 
-        ))
+        )
+        .into_response())
+    } else if let Some(crate::Error::ProfanityCheckTimedOut) = r.find() {
+        event!(Level::WARN, "Bad words API timed out");
+        Ok(warp::reply::with_status(
+            "Content check timed out, try again later".to_string(),
+            StatusCode::GATEWAY_TIMEOUT,
+        )
+        .into_response())
     } else if let Some(crate::Error::ClientError(e)) = r.find() {
         event!(Level::ERROR, "{}", e);
         Ok(warp::reply::with_status(
             "Internal Server Error".to_string(),
             StatusCode::INTERNAL_SERVER_ERROR, 
-        ))
+        )
+        .into_response())
     } else if let Some(crate::Error::ServerError(e)) = r.find() {
         event!(Level::ERROR, "{}", e);
         Ok(warp::reply::with_status(
             "Internal Server Error".to_string(),
             StatusCode::INTERNAL_SERVER_ERROR, 
-        ))
+        )
+        .into_response())
+    } else if let Some(error) = r.find::<PayloadTooLarge>() {
+        event!(Level::ERROR, "{}", error);
+        Ok(warp::reply::with_status(
+            "Request body too large".to_string(),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        )
+        .into_response())
     } else if let Some(error) = r.find::<BodyDeserializeError>() {
         event!(Level::ERROR, "Cannot deserizalize request body: {}", error);
         Ok(warp::reply::with_status(
             error.to_string(),
             StatusCode::UNPROCESSABLE_ENTITY, 
-        ))
+        )
+        .into_response())
     } else if let Some(error) = r.find::<Error>() {
         event!(Level::ERROR, "{}", error);
         Ok(warp::reply::with_status(
             error.to_string(),
             StatusCode::RANGE_NOT_SATISFIABLE,
-        ))
+        )
+        .into_response())
     } else if let Some(error) = r.find::<CorsForbidden>() {
         event!(Level::ERROR, "CORS forbidden error: {}", error);
         Ok(warp::reply::with_status(
-            error.to_string(),
+            warp::reply::json(&ErrorBody {
+                error: error.to_string(),
+                code: "CORS_FORBIDDEN",
+            }),
             StatusCode::FORBIDDEN,
-        ))
+        )
+        .into_response())
     } else {
         event!(Level::WARN, "Requested route was not found");
         Ok(warp::reply::with_status(
             "Route not found".to_string(),
             StatusCode::NOT_FOUND, 
-        ))
+        )
+        .into_response())
     }
 }
\ No newline at end of file
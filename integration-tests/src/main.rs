@@ -69,7 +69,7 @@ async fn main() -> Result<(), handle_errors::Error> {
 
     let store = setup_store(&config).await?;
 
-    let handler = oneshot(store).await;
+    let handler = oneshot(config, store).await;
 
     let u = User {
         email: "test@email.com".to_string(),
@@ -107,7 +107,7 @@ async fn main() -> Result<(), handle_errors::Error> {
     }
 
     print!("Running post_question...");
-    match std::panic::AssertUnwindSafe(post_question(token)).catch_unwind().await {
+    match std::panic::AssertUnwindSafe(post_question(token.clone())).catch_unwind().await {
         Ok(_) => println!("√"),
         Err(_) => {
             let _ = handler.sender.send(1);
@@ -115,6 +115,24 @@ async fn main() -> Result<(), handle_errors::Error> {
         }
     }    
 
+    print!("Running post_duplicate_answer...");
+    match std::panic::AssertUnwindSafe(post_duplicate_answer(token.clone())).catch_unwind().await {
+        Ok(_) => println!("√"),
+        Err(_) => {
+            let _ = handler.sender.send(1);
+            std::process::exit(1);
+        }
+    }
+
+    print!("Running sort_by_answer_count...");
+    match std::panic::AssertUnwindSafe(sort_by_answer_count(token)).catch_unwind().await {
+        Ok(_) => println!("√"),
+        Err(_) => {
+            let _ = handler.sender.send(1);
+            std::process::exit(1);
+        }
+    }
+
     let _ = handler.sender.send(1);
 
     Ok (())
@@ -172,4 +190,53 @@ async fn post_question(token: Token) {
 
     assert_eq!(res.id, 1);
     assert_eq!(res.title, q.title);
+}
+
+async fn post_duplicate_answer(token: Token) {
+    let client = reqwest::Client::new();
+    let post = || {
+        client
+            .post("http://localhost:3030/answers")
+            .header("Authorization", token.0.clone())
+            .form(&[("content", "Write a test."), ("question_id", "1")])
+            .send()
+    };
+
+    let res = post().await.unwrap();
+    assert_eq!(res.status(), 201);
+
+    let res = post().await.unwrap();
+    assert_eq!(res.status(), 409);
+}
+
+// The answer count is a join only Postgres runs, so this is where a broken
+// query would show
+async fn sort_by_answer_count(token: Token) {
+    let q = Question {
+        title: "Second Question".to_string(),
+        content: "Does anyone answer this?".to_string(),
+    };
+
+    let client = reqwest::Client::new();
+    let res = client
+        .post("http://localhost:3030/questions")
+        .header("Authorization", token.0)
+        .json(&q)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 200);
+
+    for (order, expected) in [("desc", [1, 2]), ("asc", [2, 1])] {
+        let res = client
+            .get(format!("http://localhost:3030/questions?sort=answers&order={}", order))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), 200);
+
+        let questions = res.json::<Vec<QuestionAnswer>>().await.unwrap();
+        let ids: Vec<i32> = questions.iter().map(|q| q.id).collect();
+        assert_eq!(ids, expected);
+    }
 }
\ No newline at end of file
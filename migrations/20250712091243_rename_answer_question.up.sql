@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE answers
+RENAME COLUMN corresponding_question TO question_id;
\ No newline at end of file
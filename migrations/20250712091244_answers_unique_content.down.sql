@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS answers_unique_content;
\ No newline at end of file
@@ -0,0 +1,15 @@
+-- Add up migration script here
+-- Keep the oldest of any duplicates already stored, or the index can't be built
+DELETE FROM answers a
+USING answers b
+WHERE a.question_id = b.question_id
+  AND a.account_id = b.account_id
+  AND md5(lower(regexp_replace(btrim(a.content), '\s+', ' ', 'g')))
+    = md5(lower(regexp_replace(btrim(b.content), '\s+', ' ', 'g')))
+  AND a.id > b.id;
+
+-- Content of deleted accounts is pooled under account 0, where different
+-- people's identical answers may meet
+CREATE UNIQUE INDEX IF NOT EXISTS answers_unique_content
+ON answers (question_id, account_id, md5(lower(regexp_replace(btrim(content), '\s+', ' ', 'g'))))
+WHERE account_id <> 0;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE answers
+ADD COLUMN parent_answer_id integer REFERENCES answers ON DELETE CASCADE;
\ No newline at end of file
@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- No foreign key on account_id: votes outlive a deleted account so scores
+-- don't shift. Account ids come from a sequence and are never handed out
+-- again, so nobody inherits them.
+CREATE TABLE IF NOT EXISTS votes (
+    account_id integer NOT NULL,
+    question_id integer NOT NULL REFERENCES questions ON DELETE CASCADE,
+    value SMALLINT NOT NULL CHECK (value IN (-1, 1)),
+    created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (account_id, question_id)
+);
\ No newline at end of file
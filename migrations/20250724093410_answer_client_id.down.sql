@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS answers_account_client_id;
+
+ALTER TABLE answers
+DROP COLUMN client_id;
\ No newline at end of file
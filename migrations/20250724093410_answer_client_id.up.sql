@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE answers
+ADD COLUMN client_id TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS answers_account_client_id
+ON answers (account_id, client_id);
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE accounts
+DROP CONSTRAINT IF EXISTS accounts_id_key;
\ No newline at end of file
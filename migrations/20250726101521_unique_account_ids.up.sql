@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Lets other tables reference accounts by id rather than email
+ALTER TABLE accounts
+ADD CONSTRAINT accounts_id_key UNIQUE (id);
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS email_verifications;
+ALTER TABLE accounts
+DROP COLUMN verified;
\ No newline at end of file
@@ -0,0 +1,12 @@
+-- Add up migration script here
+-- Accounts created before verification existed count as verified
+ALTER TABLE accounts
+ADD COLUMN verified BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE accounts
+ALTER COLUMN verified SET DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS email_verifications (
+    token TEXT PRIMARY KEY,
+    account_id integer NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    expires_at TIMESTAMP NOT NULL
+);
\ No newline at end of file
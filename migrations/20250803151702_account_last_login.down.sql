@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE accounts
+DROP COLUMN last_login_at,
+DROP COLUMN last_login_ip;
\ No newline at end of file
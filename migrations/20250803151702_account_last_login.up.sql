@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE accounts
+ADD COLUMN last_login_at TIMESTAMPTZ,
+ADD COLUMN last_login_ip TEXT;
\ No newline at end of file
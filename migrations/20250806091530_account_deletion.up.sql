@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Set while an account waits out its deletion grace period
+ALTER TABLE accounts
+ADD COLUMN deletion_due_at TIMESTAMPTZ;
\ No newline at end of file
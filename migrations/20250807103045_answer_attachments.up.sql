@@ -0,0 +1,7 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS answer_attachments (
+    answer_id integer NOT NULL REFERENCES answers ON DELETE CASCADE,
+    position integer NOT NULL,
+    url TEXT NOT NULL,
+    PRIMARY KEY (answer_id, position)
+);
\ No newline at end of file
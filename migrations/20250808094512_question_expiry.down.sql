@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE questions
+DROP COLUMN archived,
+DROP COLUMN expires_at;
\ No newline at end of file
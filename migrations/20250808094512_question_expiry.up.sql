@@ -0,0 +1,6 @@
+-- Add up migration script here
+-- Questions past expires_at are archived by a background task and left
+-- out of listings
+ALTER TABLE questions
+ADD COLUMN expires_at TIMESTAMPTZ,
+ADD COLUMN archived BOOLEAN NOT NULL DEFAULT false;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- Only a hash of each token is kept, like for API tokens
+CREATE TABLE IF NOT EXISTS password_resets (
+    token_hash TEXT PRIMARY KEY,
+    account_id integer NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- How clients should render the content (plain or markdown)
+ALTER TABLE questions
+ADD COLUMN content_format TEXT NOT NULL DEFAULT 'plain';
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS question_tags;
+DROP TABLE IF EXISTS tags;
\ No newline at end of file
@@ -0,0 +1,23 @@
+-- Add up migration script here
+-- questions.tags stays as the copy listings read; /tags counts come from
+-- the join table so each tag name is stored once
+CREATE TABLE IF NOT EXISTS tags (
+    id serial PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS question_tags (
+    question_id integer NOT NULL REFERENCES questions ON DELETE CASCADE,
+    tag_id integer NOT NULL REFERENCES tags ON DELETE CASCADE,
+    PRIMARY KEY (question_id, tag_id)
+);
+
+INSERT INTO tags (name)
+SELECT DISTINCT unnest(tags) FROM questions
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO question_tags (question_id, tag_id)
+SELECT questions.id, tags.id
+FROM questions, unnest(questions.tags) AS tag
+JOIN tags ON tags.name = tag
+ON CONFLICT DO NOTHING;
\ No newline at end of file
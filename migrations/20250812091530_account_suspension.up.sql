@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Writes from the account are refused until this has passed
+ALTER TABLE accounts
+ADD COLUMN suspended_until TIMESTAMPTZ;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS censorship_audit;
\ No newline at end of file
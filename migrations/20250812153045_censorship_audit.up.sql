@@ -0,0 +1,9 @@
+-- Add up migration script here
+-- Written only when censorship auditing is switched on
+CREATE TABLE IF NOT EXISTS censorship_audit (
+    id serial PRIMARY KEY,
+    account_id integer NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    resource TEXT NOT NULL,
+    preview TEXT NOT NULL,
+    created_on TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
\ No newline at end of file
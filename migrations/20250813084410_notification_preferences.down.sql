@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE accounts
+DROP COLUMN notify_on_answer,
+DROP COLUMN notify_on_reply;
\ No newline at end of file
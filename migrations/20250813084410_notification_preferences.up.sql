@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- NULL until the account changes them, meaning the defaults apply
+ALTER TABLE accounts
+ADD COLUMN notify_on_answer BOOLEAN,
+ADD COLUMN notify_on_reply BOOLEAN;
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Add up migration script here
+-- When the question was last edited, NULL until it is
+ALTER TABLE questions ADD COLUMN updated_on TIMESTAMP;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS bookmarks (
+    account_id integer NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    question_id integer NOT NULL REFERENCES questions ON DELETE CASCADE,
+    created_on TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (account_id, question_id)
+);
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Accounts from before this migration are left NULL and count as old enough
+ALTER TABLE accounts ADD COLUMN created_at TIMESTAMPTZ;
+ALTER TABLE accounts ALTER COLUMN created_at SET DEFAULT NOW();
\ No newline at end of file
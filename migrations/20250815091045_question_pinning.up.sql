@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Pinned questions lead every listing, whatever the sort
+ALTER TABLE questions
+ADD COLUMN is_pinned BOOLEAN NOT NULL DEFAULT false;
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- Add up migration script here
+-- Runtime toggles, flipped through /admin/flags. A missing row means the
+-- flag has its built-in default.
+CREATE TABLE IF NOT EXISTS feature_flags (
+    name TEXT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL,
+    updated_on TIMESTAMP NOT NULL DEFAULT NOW()
+);
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Add down migration script here
+-- Hashed tokens can't be turned back, so they are dropped as well
+DELETE FROM email_verifications;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Outstanding tokens were stored in the clear and can't be matched
+-- against their hashes, so they are dropped
+DELETE FROM email_verifications;
\ No newline at end of file
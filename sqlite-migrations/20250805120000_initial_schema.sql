@@ -0,0 +1,55 @@
+-- SQLite has no arrays, so question tags are kept as a JSON array in TEXT,
+-- and the normalized answer content the Postgres index computes is stored
+-- in content_key by the application
+CREATE TABLE IF NOT EXISTS accounts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    email TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL,
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    verified BOOLEAN NOT NULL DEFAULT FALSE,
+    last_login_at TEXT,
+    last_login_ip TEXT
+);
+
+CREATE TABLE IF NOT EXISTS questions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+    tags TEXT,
+    created_on TEXT NOT NULL,
+    account_id INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS answers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    content TEXT NOT NULL,
+    content_key TEXT NOT NULL,
+    created_on TEXT NOT NULL,
+    question_id INTEGER REFERENCES questions,
+    account_id INTEGER NOT NULL,
+    parent_answer_id INTEGER REFERENCES answers ON DELETE CASCADE,
+    client_id TEXT,
+    UNIQUE (question_id, account_id, content_key),
+    UNIQUE (account_id, client_id)
+);
+
+CREATE TABLE IF NOT EXISTS votes (
+    account_id INTEGER NOT NULL,
+    question_id INTEGER NOT NULL REFERENCES questions ON DELETE CASCADE,
+    value INTEGER NOT NULL CHECK (value IN (-1, 1)),
+    PRIMARY KEY (account_id, question_id)
+);
+
+CREATE TABLE IF NOT EXISTS email_verifications (
+    token TEXT PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES accounts ON DELETE CASCADE,
+    expires_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_id INTEGER NOT NULL REFERENCES accounts ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_on TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Set while an account waits out its deletion grace period
+ALTER TABLE accounts ADD COLUMN deletion_due_at TEXT;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS answer_attachments (
+    answer_id INTEGER NOT NULL REFERENCES answers ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    PRIMARY KEY (answer_id, position)
+);
@@ -0,0 +1,4 @@
+-- Questions past expires_at are archived by a background task and left
+-- out of listings
+ALTER TABLE questions ADD COLUMN expires_at TEXT;
+ALTER TABLE questions ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
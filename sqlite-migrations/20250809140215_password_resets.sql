@@ -0,0 +1,6 @@
+-- Only a hash of each token is kept, like for API tokens
+CREATE TABLE IF NOT EXISTS password_resets (
+    token_hash TEXT PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES accounts ON DELETE CASCADE,
+    expires_at TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- How clients should render the content (plain or markdown)
+ALTER TABLE questions ADD COLUMN content_format TEXT NOT NULL DEFAULT 'plain';
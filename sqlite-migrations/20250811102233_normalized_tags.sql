@@ -0,0 +1,20 @@
+-- questions.tags stays as the copy listings read; /tags counts come from
+-- the join table so each tag name is stored once
+CREATE TABLE IF NOT EXISTS tags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS question_tags (
+    question_id INTEGER NOT NULL REFERENCES questions ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags ON DELETE CASCADE,
+    PRIMARY KEY (question_id, tag_id)
+);
+
+INSERT OR IGNORE INTO tags (name)
+SELECT DISTINCT tag.value FROM questions, json_each(questions.tags) AS tag;
+
+INSERT OR IGNORE INTO question_tags (question_id, tag_id)
+SELECT questions.id, tags.id
+FROM questions, json_each(questions.tags) AS tag
+JOIN tags ON tags.name = tag.value;
@@ -0,0 +1,2 @@
+-- Writes from the account are refused until this has passed
+ALTER TABLE accounts ADD COLUMN suspended_until TEXT;
@@ -0,0 +1,8 @@
+-- Written only when censorship auditing is switched on
+CREATE TABLE IF NOT EXISTS censorship_audit (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    account_id INTEGER NOT NULL REFERENCES accounts ON DELETE CASCADE,
+    resource TEXT NOT NULL,
+    preview TEXT NOT NULL,
+    created_on TEXT NOT NULL
+);
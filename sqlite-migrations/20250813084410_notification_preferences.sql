@@ -0,0 +1,3 @@
+-- NULL until the account changes them, meaning the defaults apply
+ALTER TABLE accounts ADD COLUMN notify_on_answer INTEGER;
+ALTER TABLE accounts ADD COLUMN notify_on_reply INTEGER;
@@ -0,0 +1,5 @@
+-- When the question was last edited, NULL until it is
+ALTER TABLE questions ADD COLUMN updated_on TEXT;
+-- Votes cast before this migration have no time and are left out of
+-- question activity
+ALTER TABLE votes ADD COLUMN created_on TEXT;
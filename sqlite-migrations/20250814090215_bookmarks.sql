@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS bookmarks (
+    account_id INTEGER NOT NULL REFERENCES accounts ON DELETE CASCADE,
+    question_id INTEGER NOT NULL REFERENCES questions ON DELETE CASCADE,
+    created_on TEXT NOT NULL,
+    PRIMARY KEY (account_id, question_id)
+);
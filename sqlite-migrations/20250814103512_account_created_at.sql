@@ -0,0 +1,2 @@
+-- Accounts from before this migration are left NULL and count as old enough
+ALTER TABLE accounts ADD COLUMN created_at TEXT;
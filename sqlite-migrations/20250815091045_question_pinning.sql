@@ -0,0 +1,2 @@
+-- Pinned questions lead every listing, whatever the sort
+ALTER TABLE questions ADD COLUMN is_pinned INTEGER NOT NULL DEFAULT 0;
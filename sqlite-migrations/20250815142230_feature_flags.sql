@@ -0,0 +1,7 @@
+-- Runtime toggles, flipped through /admin/flags. A missing row means the
+-- flag has its built-in default.
+CREATE TABLE IF NOT EXISTS feature_flags (
+    name TEXT PRIMARY KEY,
+    enabled INTEGER NOT NULL,
+    updated_on TEXT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- Outstanding tokens were stored in the clear and can't be matched
+-- against their hashes, so they are dropped
+DELETE FROM email_verifications;
@@ -1,11 +1,23 @@
-use server::{config, run, setup_store};
+// The route tree nests deeply enough to exceed the default when its
+// future type is laid out
+#![recursion_limit = "256"]
+
+use server::{config, run};
 
 #[tokio::main]
 async fn main() -> Result<(), handle_errors::Error> {
     dotenv::dotenv().ok();
 
     let config = config::Config::new().expect("Config can't be set");
-    let store = setup_store(&config).await?;
+    config.validate()?;
+    #[cfg(feature = "memory-store")]
+    let store = server::setup_memory_store(&config);
+    #[cfg(all(feature = "sqlite", not(feature = "memory-store")))]
+    let store = server::setup_sqlite_store(&config).await?;
+    #[cfg(not(any(feature = "memory-store", feature = "sqlite")))]
+    let store = server::setup_store(&config).await?;
+
+    server::seed_store(&config, &store).await?;
 
     tracing::info!("Q&A service build ID {}", env!("RUST_WEB_DEV_VERSION"));
 
@@ -0,0 +1,444 @@
+use futures::{Stream, StreamExt};
+use handle_errors::Error;
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_json::error::Category;
+use warp::hyper::body::{Buf, Bytes};
+use warp::{Filter, Rejection};
+
+/// Limits applied to JSON request bodies before they are deserialized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JsonLimits {
+    /// Largest accepted body, in bytes
+    pub max_body_bytes: u64,
+    /// Deepest accepted nesting of arrays and objects
+    pub max_depth: usize,
+    /// Most elements accepted in any single array
+    pub max_array_len: usize,
+    /// Refuse bodies with fields the target type doesn't declare instead
+    /// of ignoring them
+    pub deny_unknown_fields: bool,
+}
+
+/// Body filter for every route that takes one. A body sent as
+/// `application/x-www-form-urlencoded` is read as a form, one sent as JSON
+/// or without a `Content-Type` as JSON, so clients can use whichever they
+/// prefer. Any other type is a 415. Both are held to
+/// `max_body_bytes`, with a 413 past it, chunked bodies without a
+/// `Content-Length` included.
+///
+/// JSON bodies over the other limits get a 400 before reaching serde, and
+/// so do bodies that aren't JSON at all; JSON of the wrong shape is a 422.
+/// A form that doesn't fit the target type is a 422 too. Forms can't carry
+/// arrays, so those fields only come through JSON unless the type splits a
+/// string itself, as `NewAnswer::attachments` does.
+pub fn json_or_form<T: DeserializeOwned + Send + 'static>(
+    limits: JsonLimits,
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("content-type")
+        .and(limited_body(limits.max_body_bytes))
+        .and_then(move |content_type: Option<String>, body: Bytes| async move {
+            let result = match content_type.as_deref() {
+                Some(content_type) if is_form(content_type) => {
+                    serde_urlencoded::from_bytes::<T>(&body)
+                        .map_err(|e| Error::InvalidBody(e.to_string()))
+                }
+                Some(content_type) if !is_json(content_type) => {
+                    Err(Error::UnsupportedMediaType(mime_type(content_type).to_string()))
+                }
+                _ => parse_json::<T>(&body, limits),
+            };
+            result.map_err(warp::reject::custom)
+        })
+}
+
+/// The whole body, refused with a 413 as soon as it is known to be over
+/// `max_bytes`: up front from `Content-Length`, or while reading when
+/// there is none. `warp::body::content_length_limit` would turn the latter
+/// away altogether.
+fn limited_body(max_bytes: u64) -> impl Filter<Extract = (Bytes,), Error = Rejection> + Clone {
+    warp::header::optional::<u64>("content-length")
+        .and(warp::body::stream())
+        .and_then(move |length: Option<u64>, stream| read_limited(length, stream, max_bytes))
+}
+
+async fn read_limited<S, B>(
+    length: Option<u64>,
+    stream: S,
+    max_bytes: u64,
+) -> Result<Bytes, Rejection>
+where
+    S: Stream<Item = Result<B, warp::Error>>,
+    B: Buf,
+{
+    if length.is_some_and(|length| length > max_bytes) {
+        return Err(warp::reject::custom(Error::BodyTooLarge));
+    }
+
+    let mut stream = Box::pin(stream);
+    let mut body = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| warp::reject::custom(Error::InvalidBody(e.to_string())))?;
+        if (body.len() + chunk.remaining()) as u64 > max_bytes {
+            return Err(warp::reject::custom(Error::BodyTooLarge));
+        }
+        body.extend_from_slice(chunk.chunk());
+    }
+
+    Ok(Bytes::from(body))
+}
+
+/// Refuses requests whose `Content-Type` isn't one of `allowed` with a
+/// 415, a missing one included. Parameters such as `charset` don't count.
+pub fn content_type_allowed(
+    allowed: Vec<String>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("content-type")
+        .and_then(move |content_type: Option<String>| {
+            let mime = content_type.as_deref().map(mime_type).unwrap_or_default().to_string();
+            let accepted = allowed.iter().any(|a| a.trim().eq_ignore_ascii_case(&mime));
+            async move {
+                if accepted {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(Error::UnsupportedMediaType(mime)))
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// The media type of a `Content-Type` value, without its parameters
+fn mime_type(content_type: &str) -> &str {
+    content_type.split(';').next().unwrap_or_default().trim()
+}
+
+fn is_form(content_type: &str) -> bool {
+    mime_type(content_type).eq_ignore_ascii_case("application/x-www-form-urlencoded")
+}
+
+/// `application/json` and its `+json` variants, such as
+/// `application/merge-patch+json`
+fn is_json(content_type: &str) -> bool {
+    let mime = mime_type(content_type).to_ascii_lowercase();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+/// Whether `json_or_form` can read a body of this content type
+pub fn is_parsed(content_type: &str) -> bool {
+    is_form(content_type) || is_json(content_type)
+}
+
+fn parse_json<T: DeserializeOwned>(body: &[u8], limits: JsonLimits) -> Result<T, Error> {
+    check_json_limits(body, limits).map_err(Error::JsonLimitExceeded)?;
+    if limits.deny_unknown_fields
+        && let Some(field) = unknown_field::<T>(body)
+    {
+        return Err(Error::UnknownField(field));
+    }
+
+    serde_json::from_slice::<T>(body).map_err(|e| match e.classify() {
+        Category::Syntax | Category::Eof | Category::Io => Error::MalformedJson(e.to_string()),
+        Category::Data => Error::InvalidBody(e.to_string()),
+    })
+}
+
+/// The first top-level key of a JSON object body which isn't one of the
+/// fields `T` declares. Gives `None` for bodies that aren't objects and
+/// for types that don't deserialize from a plain struct.
+fn unknown_field<T: DeserializeOwned>(body: &[u8]) -> Option<String> {
+    let mut fields = None;
+    let _ = T::deserialize(FieldProbe(&mut fields));
+    let fields = fields?;
+
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Object(object)) => object
+            .keys()
+            .find(|key| !fields.contains(&key.as_str()))
+            .cloned(),
+        _ => None,
+    }
+}
+
+/// Deserializer that gives up straight away, after noting the field names
+/// a derived struct announces in `deserialize_struct`
+struct FieldProbe<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de> Deserializer<'de> for FieldProbe<'_> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        *self.0 = Some(fields);
+        Err(de::Error::custom("fields noted"))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+/// Walks the raw body once, tracking nesting and the element count of
+/// each open array. serde_json has no knob for either, and its own
+/// recursion limit only kicks in at 128 levels.
+pub fn check_json_limits(body: &[u8], limits: JsonLimits) -> Result<(), String> {
+    // One entry per open container: `None` for an object, `Some(n)` for
+    // an array holding n elements so far
+    let mut open: Vec<Option<usize>> = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for &byte in body {
+        if in_string {
+            if escaped {
+                escaped = false;
+            } else if byte == b'\\' {
+                escaped = true;
+            } else if byte == b'"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match byte {
+            b' ' | b'\t' | b'\n' | b'\r' => continue,
+            b']' | b'}' => {
+                open.pop();
+                continue;
+            }
+            b',' => {
+                if let Some(Some(elements)) = open.last_mut() {
+                    *elements += 1;
+                    if *elements > limits.max_array_len {
+                        return Err(format!(
+                            "arrays may hold at most {} elements",
+                            limits.max_array_len
+                        ));
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        // Anything else starts (or continues) a value
+        if let Some(Some(elements)) = open.last_mut()
+            && *elements == 0
+        {
+            *elements = 1;
+            if limits.max_array_len == 0 {
+                return Err("arrays must be empty".to_string());
+            }
+        }
+
+        match byte {
+            b'"' => in_string = true,
+            b'[' => open.push(Some(0)),
+            b'{' => open.push(None),
+            _ => {}
+        }
+
+        if open.len() > limits.max_depth {
+            return Err(format!(
+                "JSON may be nested at most {} levels deep",
+                limits.max_depth
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod body_tests {
+    use super::{JsonLimits, check_json_limits, read_limited};
+    use crate::build_routes;
+    use crate::config::Config;
+    use crate::routes::authentication::issue_token;
+    use crate::store::{MemoryStore, Store};
+    use crate::test_utils::censoring_api;
+    use crate::types::account::AccountId;
+
+    const LIMITS: JsonLimits = JsonLimits {
+        max_body_bytes: 1024,
+        max_depth: 3,
+        max_array_len: 3,
+        deny_unknown_fields: false,
+    };
+
+    #[test]
+    fn accepts_json_within_limits() {
+        let body = br#"{"tags": ["a", "b,c", "[d]"], "nested": {"ok": [1]}}"#;
+        assert!(check_json_limits(body, LIMITS).is_ok());
+    }
+
+    #[test]
+    fn rejects_over_deep_json() {
+        let body = br#"{"a": {"b": {"c": {"d": 1}}}}"#;
+        assert!(check_json_limits(body, LIMITS).is_err());
+    }
+
+    #[test]
+    fn rejects_over_large_arrays() {
+        assert!(check_json_limits(b"[1, 2, 3]", LIMITS).is_ok());
+        assert!(check_json_limits(b"[1, 2, 3, 4]", LIMITS).is_err());
+        assert!(check_json_limits(b"[[], [], [], []]", LIMITS).is_err());
+    }
+
+    #[tokio::test]
+    async fn bodies_without_a_length_are_read_up_to_the_limit() {
+        let chunks = || {
+            futures::stream::iter(
+                ["{\"a\": ", "1}"].map(|chunk| Ok(warp::hyper::body::Bytes::from(chunk))),
+            )
+        };
+
+        let body = read_limited(None, chunks(), 8).await.unwrap();
+        assert_eq!(&body[..], b"{\"a\": 1}");
+
+        let rejection = read_limited(None, chunks(), 7).await.unwrap_err();
+        assert!(matches!(
+            rejection.find(),
+            Some(handle_errors::Error::BodyTooLarge)
+        ));
+    }
+
+    #[tokio::test]
+    async fn over_deep_body_returns_400() {
+        let config = Config {
+            json_max_depth: 2,
+            ..Config::default()
+        };
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/registration")
+            .body(r#"{"email": "a@b.c", "password": {"x": {"y": "z"}}}"#)
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_a_400_and_the_wrong_shape_a_422() {
+        let routes = build_routes(Config::default(), MemoryStore::new()).await;
+        let register = |body: &'static str| {
+            warp::test::request()
+                .method("POST")
+                .path("/registration")
+                .body(body)
+                .reply(&routes)
+        };
+
+        let res = register("{ not json").await;
+        assert_eq!(res.status(), 400);
+        assert!(String::from_utf8_lossy(res.body()).starts_with("Malformed JSON"));
+
+        let res = register(r#"{"email": "a@email.com", "password": 12345678}"#).await;
+        assert_eq!(res.status(), 422);
+    }
+
+    #[tokio::test]
+    async fn questions_and_answers_take_json_or_form_bodies() {
+        unsafe {
+            std::env::set_var("BAD_WORDS_API_KEY", "YES");
+        }
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            profanity_api_url: censoring_api(),
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "me@email.com".to_string(),
+            false,
+        );
+        let store = MemoryStore::new();
+        let routes = build_routes(config, store.clone()).await;
+        let post = |path: &'static str, content_type: &'static str, body: &'static str| {
+            warp::test::request()
+                .method("POST")
+                .path(path)
+                .header("Authorization", format!("Bearer {}", token))
+                .header("Content-Type", content_type)
+                .body(body)
+                .reply(&routes)
+        };
+        let form = "application/x-www-form-urlencoded";
+
+        let res = post(
+            "/questions",
+            "application/json",
+            r#"{"title": "Which format?", "content": "Either one works"}"#,
+        )
+        .await;
+        assert_eq!(res.status(), 200);
+        let res = post("/questions", form, "title=Which+format%3F&content=Either+one+works").await;
+        assert_eq!(res.status(), 200);
+        for id in [1, 2] {
+            let question = store.get_question(id).await.unwrap();
+            assert_eq!(question.title, "Which format?");
+            assert_eq!(question.content, "Either one works");
+        }
+
+        let res = post(
+            "/answers",
+            "application/json",
+            r#"{"question_id": 1, "content": "Form or JSON"}"#,
+        )
+        .await;
+        assert_eq!(res.status(), 201);
+        let res = post("/answers", form, "question_id=2&content=Form+or+JSON").await;
+        assert_eq!(res.status(), 201);
+        for id in [1, 2] {
+            let answers = store.get_answers(id).await.unwrap();
+            assert_eq!(answers[0].content, "Form or JSON");
+        }
+
+        let res = post("/answers", form, "question_id=first&content=Form+or+JSON").await;
+        assert_eq!(res.status(), 422);
+
+        let res = post("/questions", "text/plain", "title=Plain&content=Not+parsed").await;
+        assert_eq!(res.status(), 415);
+    }
+
+    #[tokio::test]
+    async fn unknown_fields_are_refused_only_in_strict_mode() {
+        let register = |routes| async move {
+            warp::test::request()
+                .method("POST")
+                .path("/registration")
+                .json(&serde_json::json!({
+                    "email": "a@email.com",
+                    "password": "a long enough password",
+                    "is_admn": true,
+                }))
+                .reply(&routes)
+                .await
+        };
+
+        let strict = Config {
+            strict_json: true,
+            ..Config::default()
+        };
+        let res = register(build_routes(strict, MemoryStore::new()).await).await;
+        assert_eq!(res.status(), 400);
+        assert_eq!(res.body(), "Unknown field `is_admn`");
+
+        let res = register(build_routes(Config::default(), MemoryStore::new()).await).await;
+        assert_eq!(res.status(), 200);
+    }
+}
@@ -0,0 +1,98 @@
+use handle_errors::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::broadcast;
+
+use crate::types::question::Question;
+
+/// Questions buffered for a subscriber that reads slower than they are
+/// posted; it skips ahead once it falls further behind
+const FEED_BUFFER: usize = 64;
+
+/// Fans newly posted questions out to `/questions/live` subscribers,
+/// turning away subscribers beyond `max_subscribers`
+#[derive(Debug, Clone)]
+pub struct QuestionFeed {
+    sender: broadcast::Sender<Question>,
+    subscribers: Arc<AtomicUsize>,
+    max_subscribers: usize,
+}
+
+/// A live subscription; the slot is given back when it's dropped, i.e.
+/// when the client disconnects
+#[derive(Debug)]
+pub struct Subscription {
+    pub receiver: broadcast::Receiver<Question>,
+    subscribers: Arc<AtomicUsize>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.subscribers.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl QuestionFeed {
+    pub fn new(max_subscribers: usize) -> Self {
+        let (sender, _) = broadcast::channel(FEED_BUFFER);
+        QuestionFeed {
+            sender,
+            subscribers: Arc::new(AtomicUsize::new(0)),
+            max_subscribers,
+        }
+    }
+
+    pub fn publish(&self, question: &Question) {
+        // Nobody listening is fine
+        let _ = self.sender.send(question.clone());
+    }
+
+    pub fn subscribe(&self) -> Result<Subscription, Error> {
+        self.subscribers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < self.max_subscribers).then_some(count + 1)
+            })
+            .map_err(|_| Error::ServiceOverloaded)?;
+
+        Ok(Subscription {
+            receiver: self.sender.subscribe(),
+            subscribers: self.subscribers.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod feed_tests {
+    use super::QuestionFeed;
+    use crate::types::question::{ContentFormat, Question, QuestionId};
+
+    #[test]
+    fn subscribers_beyond_the_cap_are_turned_away() {
+        let feed = QuestionFeed::new(2);
+
+        let first = feed.subscribe().unwrap();
+        let _second = feed.subscribe().unwrap();
+        assert!(feed.subscribe().is_err());
+
+        drop(first);
+        assert!(feed.subscribe().is_ok());
+    }
+
+    #[tokio::test]
+    async fn subscribers_receive_published_questions() {
+        let feed = QuestionFeed::new(1);
+        let mut subscription = feed.subscribe().unwrap();
+
+        feed.publish(&Question {
+            id: QuestionId(1),
+            title: "Live".to_string(),
+            content: "Anyone there?".to_string(),
+            tags: None,
+            expires_at: None,
+            content_format: ContentFormat::Plain,
+        });
+
+        let question = subscription.receiver.recv().await.unwrap();
+        assert_eq!(question.title, "Live");
+    }
+}
@@ -1,18 +1,28 @@
+// The route tree nests deeply enough to exceed the default when its
+// future type is laid out
+#![recursion_limit = "256"]
 #![warn(clippy::all)]
 
 use clap::Parser;
-use dotenv;
 use handle_errors::return_error;
 use tracing_subscriber::fmt::format::FmtSpan;
-use warp::{Filter, http::Method};
+use std::sync::Arc;
+use warp::{Filter, http::Method, path::FullPath};
 use tokio::sync::{oneshot, oneshot::Sender};
 pub use handle_errors;
 
+mod body;
+mod feed;
+mod middleware;
 mod profanity;
+mod rate_limit;
 mod routes;
-mod store;
-mod types;
+mod seed;
+pub mod store;
+pub mod types;
 pub mod config;
+#[cfg(test)]
+mod test_utils;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -37,84 +47,605 @@ pub struct OneshotHandler {
     pub sender: Sender<i32>,
 }
 
-async fn build_routes(store: store::Store) -> 
+async fn build_routes<S: store::Store>(config: config::Config, store: S) -> 
     impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let accounts = store.clone();
     let store_filter = warp::any().map(move || store.clone());
+    let hsts_max_age = config.hsts.then_some(config.hsts_max_age);
+    let request_spans = !config.disable_request_spans;
+    let response_headers = config.response_headers();
+    let https_redirect = middleware::https_redirect(
+        config.https_redirect.then(|| config.https_redirect_host.clone()).flatten(),
+    );
+    let json_limits = config.json_limits();
+    let answer_content_types = body::content_type_allowed(config.answer_content_types.clone());
+    let concurrency_limit = middleware::concurrency_limit(config.max_concurrent_requests);
+    let ip_limit = middleware::per_ip_limit(config.max_concurrent_requests_per_ip);
+    let global_rate_limit = middleware::global_rate_limit(rate_limit::GlobalRateLimiter::new(
+        config.max_requests_per_second,
+        std::time::Instant::now(),
+    ));
+    let token_keys = config.token_keys();
+    let require_verified = config.require_verified_email;
+    let min_account_age = config.min_account_age;
+    let disabled_status = config.disabled_route_status.status_code();
+    let maintenance = types::feature_flag::FeatureFlag::MaintenanceMode;
+    let not_read_only = middleware::route_enabled(!config.read_only, disabled_status);
+    let writes_enabled = not_read_only.clone().and(
+        middleware::flag_enabled(accounts.clone(), maintenance, false, disabled_status),
+    );
+    let registration_open = types::feature_flag::FeatureFlag::RegistrationOpen;
+    let registration_enabled =
+        middleware::route_enabled(!config.disable_registration, disabled_status).and(
+            middleware::flag_enabled(accounts.clone(), registration_open, true, disabled_status),
+        );
+    let cors = middleware::cors(&config);
+    let feed = feed::QuestionFeed::new(config.max_live_subscribers);
+    let feed_filter = warp::any().map(move || feed.clone());
+    let question_limiter = rate_limit::QuestionRateLimiter::new(
+        config.question_rate_limit,
+        std::time::Duration::from_secs(config.question_rate_window),
+    );
+    let question_limiter = warp::any().map(move || question_limiter.clone());
+    let config = Arc::new(config);
+    let config_filter = warp::any().map(move || config.clone());
+    let signing_keys = token_keys.clone();
+    let token_keys_filter = warp::any().map(move || signing_keys.clone());
 
-     let cors = warp::cors()
-        .allow_any_origin()
-        .allow_header("content-type")
-        .allow_methods(&[Method::PUT, Method::DELETE, Method::GET, Method::POST]);
 
     let get_questions = warp::get()
         .and(warp::path("questions"))
         .and(warp::path::end())
         .and(warp::query())
+        .and(routes::authentication::optional_auth(
+            token_keys.clone(),
+            accounts.clone(),
+        ))
         .and(store_filter.clone())
-        .and_then(routes::question::get_questions);
+        .and(config_filter.clone())
+        .and_then(routes::question::get_questions::<S>);
+
+    let random_question = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path("random"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::question::random_question::<S>);
+
+    let count_search_results = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path("search"))
+        .and(warp::path("count"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::question::count_search_results::<S>);
+
+    let stream_questions = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path("stream"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::question::stream_questions::<S>);
+
+    let live_questions = warp::get()
+        .and(warp::path("questions"))
+        .and(warp::path("live"))
+        .and(warp::path::end())
+        .and(feed_filter.clone())
+        .and_then(routes::question::live_questions);
+
+    let get_question = warp::get()
+        .and(warp::path("questions"))
+        .and(middleware::question_id())
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::question::get_question::<S>);
 
     let add_question = warp::post()
         .and(warp::path("questions"))
         .and(warp::path::end())
-        .and(routes::authentication::auth())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::posting_auth(
+            token_keys.clone(),
+            accounts.clone(),
+            require_verified,
+            min_account_age,
+        ))
         .and(store_filter.clone())
-        .and(warp::body::json())
-        .and_then(routes::question::add_question);
+        .and(config_filter.clone())
+        .and(feed_filter.clone())
+        .and(question_limiter)
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::question::add_question::<S>);
 
     let update_question = warp::put()
         .and(warp::path("questions"))
-        .and(warp::path::param::<i32>())
+        .and(middleware::question_id())
         .and(warp::path::end())
-        .and(routes::authentication::auth())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::verified_auth(
+            token_keys.clone(),
+            accounts.clone(),
+            require_verified,
+        ))
         .and(store_filter.clone())
-        .and(warp::body::json())
-        .and_then(routes::question::update_question);
+        .and(config_filter.clone())
+        .and(warp::header::optional::<String>("if-unmodified-since"))
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::question::update_question::<S>);
 
     let delete_question = warp::delete()
         .and(warp::path("questions"))
+        .and(middleware::question_id())
+        .and(warp::path::end())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::verified_auth(
+            token_keys.clone(),
+            accounts.clone(),
+            require_verified,
+        ))
+        .and(store_filter.clone())
+        .and_then(routes::question::delete_question::<S>);
+
+    let cast_vote = warp::post()
+        .and(warp::path("questions"))
+        .and(middleware::question_id())
+        .and(warp::path("vote"))
+        .and(warp::path::end())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::verified_auth(
+            token_keys.clone(),
+            accounts.clone(),
+            require_verified,
+        ))
+        .and(store_filter.clone())
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::vote::cast_vote::<S>);
+
+    let add_bookmark = warp::post()
+        .and(warp::path("questions"))
+        .and(middleware::question_id())
+        .and(warp::path("bookmark"))
+        .and(warp::path::end())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::write_auth(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and_then(routes::bookmark::add_bookmark::<S>);
+
+    let remove_bookmark = warp::delete()
+        .and(warp::path("questions"))
+        .and(middleware::question_id())
+        .and(warp::path("bookmark"))
+        .and(warp::path::end())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::write_auth(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and_then(routes::bookmark::remove_bookmark::<S>);
+
+    let get_activity = warp::get()
+        .and(warp::path("questions"))
+        .and(middleware::question_id())
+        .and(warp::path("activity"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and(config_filter.clone())
+        .and_then(routes::activity::get_question_activity::<S>);
+
+    let get_answers = warp::get()
+        .and(warp::path("questions"))
+        .and(middleware::question_id())
+        .and(warp::path("answers"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::answer::get_answers::<S>);
+
+    let get_answer = warp::get()
+        .and(warp::path("answers"))
         .and(warp::path::param::<i32>())
         .and(warp::path::end())
-        .and(routes::authentication::auth())
         .and(store_filter.clone())
-        .and_then(routes::question::delete_question);
+        .and_then(routes::answer::get_answer::<S>);
 
     let add_answer = warp::post()
         .and(warp::path("answers"))
         .and(warp::path::end())
-        .and(routes::authentication::auth())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::posting_auth(
+            token_keys.clone(),
+            accounts.clone(),
+            require_verified,
+            min_account_age,
+        ))
+        .and(store_filter.clone())
+        .and(config_filter.clone())
+        .and(answer_content_types)
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::answer::add_answer::<S>);
+
+    let move_answer = warp::post()
+        .and(warp::path("answers"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("move"))
+        .and(warp::path::end())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::admin(token_keys.clone(), accounts.clone()))
         .and(store_filter.clone())
-        .and(warp::body::form())
-        .and_then(routes::answer::add_answer);
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::answer::move_answer::<S>);
 
     let registration = warp::post()
         .and(warp::path("registration"))
         .and(warp::path::end())
+        .and(registration_enabled)
         .and(store_filter.clone())
-        .and(warp::body::json())
-        .and_then(routes::authentication::register);
+        .and(config_filter.clone())
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::authentication::register::<S>);
+
+    let verify_email = warp::get()
+        .and(warp::path("verify"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and_then(routes::authentication::verify_email::<S>);
+
+    let request_password_reset = warp::post()
+        .and(warp::path("password-reset"))
+        .and(warp::path("request"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(config_filter.clone())
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::authentication::request_password_reset::<S>);
+
+    let confirm_password_reset = warp::post()
+        .and(warp::path("password-reset"))
+        .and(warp::path("confirm"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and(config_filter.clone())
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::authentication::confirm_password_reset::<S>);
 
     let login = warp::post()
         .and(warp::path("login"))
         .and(warp::path::end())
         .and(store_filter.clone())
-        .and(warp::body::json())
-        .and_then(routes::authentication::login);
+        .and(token_keys_filter.clone())
+        .and(warp::addr::remote())
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::authentication::login::<S>);
 
-    update_question
+    let rotate_token = warp::post()
+        .and(warp::path("token"))
+        .and(warp::path("rotate"))
+        .and(warp::path::end())
+        .and(routes::authentication::session_auth(token_keys.clone()))
+        .and(store_filter.clone())
+        .and(token_keys_filter)
+        .and_then(routes::authentication::rotate_token::<S>);
+
+    let get_me = warp::get()
+        .and(warp::path("accounts"))
+        .and(warp::path("me"))
+        .and(warp::path::end())
+        .and(routes::authentication::auth(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and_then(routes::account::get_me::<S>);
+
+    let update_preferences = warp::put()
+        .and(warp::path("accounts"))
+        .and(warp::path("me"))
+        .and(warp::path("preferences"))
+        .and(warp::path::end())
+        .and(routes::authentication::write_auth(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::account::update_preferences::<S>);
+
+    let get_bookmarks = warp::get()
+        .and(warp::path("accounts"))
+        .and(warp::path("me"))
+        .and(warp::path("bookmarks"))
+        .and(warp::path::end())
+        .and(routes::authentication::auth(token_keys.clone(), accounts.clone()))
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and(config_filter.clone())
+        .and_then(routes::bookmark::get_bookmarks::<S>);
+
+    let export_me = warp::get()
+        .and(warp::path("accounts"))
+        .and(warp::path("me"))
+        .and(warp::path("export"))
+        .and(warp::path::end())
+        .and(routes::authentication::auth(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and(config_filter.clone())
+        .and_then(routes::account::export_me::<S>);
+
+    let whoami = warp::get()
+        .and(warp::path("whoami"))
+        .and(warp::path::end())
+        .and(routes::authentication::auth(token_keys.clone(), accounts.clone()))
+        .and_then(routes::account::whoami);
+
+    let delete_me = warp::delete()
+        .and(warp::path("accounts"))
+        .and(warp::path("me"))
+        .and(warp::path::end())
+        .and(routes::authentication::write_auth(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and(config_filter.clone())
+        .and_then(routes::account::delete_me::<S>);
+
+    let cancel_deletion = warp::post()
+        .and(warp::path("accounts"))
+        .and(warp::path("me"))
+        .and(warp::path("cancel-deletion"))
+        .and(warp::path::end())
+        .and(routes::authentication::write_auth(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and_then(routes::account::cancel_deletion::<S>);
+
+    let suspend_account = warp::post()
+        .and(warp::path("admin"))
+        .and(warp::path("accounts"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("suspend"))
+        .and(warp::path::end())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::admin(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::account::suspend_account::<S>);
+
+    let remove_question = warp::delete()
+        .and(warp::path("admin"))
+        .and(warp::path("questions"))
+        .and(middleware::question_id())
+        .and(warp::path::end())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::admin(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and_then(routes::question::remove_question::<S>);
+
+    let get_flags = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("flags"))
+        .and(warp::path::end())
+        .and(routes::authentication::admin(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and_then(routes::feature_flag::get_flags::<S>);
+
+    let set_flag = warp::put()
+        .and(warp::path("admin"))
+        .and(warp::path("flags"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        // Not `writes_enabled`, or maintenance mode could never be lifted
+        .and(not_read_only)
+        .and(routes::authentication::admin(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::feature_flag::set_flag::<S>);
+
+    let pin_question = warp::post()
+        .and(warp::path("admin"))
+        .and(warp::path("questions"))
+        .and(middleware::question_id())
+        .and(
+            warp::path("pin")
+                .map(|| true)
+                .or(warp::path("unpin").map(|| false))
+                .unify(),
+        )
+        .and(warp::path::end())
+        .and(writes_enabled.clone())
+        .and(routes::authentication::admin(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and_then(routes::question::set_pinned::<S>);
+
+    let add_api_token = warp::post()
+        .and(warp::path("accounts"))
+        .and(warp::path("me"))
+        .and(warp::path("tokens"))
+        .and(warp::path::end())
+        .and(routes::authentication::unsuspended(
+            routes::authentication::session_auth(token_keys.clone()),
+            accounts.clone(),
+        ))
+        .and(store_filter.clone())
+        .and(body::json_or_form(json_limits))
+        .and_then(routes::api_token::add_api_token::<S>);
+
+    let get_api_tokens = warp::get()
+        .and(warp::path("accounts"))
+        .and(warp::path("me"))
+        .and(warp::path("tokens"))
+        .and(warp::path::end())
+        .and(routes::authentication::session_auth(token_keys.clone()))
+        .and(store_filter.clone())
+        .and_then(routes::api_token::get_api_tokens::<S>);
+
+    let delete_api_token = warp::delete()
+        .and(warp::path("accounts"))
+        .and(warp::path("me"))
+        .and(warp::path("tokens"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path::end())
+        .and(routes::authentication::session_auth(token_keys.clone()))
+        .and(store_filter.clone())
+        .and_then(routes::api_token::delete_api_token::<S>);
+
+    let get_root = warp::get()
+        .and(warp::path::end())
+        .and(config_filter.clone())
+        .and_then(routes::service::get_root);
+
+    let get_config = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("config"))
+        .and(warp::path::end())
+        .and(routes::authentication::admin(token_keys.clone(), accounts.clone()))
+        .and(config_filter.clone())
+        .and_then(routes::service::get_config);
+
+    let get_tags = warp::get()
+        .and(warp::path("tags"))
+        .and(warp::path::end())
+        .and(warp::query())
+        .and(store_filter.clone())
+        .and(config_filter.clone())
+        .and_then(routes::tag::get_tags::<S>);
+
+    let get_stats = warp::get()
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .and(routes::authentication::admin(token_keys.clone(), accounts.clone()))
+        .and(store_filter.clone())
+        .and_then(routes::stats::get_stats::<S>);
+
+    let get_account_stats = warp::get()
+        .and(warp::path("accounts"))
+        .and(warp::path::param::<i32>())
+        .and(warp::path("stats"))
+        .and(warp::path::end())
+        .and(store_filter.clone())
+        .and_then(routes::stats::get_account_stats::<S>);
+
+    let method_not_allowed = warp::method()
+        .and(warp::path::full())
+        .and_then(|method: Method, path: FullPath| async move {
+            let allowed = allowed_methods(path.as_str());
+            let rejection = if allowed.is_empty() || allowed.contains(&method) {
+                warp::reject::not_found()
+            } else {
+                warp::reject::custom(handle_errors::Error::MethodNotAllowed(allowed))
+            };
+            Err::<String, _>(rejection)
+        });
+
+    let routes = https_redirect
+        .or(update_question)
         .or(delete_question)
         .or(add_question)
         .or(add_answer)
+        .or(get_answers)
+        .or(get_answer)
+        .or(cast_vote)
+        .or(add_bookmark)
+        .or(remove_bookmark)
+        .or(get_activity)
+        .or(move_answer)
+        .or(random_question)
+        .or(count_search_results)
+        .or(stream_questions)
+        .or(live_questions)
+        .or(get_question)
         .or(get_questions)
         .or(registration)
         .or(login)
+        .or(rotate_token)
+        .or(request_password_reset)
+        .or(confirm_password_reset)
+        .or(verify_email)
+        .or(get_me)
+        .or(export_me)
+        .or(get_bookmarks)
+        .or(update_preferences)
+        .or(whoami)
+        .or(delete_me)
+        .or(cancel_deletion)
+        .or(suspend_account)
+        .or(remove_question)
+        .or(pin_question)
+        .or(get_flags)
+        .or(get_config)
+        .or(set_flag)
+        .or(add_api_token)
+        .or(get_api_tokens)
+        .or(delete_api_token)
+        .or(get_root)
+        .or(get_tags)
+        .or(get_stats)
+        .or(get_account_stats)
+        .or(method_not_allowed)
+        // On the heap, or polling the whole chain nearly fills a 2MB stack
+        // in debug builds
+        .boxed();
+
+    // The permit is held until the routes produce a reply
+    global_rate_limit
+        .and(concurrency_limit)
+        .and(ip_limit)
+        .and(middleware::valid_query())
+        .and(middleware::pretty_requested())
+        .and(routes)
+        .and_then(|_permit, _ip_permit, pretty, reply| middleware::pretty_json(reply, pretty))
         .with(cors)
-        .with(warp::trace::request())
+        .with(middleware::request_span(request_spans))
         .recover(return_error)
+        .map(move |reply| middleware::hsts(reply, hsts_max_age))
+        .map(move |reply| middleware::response_headers(reply, &response_headers))
+        .with(middleware::request_log())
+}
+
+/// Methods served on each known path, so an unsupported method gets a 405
+/// with an `Allow` header rather than falling through to a 404
+fn allowed_methods(path: &str) -> Vec<Method> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match segments.as_slice() {
+        ["questions"] => vec![Method::GET, Method::POST],
+        ["questions", id] if id.parse::<i32>().is_ok() => {
+            vec![Method::GET, Method::PUT, Method::DELETE]
+        }
+        ["questions", "stream"] | ["questions", "live"] | ["questions", "random"] => {
+            vec![Method::GET]
+        }
+        ["questions", "search", "count"] => vec![Method::GET],
+        ["questions", id, "answers"] if id.parse::<i32>().is_ok() => vec![Method::GET],
+        ["questions", id, "activity"] if id.parse::<i32>().is_ok() => vec![Method::GET],
+        ["questions", id, "vote"] if id.parse::<i32>().is_ok() => vec![Method::POST],
+        ["questions", id, "bookmark"] if id.parse::<i32>().is_ok() => {
+            vec![Method::POST, Method::DELETE]
+        }
+        ["answers"] | ["registration"] | ["login"] | ["token", "rotate"] => vec![Method::POST],
+        ["answers", id] if id.parse::<i32>().is_ok() => vec![Method::GET],
+        ["answers", id, "move"] if id.parse::<i32>().is_ok() => vec![Method::POST],
+        ["password-reset", "request"] | ["password-reset", "confirm"] => vec![Method::POST],
+        [""] | ["stats"] | ["tags"] | ["verify"] | ["whoami"] => vec![Method::GET],
+        ["accounts", "me"] => vec![Method::GET, Method::DELETE],
+        ["accounts", id, "stats"] if id.parse::<i32>().is_ok() => vec![Method::GET],
+        ["accounts", "me", "cancel-deletion"] => vec![Method::POST],
+        ["accounts", "me", "export"] | ["accounts", "me", "bookmarks"] => vec![Method::GET],
+        ["accounts", "me", "preferences"] => vec![Method::PUT],
+        ["admin", "accounts", id, "suspend"] if id.parse::<i32>().is_ok() => {
+            vec![Method::POST]
+        }
+        ["admin", "questions", id] if id.parse::<i32>().is_ok() => vec![Method::DELETE],
+        ["admin", "flags"] | ["admin", "config"] => vec![Method::GET],
+        ["admin", "flags", _] => vec![Method::PUT],
+        ["admin", "questions", id, "pin" | "unpin"] if id.parse::<i32>().is_ok() => {
+            vec![Method::POST]
+        }
+        ["accounts", "me", "tokens"] => vec![Method::GET, Method::POST],
+        ["accounts", "me", "tokens", id] if id.parse::<i32>().is_ok() => {
+            vec![Method::DELETE]
+        }
+        _ => Vec::new(),
+    }
 }
 
 pub async fn setup_store(
     config: &config::Config,
-) -> Result<store::Store, handle_errors::Error> {
+) -> Result<store::PgStore, handle_errors::Error> {
     dotenv::dotenv().ok();
 
     // let config = config::Config::new().expect("Config can't be set");
@@ -144,19 +675,67 @@ pub async fn setup_store(
     // let db_name = std::env::var("POSTGRES_DB")
     //     .unwrap_or(args.db_name.to_owned());
 
-    let store = store::Store::new(&format!(
-        "postgres://{}:{}@{}:{}/{}",
-        config.db_user, config.db_password,
-        config.db_host, config.db_port, config.db_name
-    ))
+    let seconds = |secs: u64| (secs > 0).then(|| std::time::Duration::from_secs(secs));
+    let store = store::PgStore::new(
+        &format!(
+            "postgres://{}:{}@{}:{}/{}",
+            config.db_user, config.db_password,
+            config.db_host, config.db_port, config.db_name
+        ),
+        seconds(config.db_idle_timeout),
+        seconds(config.db_max_lifetime),
+        config.db_statement_cache_capacity,
+        config.db_warmup_connections,
+        config.db_ssl_mode,
+        config.db_ssl_root_cert.as_deref(),
+    )
     .await
-    .map_err(|e| handle_errors::Error::DatabaseQueryError(e))?;
+    .map_err(handle_errors::Error::DatabaseQueryError)?
+    .with_max_rows(config.max_rows);
 
     sqlx::migrate!("./migrations")
         .run(&store.clone().connection)
         .await
-        .map_err(|e| handle_errors::Error::MigrationError(e))?;
+        .map_err(handle_errors::Error::MigrationError)?;
+
+    init_tracing(config);
 
+    Ok(store)
+}
+
+/// SQLite backed store for single-node deployments
+#[cfg(feature = "sqlite")]
+pub async fn setup_sqlite_store(
+    config: &config::Config,
+) -> Result<store::SqliteStore, handle_errors::Error> {
+    dotenv::dotenv().ok();
+
+    let store = store::SqliteStore::new(&config.sqlite_url, config.db_statement_cache_capacity)
+        .await
+        .map_err(handle_errors::Error::DatabaseQueryError)?
+        .with_max_rows(config.max_rows);
+
+    sqlx::migrate!("./sqlite-migrations")
+        .run(&store.connection)
+        .await
+        .map_err(handle_errors::Error::MigrationError)?;
+
+    init_tracing(config);
+
+    Ok(store)
+}
+
+/// Store for running the API without a database, e.g. for demos
+#[cfg(feature = "memory-store")]
+pub fn setup_memory_store(config: &config::Config) -> store::MemoryStore {
+    dotenv::dotenv().ok();
+
+    init_tracing(config);
+
+    store::MemoryStore::new().with_max_rows(config.max_rows)
+}
+
+fn init_tracing(config: &config::Config) {
     let log_filter = std::env::var("RUST_LOG").unwrap_or_else(|_| {
             format!(
                 "handle_errors={},rust_web_dev={},warp={}",
@@ -172,20 +751,83 @@ pub async fn setup_store(
     .with_span_events(FmtSpan::CLOSE)
     .with_level(true)
     .init();
+}
 
-    Ok(store)
+/// Inserts sample data when started with `--seed`, see `seed::seed`
+pub async fn seed_store<S: store::Store>(
+    config: &config::Config,
+    store: &S,
+) -> Result<(), handle_errors::Error> {
+    if config.seed {
+        seed::seed(config, store).await?;
+    }
+
+    Ok(())
 }
 
-pub async fn run(config: config::Config, store: store::Store) {
-    let routes = build_routes(store).await;
+pub async fn run<S: store::Store>(config: config::Config, store: S) {
+    let port = config.port;
+    tokio::spawn(sweep_account_deletions(
+        store.clone(),
+        config.deleted_account_content,
+    ));
+    tokio::spawn(sweep_expired_questions(store.clone()));
+    if config.answer_client_id_ttl > 0 {
+        let ttl = chrono::Duration::seconds(config.answer_client_id_ttl as i64);
+        tokio::spawn(sweep_expired_client_ids(store.clone(), ttl));
+    }
+    let routes = build_routes(config, store).await;
 
     warp::serve(routes)
-        .run(([0, 0, 0, 0], config.port))
+        .run(([0, 0, 0, 0], port))
         .await;
 }
 
-pub async fn oneshot(store: store::Store) -> OneshotHandler {
-    let routes = build_routes(store).await;
+/// How often accounts past their deletion grace period are looked for
+const ACCOUNT_DELETION_SWEEP: std::time::Duration = std::time::Duration::from_secs(3600);
+
+async fn sweep_account_deletions<S: store::Store>(
+    store: S,
+    content: types::account::DeletedContent,
+) {
+    let mut interval = tokio::time::interval(ACCOUNT_DELETION_SWEEP);
+    loop {
+        interval.tick().await;
+        if let Err(e) = routes::account::finalize_account_deletions(&store, content).await {
+            tracing::error!("Could not finalize account deletions: {}", e);
+        }
+    }
+}
+
+/// How often questions past their expiry are archived
+const QUESTION_EXPIRY_SWEEP: std::time::Duration = std::time::Duration::from_secs(60);
+
+async fn sweep_expired_questions<S: store::Store>(store: S) {
+    let mut interval = tokio::time::interval(QUESTION_EXPIRY_SWEEP);
+    loop {
+        interval.tick().await;
+        if let Err(e) = store.archive_expired_questions(chrono::Utc::now()).await {
+            tracing::error!("Could not archive expired questions: {}", e);
+        }
+    }
+}
+
+/// How often answer client ids past their TTL are forgotten. An id can be
+/// reused up to this long after the TTL is over.
+const CLIENT_ID_EXPIRY_SWEEP: std::time::Duration = std::time::Duration::from_secs(300);
+
+async fn sweep_expired_client_ids<S: store::Store>(store: S, ttl: chrono::Duration) {
+    let mut interval = tokio::time::interval(CLIENT_ID_EXPIRY_SWEEP);
+    loop {
+        interval.tick().await;
+        if let Err(e) = store.expire_client_ids(chrono::Utc::now() - ttl).await {
+            tracing::error!("Could not expire answer client ids: {}", e);
+        }
+    }
+}
+
+pub async fn oneshot<S: store::Store>(config: config::Config, store: S) -> OneshotHandler {
+    let routes = build_routes(config, store).await;
     let (tx, rx) = oneshot::channel::<i32>();
 
     let socket: std::net::SocketAddr = "127.0.0.1:3030"
@@ -202,3 +844,400 @@ pub async fn oneshot(store: store::Store) -> OneshotHandler {
 
     OneshotHandler {sender: tx}
 }
+
+
+#[cfg(test)]
+mod memory_store_tests {
+    use super::build_routes;
+    use crate::config::Config;
+    use crate::store::{MemoryStore, Store};
+    use crate::types::account::AccountId;
+    use crate::types::question::{NewQuestion, Question};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn register_login_and_list_questions() {
+        unsafe {
+            std::env::set_var("PASETO_KEY", "RANDOM WORDS WINTER MACINTOSH PC");
+        }
+
+        let store = MemoryStore::new();
+        let routes = build_routes(Config::default(), store.clone()).await;
+        let user = json!({ "email": "test@email.com", "password": "password" });
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/registration")
+            .json(&user)
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/registration")
+            .json(&user)
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 422);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/login")
+            .json(&user)
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        assert!(serde_json::from_slice::<String>(res.body()).is_ok());
+
+        store
+            .add_question(
+                NewQuestion {
+                    title: "First Question".to_string(),
+                    content: "How can I test?".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let questions = serde_json::from_slice::<Vec<Question>>(res.body()).unwrap();
+        assert_eq!(questions.len(), 1);
+        assert_eq!(questions[0].title, "First Question");
+    }
+
+    #[tokio::test]
+    async fn wrong_password_is_rejected() {
+        let store = MemoryStore::new();
+        let routes = build_routes(Config::default(), store).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/registration")
+            .json(&json!({ "email": "test@email.com", "password": "password" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/login")
+            .json(&json!({ "email": "test@email.com", "password": "wrong" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 401);
+    }
+}
+
+#[cfg(test)]
+mod routes_tests {
+    use super::build_routes;
+    use crate::config::{Config, DisabledRouteStatus, Profile};
+    use crate::store::MemoryStore;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn malformed_query_string_returns_400() {
+        let routes = build_routes(Config::default(), MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .path("/questions?limit=%zz")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 400);
+        let body = String::from_utf8_lossy(res.body());
+        assert!(body.starts_with("Invalid query string"), "{}", body);
+    }
+
+    #[tokio::test]
+    async fn question_ids_below_one_are_refused_before_the_store() {
+        use crate::store::Store;
+        use crate::types::account::AccountId;
+        use crate::types::question::NewQuestion;
+
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Only question".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let routes = build_routes(Config::default(), store).await;
+
+        for path in ["/questions/0", "/questions/-5", "/questions/-5/answers"] {
+            let res = warp::test::request().path(path).reply(&routes).await;
+            assert_eq!(res.status(), 400, "{}", path);
+            let body = String::from_utf8_lossy(res.body());
+            assert!(body.contains("question id must be positive"), "{}", body);
+        }
+
+        let res = warp::test::request().path("/questions/1").reply(&routes).await;
+        assert_eq!(res.status(), 200);
+        let res = warp::test::request().path("/questions/2").reply(&routes).await;
+        assert_eq!(res.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn routes_work_without_request_spans() {
+        use crate::test_utils::capture_logs;
+
+        for disable_request_spans in [false, true] {
+            let (logs, _guard) = capture_logs();
+            let config = Config {
+                disable_request_spans,
+                ..Config::default()
+            };
+            let routes = build_routes(config, MemoryStore::new()).await;
+
+            let res = warp::test::request().path("/questions").reply(&routes).await;
+            assert_eq!(res.status(), 200);
+            let res = warp::test::request().path("/questions/7").reply(&routes).await;
+            assert_eq!(res.status(), 404);
+
+            let logs = logs.contents();
+            assert_eq!(logs.contains("request{"), !disable_request_spans, "{}", logs);
+            assert!(logs.contains("Question not found"));
+            assert_eq!(logs.matches("request completed").count(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn configured_response_headers_are_sent() {
+        use clap::Parser;
+
+        let config = Config::parse_from([
+            "server",
+            "--response-header",
+            "Content-Security-Policy: default-src 'self'",
+            "--response-header",
+            "X-Frame-Options: SAMEORIGIN",
+        ]);
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request().path("/").reply(&routes).await;
+
+        assert_eq!(res.status(), 200);
+        let headers = res.headers();
+        assert_eq!(headers["content-security-policy"], "default-src 'self'");
+        assert_eq!(headers["x-frame-options"], "SAMEORIGIN");
+        assert_eq!(headers["x-content-type-options"], "nosniff");
+    }
+
+    #[tokio::test]
+    async fn unsupported_method_returns_405_with_allow_header() {
+        let routes = build_routes(Config::default(), MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/questions/5")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 405);
+        assert_eq!(res.headers()["Allow"], "GET, PUT, DELETE");
+    }
+
+    #[tokio::test]
+    async fn cors_rejection_has_json_body() {
+        let routes = build_routes(Config::default(), MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("OPTIONS")
+            .path("/questions")
+            .header("Origin", "http://example.com")
+            .header("Access-Control-Request-Method", "PATCH")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 403);
+        let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(body["code"], "CORS_FORBIDDEN");
+        assert!(body["error"].is_string());
+    }
+
+    #[tokio::test]
+    async fn unknown_path_still_returns_404() {
+        let routes = build_routes(Config::default(), MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/nowhere")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn disabled_registration_answers_with_configured_status() {
+        let config = Config {
+            disable_registration: true,
+            disabled_route_status: DisabledRouteStatus::Forbidden,
+            ..Config::default()
+        };
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/registration")
+            .json(&json!({ "email": "test@email.com", "password": "password" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 403);
+
+        let config = Config {
+            disable_registration: true,
+            ..Config::default()
+        };
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/registration")
+            .json(&json!({ "email": "test@email.com", "password": "password" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn read_only_mode_blocks_writes_but_serves_reads() {
+        let config = Config {
+            read_only: true,
+            disabled_route_status: DisabledRouteStatus::Forbidden,
+            ..Config::default()
+        };
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        for (method, path) in [
+            ("POST", "/questions"),
+            ("PUT", "/questions/1"),
+            ("DELETE", "/questions/1"),
+            ("POST", "/answers"),
+        ] {
+            let res = warp::test::request()
+                .method(method)
+                .path(path)
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), 403, "{} {}", method, path);
+        }
+    }
+
+    #[tokio::test]
+    async fn cors_default_depends_on_profile() {
+        let routes = build_routes(Config::default(), MemoryStore::new()).await;
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .header("Origin", "http://example.com")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            res.headers()["access-control-allow-origin"],
+            "http://example.com"
+        );
+
+        let config = Config {
+            profile: Profile::Prod,
+            ..Config::default()
+        };
+        let routes = build_routes(config, MemoryStore::new()).await;
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .header("Origin", "http://example.com")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 403);
+
+        let config = Config {
+            profile: Profile::Prod,
+            cors_allowed_origins: vec!["http://example.com".to_string()],
+            ..Config::default()
+        };
+        let routes = build_routes(config, MemoryStore::new()).await;
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .header("Origin", "http://example.com")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn prod_refuses_cross_origin_preflights_without_configured_origins() {
+        let preflight = |profile: Profile| async move {
+            let config = Config {
+                profile,
+                ..Config::default()
+            };
+            let routes = build_routes(config, MemoryStore::new()).await;
+            warp::test::request()
+                .method("OPTIONS")
+                .path("/questions")
+                .header("Origin", "http://example.com")
+                .header("Access-Control-Request-Method", "POST")
+                .header("Access-Control-Request-Headers", "content-type")
+                .reply(&routes)
+                .await
+        };
+
+        let res = preflight(Profile::Prod).await;
+        assert_eq!(res.status(), 403);
+        assert!(!res.headers().contains_key("access-control-allow-origin"));
+
+        let res = preflight(Profile::Dev).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["access-control-allow-origin"], "http://example.com");
+    }
+
+    #[tokio::test]
+    async fn preflights_allow_the_auth_and_conditional_headers() {
+        let routes = build_routes(Config::default(), MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("OPTIONS")
+            .path("/questions/1")
+            .header("Origin", "http://example.com")
+            .header("Access-Control-Request-Method", "PUT")
+            .header(
+                "Access-Control-Request-Headers",
+                "authorization, content-type, if-unmodified-since",
+            )
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        let allowed = res.headers()["access-control-allow-headers"].to_str().unwrap();
+        for header in ["authorization", "content-type", "if-unmodified-since"] {
+            assert!(allowed.contains(header), "{} not in {}", header, allowed);
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use handle_errors::Error;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{Level, event};
+use warp::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use warp::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use warp::hyper::Body;
+use warp::log::{Info, Log};
+use warp::trace::Trace;
+use warp::path::FullPath;
+use warp::reply::Response;
+use warp::{Filter, Rejection, Reply};
+
+use crate::config::{Config, Profile};
+use crate::rate_limit::GlobalRateLimiter;
+use crate::store::Store;
+use crate::types::feature_flag::FeatureFlag;
+use crate::types::question::QuestionId;
+
+/// Emits one structured line per completed request with the method, path,
+/// final status code and latency. Apply it after `recover` so rejected
+/// requests are logged with the status the client actually received.
+pub fn request_log() -> Log<impl Fn(Info<'_>) + Copy> {
+    warp::log::custom(|info: Info<'_>| {
+        event!(
+            target: "server::request",
+            Level::INFO,
+            method = %info.method(),
+            path = info.path(),
+            status = info.status().as_u16(),
+            latency_ms = info.elapsed().as_millis() as u64,
+            "request completed"
+        );
+    })
+}
+
+/// `warp::trace::request()`, or when not `enabled` a disabled span, which
+/// costs next to nothing for deployments where the spans are too much
+pub fn request_span(
+    enabled: bool,
+) -> Trace<impl Fn(warp::trace::Info<'_>) -> tracing::Span + Clone> {
+    use tracing::field::{Empty, display};
+
+    warp::trace(move |info: warp::trace::Info<'_>| {
+        if !enabled {
+            return tracing::Span::none();
+        }
+        let span = tracing::info_span!(
+            "request",
+            remote.addr = Empty,
+            method = %info.method(),
+            path = %info.path(),
+            version = ?info.version(),
+            referer = Empty,
+        );
+        if let Some(remote_addr) = info.remote_addr() {
+            span.record("remote.addr", display(remote_addr));
+        }
+        if let Some(referer) = info.referer() {
+            span.record("referer", display(referer));
+        }
+        tracing::debug!(parent: &span, "received request");
+
+        span
+    })
+}
+
+/// Adds `Strict-Transport-Security` with the given max-age, if any
+pub fn hsts(reply: impl Reply, max_age: Option<u64>) -> Response {
+    let mut res = reply.into_response();
+    if let Some(max_age) = max_age {
+        let value = format!("max-age={}; includeSubDomains", max_age);
+        res.headers_mut().insert(
+            "Strict-Transport-Security",
+            HeaderValue::from_str(&value).expect("max-age is a valid header value"),
+        );
+    }
+
+    res
+}
+
+/// Sets each of `headers` on the response, replacing what the route set
+pub fn response_headers(reply: impl Reply, headers: &HeaderMap) -> Response {
+    let mut res = reply.into_response();
+    for (name, value) in headers {
+        res.headers_mut().insert(name, value.clone());
+    }
+
+    res
+}
+
+/// Answers requests a TLS-terminating proxy forwarded as plain http with a
+/// 301 to the same path on `https://host`. Rejects with a 404 for anything
+/// else (or without a host) so the regular routes get to handle the request.
+/// The client's `Host` header is never used, so it can't steer the redirect.
+pub fn https_redirect(
+    host: Option<String>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("x-forwarded-proto")
+        .and(warp::path::full())
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .and_then(move |proto: Option<String>, path: FullPath, query: String| {
+            let host = host.clone();
+            async move {
+                match (proto, host) {
+                    (Some(proto), Some(host)) if proto.eq_ignore_ascii_case("http") => {
+                        let mut location = format!("https://{}{}", host, path.as_str());
+                        if !query.is_empty() {
+                            location.push('?');
+                            location.push_str(&query);
+                        }
+
+                        Ok(warp::reply::with_header(
+                            StatusCode::MOVED_PERMANENTLY,
+                            "Location",
+                            location,
+                        )
+                        .into_response())
+                    }
+                    _ => Err(warp::reject::not_found()),
+                }
+            }
+        })
+}
+
+/// CORS policy for the API. Explicitly configured origins always win;
+/// without them dev and test allow any origin, prod none. Tokens and
+/// conditional updates need their headers let through too.
+pub fn cors(config: &Config) -> warp::cors::Builder {
+    let cors = warp::cors()
+        .allow_headers(["content-type", "authorization", "if-unmodified-since"])
+        .allow_methods(&[Method::PUT, Method::DELETE, Method::GET, Method::POST]);
+
+    if !config.cors_allowed_origins.is_empty() {
+        cors.allow_origins(config.cors_allowed_origins.iter().map(String::as_str))
+    } else if config.profile == Profile::Prod {
+        // A builder without origins lets any through, an empty list none
+        cors.allow_origins(Vec::<&str>::new())
+    } else {
+        cors.allow_any_origin()
+    }
+}
+
+/// Whether the request asked for `?pretty=true`
+pub fn pretty_requested() -> impl Filter<Extract = (bool,), Error = Infallible> + Clone {
+    warp::query::raw()
+        .or(warp::any().map(String::new))
+        .unify()
+        .map(|query: String| query.split('&').any(|pair| pair == "pretty=true"))
+}
+
+/// The `:id` of `/questions/:id/...` routes, checked to be a valid
+/// `QuestionId` before any store lookup. Segments which aren't numbers
+/// don't match, as with `param::<i32>()`, so other routes still can; ids
+/// below 1 are a 400.
+pub fn question_id() -> impl Filter<Extract = (i32,), Error = Rejection> + Copy {
+    warp::path::param::<i32>().and_then(|id: i32| async move {
+        QuestionId::try_from(id)
+            .map(|id| id.0)
+            .map_err(|e| warp::reject::custom(Error::InvalidParameter(e)))
+    })
+}
+
+/// Rejects requests whose query string is not valid percent-encoded
+/// UTF-8. `warp::query` decodes such strings lossily, so without this a
+/// typo like `%zz` silently turns into a different parameter value.
+pub fn valid_query() -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::query::raw()
+        .or(warp::any().map(String::new))
+        .unify()
+        .and_then(|query: String| async move {
+            check_query(&query).map_err(|e| warp::reject::custom(Error::InvalidQuery(e)))
+        })
+        .untuple_one()
+}
+
+fn check_query(query: &str) -> Result<(), String> {
+    for pair in query.split('&') {
+        let bytes = pair.as_bytes();
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| std::str::from_utf8(hex).ok())
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("malformed percent-encoding in `{}`", pair))?;
+                decoded.push(hex);
+                i += 3;
+            } else {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+        if String::from_utf8(decoded).is_err() {
+            return Err(format!("`{}` does not decode to UTF-8", pair));
+        }
+    }
+    Ok(())
+}
+
+/// Re-renders JSON replies with indentation when `pretty` is set, for
+/// reading responses in a terminal. Everything else passes through.
+pub async fn pretty_json(reply: impl Reply, pretty: bool) -> Result<Response, Rejection> {
+    let response = reply.into_response();
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == "application/json");
+    if !pretty || !is_json {
+        return Ok(response);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = warp::hyper::body::to_bytes(body).await.unwrap_or_default();
+    let body = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .ok()
+        .and_then(|value| serde_json::to_vec_pretty(&value).ok())
+        .map_or_else(|| Body::from(bytes), Body::from);
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Ok(Response::from_parts(parts, body))
+}
+
+/// Hands out one of `max` permits per request, rejecting with a 503 once
+/// they are all taken instead of queueing. Keep the extracted permit alive
+/// until the reply is built. A `max` of 0 disables the limit.
+pub fn concurrency_limit(
+    max: usize,
+) -> impl Filter<Extract = (Option<OwnedSemaphorePermit>,), Error = Rejection> + Clone {
+    let semaphore = (max > 0).then(|| Arc::new(Semaphore::new(max)));
+
+    warp::any().and_then(move || {
+        let semaphore = semaphore.clone();
+        async move {
+            match semaphore {
+                Some(semaphore) => semaphore
+                    .try_acquire_owned()
+                    .map(Some)
+                    .map_err(|_| warp::reject::custom(Error::ServiceOverloaded)),
+                None => Ok(None),
+            }
+        }
+    })
+}
+
+/// One request's share of its client address' concurrency allowance,
+/// handed back when dropped
+pub struct IpPermit {
+    active: Arc<Mutex<HashMap<IpAddr, usize>>>,
+    ip: IpAddr,
+}
+
+impl Drop for IpPermit {
+    fn drop(&mut self) {
+        let mut active = self.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Caps the requests a single client address has in flight at `max`,
+/// rejecting the rest with a 429. Works alongside `concurrency_limit`,
+/// and like it the extracted permit must outlive the reply. A `max` of 0,
+/// or a connection without a known peer address, is not limited.
+pub fn per_ip_limit(
+    max: usize,
+) -> impl Filter<Extract = (Option<IpPermit>,), Error = Rejection> + Clone {
+    let active: Arc<Mutex<HashMap<IpAddr, usize>>> = Arc::default();
+
+    warp::addr::remote().and_then(move |remote: Option<SocketAddr>| {
+        let active = active.clone();
+        async move {
+            let Some(ip) = remote.map(|remote| remote.ip()).filter(|_| max > 0) else {
+                return Ok(None);
+            };
+            let mut counts = active.lock().unwrap();
+            let count = counts.entry(ip).or_default();
+            if *count >= max {
+                return Err(warp::reject::custom(Error::TooManyRequests(1)));
+            }
+            *count += 1;
+            drop(counts);
+
+            Ok(Some(IpPermit { active, ip }))
+        }
+    })
+}
+
+/// Rejects requests with a 429 once the server-wide rate is exceeded, see
+/// `GlobalRateLimiter`
+pub fn global_rate_limit(
+    limiter: GlobalRateLimiter,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::any()
+        .and_then(move || {
+            let result = limiter.check(Instant::now());
+            async move { result.map_err(warp::reject::custom) }
+        })
+        .untuple_one()
+}
+
+/// Lets requests through only while `enabled`, otherwise rejects them with
+/// the given status. Put it after the method and path filters so only the
+/// matched route is switched off.
+pub fn route_enabled(
+    enabled: bool,
+    status: StatusCode,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::any()
+        .and_then(move || async move {
+            if enabled {
+                Ok(())
+            } else {
+                Err(warp::reject::custom(Error::RouteDisabled(status)))
+            }
+        })
+        .untuple_one()
+}
+
+/// Like `route_enabled`, but looks the switch up on every request: lets it
+/// through only while the store's `flag` is `wanted`
+pub fn flag_enabled<S: Store>(
+    store: S,
+    flag: FeatureFlag,
+    wanted: bool,
+    status: StatusCode,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::any()
+        .and_then(move || {
+            let store = store.clone();
+            async move {
+                if store.flag_enabled(flag).await? == wanted {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(Error::RouteDisabled(status)))
+                }
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod middleware_tests {
+    use super::{
+        concurrency_limit, global_rate_limit, hsts, https_redirect, per_ip_limit, pretty_json,
+        pretty_requested, request_log,
+    };
+    use crate::rate_limit::GlobalRateLimiter;
+    use crate::test_utils::capture_logs;
+    use handle_errors::return_error;
+    use std::time::Duration;
+    use warp::Filter;
+
+    #[tokio::test]
+    async fn logs_method_path_status_and_latency() {
+        let (logs, _guard) = capture_logs();
+
+        let filter = warp::path("questions")
+            .map(warp::reply)
+            .with(request_log());
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .reply(&filter)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let line = logs.contents();
+        assert!(line.contains("request completed"));
+        assert!(line.contains("method=GET"));
+        assert!(line.contains("/questions"));
+        assert!(line.contains("status=200"));
+        assert!(line.contains("latency_ms="));
+    }
+
+    #[tokio::test]
+    async fn hsts_header_is_added_when_enabled() {
+        let filter = warp::any()
+            .map(warp::reply)
+            .map(|reply| hsts(reply, Some(600)));
+
+        let res = warp::test::request().reply(&filter).await;
+        assert_eq!(
+            res.headers()["Strict-Transport-Security"],
+            "max-age=600; includeSubDomains"
+        );
+
+        let filter = warp::any().map(warp::reply).map(|reply| hsts(reply, None));
+
+        let res = warp::test::request().reply(&filter).await;
+        assert!(res.headers().get("Strict-Transport-Security").is_none());
+    }
+
+    #[tokio::test]
+    async fn forwarded_http_is_redirected_to_https() {
+        let filter = https_redirect(Some("example.com".to_string()));
+
+        let res = warp::test::request()
+            .path("/questions?limit=1&offset=0")
+            .header("host", "example.com")
+            .header("x-forwarded-proto", "http")
+            .reply(&filter)
+            .await;
+        assert_eq!(res.status(), 301);
+        assert_eq!(
+            res.headers()["Location"],
+            "https://example.com/questions?limit=1&offset=0"
+        );
+
+        let res = warp::test::request()
+            .path("/questions")
+            .header("host", "example.com")
+            .header("x-forwarded-proto", "https")
+            .reply(&filter)
+            .await;
+        assert_eq!(res.status(), 404);
+
+        let res = warp::test::request()
+            .path("/questions")
+            .header("host", "example.com")
+            .header("x-forwarded-proto", "http")
+            .reply(&https_redirect(None))
+            .await;
+        assert_eq!(res.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn redirects_ignore_the_clients_host_header() {
+        let res = warp::test::request()
+            .path("/questions")
+            .header("host", "evil.example")
+            .header("x-forwarded-proto", "http")
+            .reply(&https_redirect(Some("example.com".to_string())))
+            .await;
+        assert_eq!(res.status(), 301);
+        assert_eq!(res.headers()["Location"], "https://example.com/questions");
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_concurrency_limit_get_503() {
+        let slow = warp::any().and_then(|| async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok::<_, warp::Rejection>("done")
+        });
+        let filter = concurrency_limit(2)
+            .and(slow)
+            .map(|_permit, reply| reply)
+            .recover(return_error);
+
+        let (a, b, c, d) = tokio::join!(
+            warp::test::request().reply(&filter),
+            warp::test::request().reply(&filter),
+            warp::test::request().reply(&filter),
+            warp::test::request().reply(&filter),
+        );
+
+        let statuses = [a.status(), b.status(), c.status(), d.status()];
+        assert_eq!(statuses.iter().filter(|s| s.as_u16() == 200).count(), 2);
+        assert_eq!(statuses.iter().filter(|s| s.as_u16() == 503).count(), 2);
+
+        // Permits are given back once the replies are built
+        let res = warp::test::request().reply(&filter).await;
+        assert_eq!(res.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_per_ip_limit_get_429() {
+        let slow = warp::any().and_then(|| async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok::<_, warp::Rejection>("done")
+        });
+        let filter = per_ip_limit(2)
+            .and(slow)
+            .map(|_permit, reply| reply)
+            .recover(return_error);
+        let client = |ip: [u8; 4]| warp::test::request().remote_addr((ip, 4000).into());
+
+        let (a, b, c, other) = tokio::join!(
+            client([10, 0, 0, 1]).reply(&filter),
+            client([10, 0, 0, 1]).reply(&filter),
+            client([10, 0, 0, 1]).reply(&filter),
+            client([10, 0, 0, 2]).reply(&filter),
+        );
+
+        let statuses = [a.status(), b.status(), c.status()];
+        assert_eq!(statuses.iter().filter(|s| s.as_u16() == 200).count(), 2);
+        assert_eq!(statuses.iter().filter(|s| s.as_u16() == 429).count(), 1);
+        assert_eq!(other.status(), 200);
+
+        // Counts drop back once the replies are built
+        let res = client([10, 0, 0, 1]).reply(&filter).await;
+        assert_eq!(res.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_global_rate_get_429() {
+        let limiter = GlobalRateLimiter::new(3, std::time::Instant::now());
+        let filter = global_rate_limit(limiter)
+            .map(|| "done")
+            .recover(return_error);
+
+        let mut statuses = Vec::new();
+        for _ in 0..5 {
+            statuses.push(warp::test::request().reply(&filter).await.status());
+        }
+
+        assert_eq!(statuses.iter().filter(|s| s.as_u16() == 200).count(), 3);
+        assert_eq!(statuses.iter().filter(|s| s.as_u16() == 429).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn pretty_param_indents_json_replies() {
+        let filter = pretty_requested()
+            .and(warp::any().map(|| {
+                warp::reply::json(&serde_json::json!({ "tags": ["rust"] }))
+            }))
+            .and_then(|pretty, reply| pretty_json(reply, pretty));
+
+        let res = warp::test::request()
+            .path("/questions?pretty=true")
+            .reply(&filter)
+            .await;
+        let body = std::str::from_utf8(res.body()).unwrap();
+        assert!(body.contains("\n  \"tags\""));
+
+        let res = warp::test::request().path("/questions").reply(&filter).await;
+        assert_eq!(res.body(), r#"{"tags":["rust"]}"#);
+    }
+}
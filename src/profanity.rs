@@ -2,7 +2,11 @@ use reqwest_middleware::ClientBuilder;
 use reqwest_retry::{RetryTransientMiddleware, policies::ExponentialBackoff};
 use serde::{Deserialize, Serialize};
 use std::env;
-use tracing::instrument;
+use std::time::{Duration, Instant};
+use tracing::{Instrument, field, instrument};
+
+use crate::store::Store;
+use crate::types::account::AccountId;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct APIResponse {
@@ -17,6 +21,11 @@ pub struct BadWord {
     info: i64,
     #[serde(rename = "replacedLen")]
     replaced_len: i64,
+    /// Character offsets of the word in the checked content
+    #[serde(default)]
+    start: usize,
+    #[serde(default)]
+    end: usize,
 }
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct BadWordsResponse {
@@ -26,11 +35,66 @@ pub struct BadWordsResponse {
     pub censored_content: String,
 }
 
+/// Where the bad words API lives and how long it is waited for
+#[derive(Debug, Clone)]
+pub struct ProfanityApi {
+    pub url: String,
+    pub timeout: Duration,
+    /// Pass content through unchecked on a timeout instead of failing
+    pub fail_open: bool,
+}
+
+/// Sends `content` to the bad words API and returns the censored version.
+/// Words on the `allow_list` are never censored, even when the API flags
+/// them. When the API takes longer than `api.timeout` the content is
+/// returned as it is if `api.fail_open`, otherwise the check fails.
 #[instrument]
-pub async fn check_profanity(content: String) -> Result<String, handle_errors::Error> {
+pub async fn check_profanity(
+    api: ProfanityApi,
+    content: String,
+    allow_list: Vec<String>,
+) -> Result<String, handle_errors::Error> {
     let api_key = env::var("BAD_WORDS_API_KEY").expect("BAD WORDS API KEY NOT SET");
-    let api_layer_url = env::var("API_LAYER_URL").expect("APILAYER URL NOT SET");
 
+    let check = check_profanity_at(&api.url, &api_key, content.clone(), allow_list);
+    match tokio::time::timeout(api.timeout, check).await {
+        Ok(checked) => checked,
+        Err(_) => {
+            let timeout_ms = api.timeout.as_millis() as u64;
+            if api.fail_open {
+                tracing::warn!(timeout_ms, "Bad words API timed out, content not checked");
+                Ok(content)
+            } else {
+                tracing::warn!(timeout_ms, "Bad words API timed out");
+                Err(handle_errors::Error::ProfanityCheckTimedOut)
+            }
+        }
+    }
+}
+
+/// `check_profanity` when `enabled`, otherwise `content` as it is
+pub async fn check_profanity_when(
+    enabled: bool,
+    api: ProfanityApi,
+    content: String,
+    allow_list: Vec<String>,
+) -> Result<String, handle_errors::Error> {
+    if !enabled {
+        return Ok(content);
+    }
+
+    check_profanity(api, content, allow_list).await
+}
+
+/// `check_profanity` against the bad words API at `api_layer_url`. Each
+/// call gets a `profanity_api` span recording the URL, the response status
+/// and how long the API took, retries included.
+async fn check_profanity_at(
+    api_layer_url: &str,
+    api_key: &str,
+    content: String,
+    allow_list: Vec<String>,
+) -> Result<String, handle_errors::Error> {
     let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
     let client = ClientBuilder::new(reqwest::Client::new())
         // Trace HTTP requests. See the tracing crate to make use of these traces.
@@ -38,55 +102,166 @@ pub async fn check_profanity(content: String) -> Result<String, handle_errors::E
         .with(RetryTransientMiddleware::new_with_policy(retry_policy))
         .build();
 
+    let url = format!("{}/bad_words?censor_character=*", api_layer_url);
+    let span = tracing::info_span!(
+        "profanity_api",
+        url = %url,
+        status = field::Empty,
+        elapsed_ms = field::Empty,
+    );
+    let started = Instant::now();
     let res = client
-        .post(format!("{}/bad_words?censor_character=*", api_layer_url))
+        .post(&url)
         .header("apikey", api_key)
-        .body(content)
+        .body(content.clone())
         .send()
-        .await
-        .map_err(handle_errors::Error::MiddlewareReqwestError)?;
+        .instrument(span.clone())
+        .await;
+
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    span.record("elapsed_ms", elapsed_ms);
+    if let Ok(res) = &res {
+        span.record("status", res.status().as_u16());
+    }
+    span.in_scope(|| tracing::info!(elapsed_ms, "Bad words API responded"));
+    let res = res.map_err(handle_errors::Error::MiddlewareReqwestError)?;
 
     if !res.status().is_success() {
-        if res.status().is_client_error() {
-            let err = transform_error(res).await;
-            return Err(handle_errors::Error::ClientError(err));
-        } else {
-            let err = transform_error(res).await;
-            return Err(handle_errors::Error::ServerError(err));
-        }
+        return Err(api_error(res).await);
     }
 
     match res.json::<BadWordsResponse>().await {
-        Ok(res) => Ok(res.censored_content),
+        Ok(res) => Ok(apply_allow_list(&content, res, &allow_list)),
         Err(e) => Err(handle_errors::Error::ReqwestAPIError(e)),
     }
 }
 
-async fn transform_error(res: reqwest::Response) -> handle_errors::APILayerError {
-    handle_errors::APILayerError {
-        status: res.status().as_u16(),
-        message: res.json::<APIResponse>().await.unwrap().message,
+/// Censors only the flagged words which aren't allow-listed, falling back
+/// to the API's own censoring when none of them are
+fn apply_allow_list(content: &str, res: BadWordsResponse, allow_list: &[String]) -> String {
+    let is_allowed = |bad_word: &BadWord| {
+        allow_list.iter().any(|allowed| {
+            allowed.eq_ignore_ascii_case(&bad_word.word)
+                || allowed.eq_ignore_ascii_case(&bad_word.original)
+        })
+    };
+    let blocked: Vec<&BadWord> = res
+        .bad_words_list
+        .iter()
+        .filter(|bad_word| !is_allowed(bad_word))
+        .collect();
+
+    if blocked.len() == res.bad_words_list.len() {
+        return res.censored_content;
+    }
+
+    content
+        .chars()
+        .enumerate()
+        .map(|(index, c)| {
+            if blocked
+                .iter()
+                .any(|bad_word| (bad_word.start..bad_word.end).contains(&index))
+            {
+                '*'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Characters of censored text kept in logs and the audit trail
+const PREVIEW_CHARS: usize = 40;
+
+/// Notes that the account's `resource` (e.g. "question") came back from
+/// `check_profanity` changed: an INFO line, plus a row in the audit table
+/// when `audit` is on. The preview is taken from the censored text, so
+/// the flagged words never end up in the logs.
+pub async fn record_censorship<S: Store>(
+    store: &S,
+    audit: bool,
+    account_id: &AccountId,
+    resource: &str,
+    original: &str,
+    censored: &str,
+) -> Result<(), handle_errors::Error> {
+    if original == censored {
+        return Ok(());
+    }
+
+    let mut preview: String = censored.chars().take(PREVIEW_CHARS).collect();
+    if censored.chars().nth(PREVIEW_CHARS).is_some() {
+        preview.push('…');
+    }
+    tracing::info!(
+        account_id = account_id.0,
+        resource,
+        preview = %preview,
+        "Content censored"
+    );
+
+    if audit {
+        store.record_censorship(account_id, resource, &preview).await?;
+    }
+
+    Ok(())
+}
+
+/// Classifies a non-2xx response: 4xx means our request was wrong, anything
+/// else is a failure on the API's side. APILayer usually answers with
+/// `{"message": ...}`, other bodies are kept as they are.
+async fn api_error(res: reqwest::Response) -> handle_errors::Error {
+    let status = res.status();
+    let body = res.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<APIResponse>(&body)
+        .map(|response| response.message)
+        .unwrap_or(body);
+
+    let err = handle_errors::APILayerError {
+        status: status.as_u16(),
+        message,
+    };
+    if status.is_client_error() {
+        handle_errors::Error::ClientError(err)
+    } else {
+        handle_errors::Error::ServerError(err)
     }
 }
 
 #[cfg(test)]
 mod profanity_tests {
-    use super::{check_profanity, env};
+    use super::{ProfanityApi, check_profanity, check_profanity_at, env, record_censorship};
+    use crate::config::Config;
+    use crate::store::MemoryStore;
+    use crate::test_utils::capture_logs;
+    use crate::types::account::AccountId;
 
+    use handle_errors::Error;
     use mock_server::{MockServer, OneshotHandler};
+    use serde_json::json;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use warp::Filter;
 
     #[tokio::test]
     async fn run() {
         let handler = run_mock();
         censor_profane_words().await;
         no_profane_words().await;
+        allow_listed_words_pass_through().await;
         let _ = handler.sender.send(1);
     }
 
+    const MOCK_URL: &str = "http://127.0.0.1:3030";
+
     fn run_mock() -> OneshotHandler {
         unsafe {
             env::set_var("BAD_WORDS_API_KEY", "YES");
-            env::set_var("API_LAYER_URL", "http://127.0.0.1:3030");
         }
 
         let socket = "127.0.0.1:3030"
@@ -97,15 +272,204 @@ mod profanity_tests {
         mock.oneshot()
     }
 
+    fn mock_api() -> ProfanityApi {
+        ProfanityApi {
+            url: MOCK_URL.to_string(),
+            timeout: Duration::from_secs(3),
+            fail_open: false,
+        }
+    }
+
     async fn censor_profane_words() {
         let content = "this is a shitty sentence".to_string();
-        let censored_content = check_profanity(content).await;
+        let censored_content = check_profanity(mock_api(), content, Vec::new()).await;
         assert_eq!(censored_content.unwrap(), "this is a ****** sentence");
     }
 
     async fn no_profane_words() {
         let content = "this is a sentence".to_string();
-        let censored_content = check_profanity(content).await;
+        let censored_content = check_profanity(mock_api(), content, Vec::new()).await;
         assert_eq!(censored_content.unwrap(), "");
     }
+
+    async fn allow_listed_words_pass_through() {
+        let content = "this is a shitty sentence".to_string();
+        let allow_list = vec!["Shitty".to_string()];
+        let checked = check_profanity(mock_api(), content.clone(), allow_list).await;
+        assert_eq!(checked.unwrap(), content);
+    }
+
+    /// Collects every `elapsed_ms` recorded on a span
+    #[derive(Clone, Default)]
+    struct ElapsedCapture(Arc<Mutex<Vec<u64>>>);
+
+    impl Visit for ElapsedCapture {
+        fn record_u64(&mut self, field: &Field, value: u64) {
+            if field.name() == "elapsed_ms" {
+                self.0.lock().unwrap().push(value);
+            }
+        }
+
+        fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+    }
+
+    impl<S: tracing::Subscriber> Layer<S> for ElapsedCapture {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            attrs.record(&mut self.clone());
+        }
+
+        fn on_record(&self, _id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+            values.record(&mut self.clone());
+        }
+    }
+
+    /// Serves `bad_words` answering every request with `status` and `body`
+    fn failing_api(status: u16, body: &'static str) -> String {
+        let api = warp::post().and(warp::path("bad_words")).map(move || {
+            warp::reply::with_status(body, warp::http::StatusCode::from_u16(status).unwrap())
+        });
+        let (addr, server) = warp::serve(api).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn checks_go_to_the_configured_url() {
+        use clap::Parser;
+
+        let hits = Arc::new(Mutex::new(0));
+        let counted = hits.clone();
+        let api = warp::post().and(warp::path("bad_words")).map(move || {
+            *counted.lock().unwrap() += 1;
+            warp::reply::json(&json!({
+                "content": "a sentence",
+                "bad_words_total": 0,
+                "bad_words_list": [],
+                "censored_content": "a sentence",
+            }))
+        });
+        let (addr, server) = warp::serve(api).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        unsafe {
+            env::set_var("BAD_WORDS_API_KEY", "YES");
+        }
+
+        let url = format!("http://{}", addr);
+        let config = Config::parse_from(["server", "--profanity-api-url", &url]);
+        let content = "a sentence".to_string();
+        let checked = check_profanity(config.profanity_api(), content, Vec::new())
+            .await
+            .unwrap();
+
+        assert_eq!(checked, "a sentence");
+        assert_eq!(*hits.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn timeouts_fail_closed_unless_configured_open() {
+        let hung_api = warp::post()
+            .and(warp::path("bad_words"))
+            .and_then(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok::<_, Infallible>(warp::reply())
+            });
+        let (addr, server) = warp::serve(hung_api).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        unsafe {
+            env::set_var("BAD_WORDS_API_KEY", "YES");
+        }
+        let api = |fail_open| ProfanityApi {
+            url: format!("http://{}", addr),
+            timeout: Duration::from_millis(100),
+            fail_open,
+        };
+        let content = "a shitty sentence".to_string();
+
+        let result = check_profanity(api(false), content.clone(), Vec::new()).await;
+        assert!(matches!(result, Err(Error::ProfanityCheckTimedOut)));
+
+        let result = check_profanity(api(true), content.clone(), Vec::new()).await;
+        assert_eq!(result.unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_parsed() {
+        let url = failing_api(400, r#"{"message": "Invalid censor character"}"#);
+
+        let result = check_profanity_at(&url, "key", "a sentence".to_string(), Vec::new()).await;
+        match result {
+            Err(Error::ClientError(err)) => {
+                assert_eq!(err.status, 400);
+                assert_eq!(err.message, "Invalid censor character");
+            }
+            other => panic!("expected a client error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_parsed() {
+        let url = failing_api(500, "upstream exploded");
+
+        let result = check_profanity_at(&url, "key", "a sentence".to_string(), Vec::new()).await;
+        match result {
+            Err(Error::ServerError(err)) => {
+                assert_eq!(err.status, 500);
+                assert_eq!(err.message, "upstream exploded");
+            }
+            other => panic!("expected a server error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn api_calls_are_timed() {
+        let slow_api = warp::post()
+            .and(warp::path("bad_words"))
+            .and_then(|| async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok::<_, Infallible>(warp::reply::json(&json!({
+                    "content": "a sentence",
+                    "bad_words_total": 0,
+                    "bad_words_list": [],
+                    "censored_content": "a sentence",
+                })))
+            });
+        let (addr, server) = warp::serve(slow_api).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let capture = ElapsedCapture::default();
+        let subscriber = tracing_subscriber::registry().with(capture.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let url = format!("http://{}", addr);
+        check_profanity_at(&url, "key", "a sentence".to_string(), Vec::new())
+            .await
+            .unwrap();
+
+        let recorded = capture.0.lock().unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert!(recorded[0] >= 50);
+    }
+
+    #[tokio::test]
+    async fn censoring_is_logged_without_the_flagged_words() {
+        let (logs, _guard) = capture_logs();
+        let store = MemoryStore::new();
+        let account_id = AccountId(3);
+
+        for (original, censored) in [
+            ("a shitty answer", "a ****** answer"),
+            ("a fine answer", "a fine answer"),
+        ] {
+            record_censorship(&store, true, &account_id, "answer", original, censored)
+                .await
+                .unwrap();
+        }
+
+        let logs = logs.contents();
+        assert_eq!(logs.matches("Content censored").count(), 1);
+        assert!(logs.contains("account_id=3"));
+        assert!(logs.contains("resource=\"answer\""));
+        assert!(logs.contains("preview=a ****** answer"));
+        assert!(!logs.contains("shitty"));
+    }
 }
@@ -0,0 +1,146 @@
+use handle_errors::Error;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::types::account::AccountId;
+
+/// Sliding-window cap on how many questions one account may post. Kept in
+/// memory, so every server instance counts on its own.
+#[derive(Debug, Clone)]
+pub struct QuestionRateLimiter {
+    limit: usize,
+    window: Duration,
+    /// Account id -> when its posts still inside the window were made
+    posts: Arc<Mutex<HashMap<i32, VecDeque<Instant>>>>,
+}
+
+impl QuestionRateLimiter {
+    /// A `limit` of 0 lets everything through
+    pub fn new(limit: usize, window: Duration) -> Self {
+        QuestionRateLimiter {
+            limit,
+            window,
+            posts: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Count a post by `account_id` at `now`, or fail with how many
+    /// seconds remain until it would be allowed
+    pub fn check(&self, account_id: &AccountId, now: Instant) -> Result<(), Error> {
+        if self.limit == 0 {
+            return Ok(());
+        }
+
+        let mut posts = self.posts.lock().unwrap();
+        let recent = posts.entry(account_id.0).or_default();
+        while recent
+            .front()
+            .is_some_and(|posted| now.duration_since(*posted) >= self.window)
+        {
+            recent.pop_front();
+        }
+
+        if recent.len() >= self.limit {
+            let oldest = recent.front().expect("limit is above 0");
+            let wait = self.window - now.duration_since(*oldest);
+            // Round up so retrying after Retry-After always succeeds
+            let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            return Err(Error::TooManyRequests(retry_after));
+        }
+
+        recent.push_back(now);
+        Ok(())
+    }
+}
+
+/// Token bucket capping the requests the whole server accepts per second,
+/// whoever sends them. Up to a second's worth may arrive in a burst.
+#[derive(Debug, Clone)]
+pub struct GlobalRateLimiter {
+    per_second: u32,
+    /// Tokens left and when they were last topped up
+    bucket: Arc<Mutex<(f64, Instant)>>,
+}
+
+impl GlobalRateLimiter {
+    /// A `per_second` of 0 lets everything through
+    pub fn new(per_second: u32, now: Instant) -> Self {
+        GlobalRateLimiter {
+            per_second,
+            bucket: Arc::new(Mutex::new((f64::from(per_second), now))),
+        }
+    }
+
+    /// Take a token for a request arriving at `now`, or fail with how many
+    /// seconds remain until the next one is available
+    pub fn check(&self, now: Instant) -> Result<(), Error> {
+        if self.per_second == 0 {
+            return Ok(());
+        }
+
+        let rate = f64::from(self.per_second);
+        let mut bucket = self.bucket.lock().unwrap();
+        let (tokens, refilled) = &mut *bucket;
+        let elapsed = now.saturating_duration_since(*refilled).as_secs_f64();
+        *tokens = (*tokens + elapsed * rate).min(rate);
+        *refilled = now.max(*refilled);
+
+        if *tokens < 1.0 {
+            let wait = (1.0 - *tokens) / rate;
+            return Err(Error::TooManyRequests(wait.ceil() as u64));
+        }
+
+        *tokens -= 1.0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::*;
+
+    #[test]
+    fn posts_beyond_the_limit_wait_for_the_window() {
+        let limiter = QuestionRateLimiter::new(2, Duration::from_secs(3600));
+        let account = AccountId(1);
+        let start = Instant::now();
+
+        assert!(limiter.check(&account, start).is_ok());
+        assert!(limiter.check(&account, start + Duration::from_secs(60)).is_ok());
+        assert!(matches!(
+            limiter.check(&account, start + Duration::from_secs(120)),
+            Err(Error::TooManyRequests(3480))
+        ));
+        // Other accounts have their own budget
+        assert!(limiter.check(&AccountId(2), start).is_ok());
+
+        // The first post has left the window
+        assert!(limiter.check(&account, start + Duration::from_secs(3600)).is_ok());
+        assert!(limiter.check(&account, start + Duration::from_secs(3601)).is_err());
+    }
+
+    #[test]
+    fn zero_limit_never_throttles() {
+        let limiter = QuestionRateLimiter::new(0, Duration::from_secs(3600));
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert!(limiter.check(&AccountId(1), now).is_ok());
+        }
+    }
+
+    #[test]
+    fn global_bucket_refills_at_the_configured_rate() {
+        let start = Instant::now();
+        let limiter = GlobalRateLimiter::new(2, start);
+
+        assert!(limiter.check(start).is_ok());
+        assert!(limiter.check(start).is_ok());
+        assert!(matches!(limiter.check(start), Err(Error::TooManyRequests(1))));
+
+        // Half a second buys one more request at 2 per second
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check(later).is_ok());
+        assert!(limiter.check(later).is_err());
+    }
+}
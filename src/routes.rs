@@ -1,3 +1,12 @@
+pub mod account;
+pub mod activity;
 pub mod answer;
+pub mod api_token;
 pub mod authentication;
+pub mod bookmark;
+pub mod feature_flag;
 pub mod question;
+pub mod service;
+pub mod stats;
+pub mod tag;
+pub mod vote;
@@ -0,0 +1,551 @@
+use chrono::Utc;
+use std::sync::Arc;
+use warp::http::StatusCode;
+
+use crate::config::Config;
+use crate::store::Store;
+use crate::types::account::{
+    AccountExport, AccountId, DeletedContent, NotificationPreferences, Session, Suspension,
+    WhoAmI,
+};
+
+/// `GET /accounts/me`: the logged in account, including its last login
+pub async fn get_me<S: Store>(
+    session: Session,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_account_profile(&session.account_id).await {
+        Ok(profile) => Ok(warp::reply::json(&profile)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// `PUT /accounts/me/preferences`: replace the account's notification
+/// preferences
+pub async fn update_preferences<S: Store>(
+    session: Session,
+    store: S,
+    preferences: NotificationPreferences,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store
+        .set_preferences(&session.account_id, &preferences)
+        .await?;
+    let profile = store.get_account_profile(&session.account_id).await?;
+
+    Ok(warp::reply::json(&profile))
+}
+
+/// `GET /accounts/me/export`: everything stored about the logged in
+/// account, for data portability requests
+pub async fn export_me<S: Store>(
+    session: Session,
+    store: S,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = session.account_id;
+
+    // Paged, so `max_rows` doesn't cut the list short
+    let page = config.max_rows as u32;
+    let mut bookmarks = Vec::new();
+    loop {
+        let offset = bookmarks.len() as u32;
+        let next = store.get_bookmarks(&account_id, Some(page), offset).await?;
+        let done = next.len() < page as usize;
+        bookmarks.extend(next);
+        if done {
+            break;
+        }
+    }
+
+    let export = AccountExport {
+        profile: store.get_account_profile(&account_id).await?,
+        questions: store.get_account_questions(&account_id).await?,
+        answers: store.get_account_answers(&account_id).await?,
+        votes: store.get_account_votes(&account_id).await?,
+        bookmarks,
+    };
+
+    Ok(warp::reply::json(&export))
+}
+
+/// `GET /whoami`: a cheap check that a token is still valid. Answers from
+/// the session alone, without loading the account.
+pub async fn whoami(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&WhoAmI {
+        account_id: session.account_id,
+        is_admin: session.is_admin,
+    }))
+}
+
+/// `DELETE /accounts/me`: schedule the account for deletion once the
+/// configured grace period is over
+pub async fn delete_me<S: Store>(
+    session: Session,
+    store: S,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let grace_period = chrono::Duration::seconds(config.account_deletion_grace_period as i64);
+    let due_at = Utc::now() + grace_period;
+    store
+        .schedule_account_deletion(&session.account_id, due_at)
+        .await?;
+    let profile = store.get_account_profile(&session.account_id).await?;
+
+    Ok(warp::reply::with_status(
+        warp::reply::json(&profile),
+        StatusCode::ACCEPTED,
+    ))
+}
+
+/// `POST /accounts/me/cancel-deletion`: keep an account scheduled for
+/// deletion
+pub async fn cancel_deletion<S: Store>(
+    session: Session,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.cancel_account_deletion(&session.account_id).await?;
+    let profile = store.get_account_profile(&session.account_id).await?;
+
+    Ok(warp::reply::json(&profile))
+}
+
+/// `POST /admin/accounts/:id/suspend`: block the account's writes until
+/// the given time. Reads stay allowed. For admins only.
+pub async fn suspend_account<S: Store>(
+    account_id: i32,
+    _session: Session,
+    store: S,
+    suspension: Suspension,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account_id = AccountId(account_id);
+    store.suspend_account(&account_id, suspension.until).await?;
+    let profile = store.get_account_profile(&account_id).await?;
+
+    Ok(warp::reply::json(&profile))
+}
+
+/// Remove the accounts whose grace period is over, with their content
+/// deleted or anonymized as `content` says. Run periodically by `run`.
+pub async fn finalize_account_deletions<S: Store>(
+    store: &S,
+    content: DeletedContent,
+) -> Result<u64, handle_errors::Error> {
+    let deleted = store.delete_due_accounts(Utc::now(), content).await?;
+    if deleted > 0 {
+        tracing::info!("Deleted {} accounts past their grace period", deleted);
+    }
+
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod account_tests {
+    use chrono::Utc;
+    use serde_json::json;
+    use std::net::SocketAddr;
+
+    use super::finalize_account_deletions;
+    use crate::build_routes;
+    use crate::config::{Config, DisabledRouteStatus, PasswordAlgorithm};
+    use crate::routes::authentication::{hash_password, issue_token};
+    use crate::store::{MemoryStore, Store};
+    use crate::types::account::{
+        Account, AccountId, AccountProfile, DeletedContent, NotificationPreferences, WhoAmI,
+    };
+    use crate::types::answer::NewAnswer;
+    use crate::types::question::{NewQuestion, QuestionId};
+    use crate::types::vote::Vote;
+
+    async fn store_with_account() -> MemoryStore {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "me@email.com".to_string(),
+                password: hash_password(b"password", PasswordAlgorithm::Argon2).unwrap(),
+                is_admin: false,
+                verified: true,
+            })
+            .await
+            .unwrap();
+        store
+    }
+
+    fn config(grace_period: u64) -> Config {
+        Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            account_deletion_grace_period: grace_period,
+            ..Config::default()
+        }
+    }
+
+    async fn login_from(
+        routes: &(impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection>
+              + Clone
+              + 'static),
+        addr: &str,
+    ) -> String {
+        let res = warp::test::request()
+            .method("POST")
+            .path("/login")
+            .remote_addr(addr.parse::<SocketAddr>().unwrap())
+            .json(&json!({ "email": "me@email.com", "password": "password" }))
+            .reply(routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        serde_json::from_slice(res.body()).unwrap()
+    }
+
+    async fn profile(
+        routes: &(impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection>
+              + Clone
+              + 'static),
+        token: &str,
+    ) -> AccountProfile {
+        let res = warp::test::request()
+            .method("GET")
+            .path("/accounts/me")
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        serde_json::from_slice(res.body()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn login_records_time_and_address() {
+        let routes = build_routes(config(0), store_with_account().await).await;
+
+        let token = login_from(&routes, "10.0.0.1:4000").await;
+        let first = profile(&routes, &token).await;
+        assert!(first.last_login_at.is_some());
+        assert_eq!(first.last_login_ip.as_deref(), Some("10.0.0.1"));
+
+        let token = login_from(&routes, "10.0.0.2:4000").await;
+        let second = profile(&routes, &token).await;
+        assert!(second.last_login_at > first.last_login_at);
+        assert_eq!(second.last_login_ip.as_deref(), Some("10.0.0.2"));
+    }
+
+    async fn request(
+        routes: &(impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection>
+              + Clone
+              + 'static),
+        method: &str,
+        path: &str,
+        token: &str,
+    ) -> u16 {
+        warp::test::request()
+            .method(method)
+            .path(path)
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(routes)
+            .await
+            .status()
+            .as_u16()
+    }
+
+    #[tokio::test]
+    async fn deletion_is_scheduled_and_can_be_cancelled() {
+        let routes = build_routes(config(3600), store_with_account().await).await;
+        let token = login_from(&routes, "10.0.0.1:4000").await;
+
+        assert_eq!(request(&routes, "DELETE", "/accounts/me", &token).await, 202);
+        assert!(profile(&routes, &token).await.deletion_due_at.is_some());
+
+        let cancel = "/accounts/me/cancel-deletion";
+        assert_eq!(request(&routes, "POST", cancel, &token).await, 200);
+        assert!(profile(&routes, &token).await.deletion_due_at.is_none());
+        // Nothing left to cancel
+        assert_eq!(request(&routes, "POST", cancel, &token).await, 409);
+    }
+
+    #[tokio::test]
+    async fn finalizer_only_removes_accounts_past_their_grace_period() {
+        let store = store_with_account().await;
+        let routes = build_routes(config(3600), store.clone()).await;
+        let token = login_from(&routes, "10.0.0.1:4000").await;
+        assert_eq!(request(&routes, "DELETE", "/accounts/me", &token).await, 202);
+
+        assert_eq!(finalize_account_deletions(&store, DeletedContent::Anonymize).await.unwrap(), 0);
+        assert!(store.get_account("me@email.com".to_string()).await.is_ok());
+
+        let routes = build_routes(config(0), store.clone()).await;
+        assert_eq!(request(&routes, "DELETE", "/accounts/me", &token).await, 202);
+        assert_eq!(finalize_account_deletions(&store, DeletedContent::Anonymize).await.unwrap(), 1);
+        assert!(store.get_account("me@email.com".to_string()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn suspended_accounts_can_read_until_the_suspension_ends() {
+        let store = store_with_account().await;
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Mine".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let config = config(3600);
+        let admin = issue_token(
+            &config.token_keys(),
+            AccountId(99),
+            "admin@email.com".to_string(),
+            true,
+        );
+        let routes = build_routes(config, store).await;
+        let token = login_from(&routes, "10.0.0.1:4000").await;
+
+        let until = Utc::now() + chrono::Duration::seconds(1);
+        let res = warp::test::request()
+            .method("POST")
+            .path("/admin/accounts/1/suspend")
+            .header("Authorization", format!("Bearer {}", admin))
+            .json(&json!({ "until": until }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        assert_eq!(request(&routes, "DELETE", "/questions/1", &token).await, 403);
+        assert_eq!(profile(&routes, &token).await.suspended_until, Some(until));
+
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        assert_eq!(request(&routes, "DELETE", "/questions/1", &token).await, 200);
+    }
+
+    #[tokio::test]
+    async fn suspended_accounts_cannot_change_their_own_data() {
+        let store = store_with_account().await;
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Someone's".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(2),
+            )
+            .await
+            .unwrap();
+        let routes = build_routes(config(3600), store.clone()).await;
+        let token = login_from(&routes, "10.0.0.1:4000").await;
+        let until = Utc::now() + chrono::Duration::hours(1);
+        store.suspend_account(&AccountId(1), Some(until)).await.unwrap();
+
+        for (method, path) in [
+            ("POST", "/questions/1/bookmark"),
+            ("DELETE", "/questions/1/bookmark"),
+            ("PUT", "/accounts/me/preferences"),
+            ("POST", "/accounts/me/tokens"),
+            ("DELETE", "/accounts/me"),
+        ] {
+            assert_eq!(request(&routes, method, path, &token).await, 403, "{} {}", method, path);
+        }
+        assert_eq!(request(&routes, "GET", "/accounts/me/bookmarks", &token).await, 200);
+    }
+
+    #[tokio::test]
+    async fn accounts_are_not_suspended_in_read_only_mode() {
+        let store = store_with_account().await;
+        let config = Config {
+            read_only: true,
+            disabled_route_status: DisabledRouteStatus::Forbidden,
+            ..config(3600)
+        };
+        let admin = issue_token(
+            &config.token_keys(),
+            AccountId(99),
+            "admin@email.com".to_string(),
+            true,
+        );
+        let routes = build_routes(config, store.clone()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/admin/accounts/1/suspend")
+            .header("Authorization", format!("Bearer {}", admin))
+            .json(&json!({ "until": Utc::now() + chrono::Duration::hours(1) }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 403);
+        assert_eq!(store.get_suspension(&AccountId(1)).await.unwrap(), None);
+    }
+
+    async fn whoami(token: Option<&str>) -> warp::http::Response<warp::hyper::body::Bytes> {
+        let routes = build_routes(config(3600), MemoryStore::new()).await;
+        let mut request = warp::test::request().method("GET").path("/whoami");
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+        request.reply(&routes).await
+    }
+
+    #[tokio::test]
+    async fn whoami_describes_a_valid_token() {
+        let token = issue_token(
+            &config(3600).token_keys(),
+            AccountId(7),
+            "me@email.com".to_string(),
+            true,
+        );
+
+        let res = whoami(Some(&token)).await;
+
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            serde_json::from_slice::<WhoAmI>(res.body()).unwrap(),
+            WhoAmI {
+                account_id: AccountId(7),
+                is_admin: true,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn whoami_refuses_expired_and_missing_tokens() {
+        let expired = paseto::tokens::PasetoBuilder::new()
+            .set_encryption_key(b"RANDOM WORDS WINTER MACINTOSH PC")
+            .set_expiration(&(Utc::now() - chrono::Duration::minutes(10)))
+            .set_not_before(&(Utc::now() - chrono::Duration::days(1)))
+            .set_claim("account_id", json!(7))
+            .set_claim("is_admin", json!(false))
+            .set_claim("email", json!("me@email.com"))
+            .build()
+            .unwrap();
+
+        assert_eq!(whoami(Some(&expired)).await.status(), 401);
+        assert_eq!(whoami(None).await.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn export_bundles_the_accounts_content_without_secrets() {
+        let store = store_with_account().await;
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Mine".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        for (content, account_id) in [("My answer", 1), ("Someone else's", 2)] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(1),
+                        parent_answer_id: None,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(account_id),
+                )
+                .await
+                .unwrap();
+        }
+        let routes = build_routes(config(3600), store).await;
+        let token = login_from(&routes, "10.0.0.1:4000").await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/accounts/me/export")
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let export: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(export["profile"]["email"], "me@email.com");
+        assert_eq!(export["questions"][0]["title"], "Mine");
+        assert_eq!(export["answers"].as_array().unwrap().len(), 1);
+        assert_eq!(export["answers"][0]["content"], "My answer");
+        let body = String::from_utf8_lossy(res.body());
+        assert!(!body.contains("password"));
+        assert!(!body.contains("$argon2"));
+    }
+
+    #[tokio::test]
+    async fn export_includes_votes_and_every_bookmark() {
+        let store = store_with_account().await;
+        for title in ["first", "second", "third"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(2),
+                )
+                .await
+                .unwrap();
+        }
+        for id in [1, 2, 3] {
+            store.add_bookmark(id, AccountId(1)).await.unwrap();
+        }
+        store.cast_vote(1, AccountId(1), Vote::Up).await.unwrap();
+        store.cast_vote(2, AccountId(1), Vote::Down).await.unwrap();
+        store.cast_vote(3, AccountId(2), Vote::Up).await.unwrap();
+        // Fewer rows per query than there are bookmarks
+        let config = Config {
+            max_rows: 2,
+            ..config(3600)
+        };
+        let routes = build_routes(config, store).await;
+        let token = login_from(&routes, "10.0.0.1:4000").await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/accounts/me/export")
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let export: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(export["votes"], json!({ "1": "up", "2": "down" }));
+        assert_eq!(export["bookmarks"].as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn preferences_default_until_set() {
+        let routes = build_routes(config(3600), store_with_account().await).await;
+        let token = login_from(&routes, "10.0.0.1:4000").await;
+        assert_eq!(
+            profile(&routes, &token).await.preferences,
+            NotificationPreferences::default()
+        );
+
+        let res = warp::test::request()
+            .method("PUT")
+            .path("/accounts/me/preferences")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({ "notify_on_answer": false }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        assert_eq!(
+            profile(&routes, &token).await.preferences,
+            NotificationPreferences {
+                notify_on_answer: false,
+                notify_on_reply: true,
+            }
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::Reply;
+
+use crate::config::Config;
+use crate::store::Store;
+use crate::types::pagination::{Pagination, extract_pagination};
+
+/// `GET /questions/:id/activity`: the question's creation, latest edit,
+/// answers and votes, oldest first and paged like `/questions`
+pub async fn get_question_activity<S: Store>(
+    question_id: i32,
+    params: HashMap<String, String>,
+    store: S,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut pagination = Pagination::default();
+
+    if params.contains_key("limit") || params.contains_key("offset") {
+        pagination = extract_pagination(params)?;
+    }
+    let pagination = pagination.clamp(config.max_page_size);
+
+    // A missing question is a 404 rather than an empty log
+    store.get_question(question_id).await?;
+    let activity = store
+        .get_question_activity(question_id, pagination.limit, pagination.offset)
+        .await?;
+
+    let mut response = warp::reply::json(&activity).into_response();
+    pagination.add_headers(response.headers_mut());
+    Ok(response)
+}
+
+#[cfg(test)]
+mod activity_tests {
+    use crate::build_routes;
+    use crate::config::Config;
+    use crate::store::{MemoryStore, Store};
+    use crate::types::account::AccountId;
+    use crate::types::activity::{Activity, ActivityKind};
+    use crate::types::answer::{AnswerId, NewAnswer};
+    use crate::types::question::{NewQuestion, QuestionId};
+    use crate::types::vote::Vote;
+
+    #[tokio::test]
+    async fn activity_is_merged_in_order() {
+        let store = MemoryStore::new();
+        let question = store
+            .add_question(
+                NewQuestion {
+                    title: "Busy question".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        store.cast_vote(1, AccountId(2), Vote::Up).await.unwrap();
+        store
+            .add_answer(
+                NewAnswer {
+                    content: "An answer".to_string(),
+                    question_id: QuestionId(1),
+                    parent_answer_id: None,
+                    client_id: None,
+                    attachments: Vec::new(),
+                },
+                AccountId(3),
+            )
+            .await
+            .unwrap();
+        store.update_question(question, 1, AccountId(1)).await.unwrap();
+        // Changing a vote isn't new activity
+        store.cast_vote(1, AccountId(2), Vote::Down).await.unwrap();
+        let routes = build_routes(Config::default(), store).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/1/activity")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        let activity: Vec<Activity> = serde_json::from_slice(res.body()).unwrap();
+        let kinds: Vec<ActivityKind> = activity.iter().map(|event| event.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ActivityKind::Created,
+                ActivityKind::Voted,
+                ActivityKind::Answered,
+                ActivityKind::Edited,
+            ]
+        );
+        assert!(activity.windows(2).all(|pair| pair[0].at <= pair[1].at));
+        assert_eq!(activity[2].answer_id, Some(AnswerId(1)));
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/1/activity?limit=2&offset=2")
+            .reply(&routes)
+            .await;
+        let page: Vec<Activity> = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(page, activity[2..].to_vec());
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/2/activity")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 404);
+    }
+}
@@ -1,27 +1,705 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use uuid::Uuid;
 use warp::http::StatusCode;
 
-use crate::profanity::check_profanity;
+use crate::config::Config;
+use crate::profanity::{check_profanity, record_censorship};
 use crate::store::Store;
 use crate::types::account::Session;
-use crate::types::answer::NewAnswer;
-pub async fn add_answer(
+use crate::types::answer::{MoveAnswer, NewAnswer, nest_answers, validate_attachments};
+pub async fn add_answer<S: Store>(
     session: Session,
-    store: Store,
+    store: S,
+    config: Arc<Config>,
     new_answer: NewAnswer,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let account_id = session.account_id;
-    let content = match check_profanity(new_answer.content).await {
+
+    // Stored in canonical form so differently formatted retries still match
+    let client_id = match &new_answer.client_id {
+        Some(client_id) => Some(
+            Uuid::parse_str(client_id)
+                .map_err(|_| {
+                    handle_errors::Error::InvalidParameter(format!(
+                        "client_id must be a UUID, got `{}`",
+                        client_id
+                    ))
+                })?
+                .to_string(),
+        ),
+        None => None,
+    };
+
+    validate_attachments(&new_answer.attachments, config.max_answer_attachments)?;
+
+    // A reply has to stay within the question its parent belongs to
+    if let Some(parent) = &new_answer.parent_answer_id {
+        let in_question = match store.get_answer(parent.0).await {
+            Ok(answer) => answer.question_id == new_answer.question_id,
+            Err(handle_errors::Error::AnswerNotFound) => false,
+            Err(e) => return Err(warp::reject::custom(e)),
+        };
+        if !in_question {
+            return Err(warp::reject::custom(
+                handle_errors::Error::InvalidParameter(format!(
+                    "answer {} is not part of question {}",
+                    parent.0, new_answer.question_id.0
+                )),
+            ));
+        }
+    }
+
+    let allow_list = config.profanity_allow_list.clone();
+    let api = config.profanity_api();
+    let content = match check_profanity(api, new_answer.content.clone(), allow_list).await {
         Ok(res) => res,
         Err(e) => return Err(warp::reject::custom(e)),
     };
+    record_censorship(
+        &store,
+        config.censorship_audit,
+        &account_id,
+        "answer",
+        &new_answer.content,
+        &content,
+    )
+    .await?;
 
     let answer = NewAnswer {
         content,
         question_id: new_answer.question_id,
+        parent_answer_id: new_answer.parent_answer_id,
+        client_id,
+        attachments: new_answer.attachments,
     };
 
     match store.add_answer(answer, account_id).await {
-        Ok(_) => Ok(warp::reply::with_status("Answer added", StatusCode::OK)),
+        Ok(_) => Ok(warp::reply::with_status("Answer added", StatusCode::CREATED)),
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
+
+/// `GET /answers/:id`: a single answer, for linking straight to it
+pub async fn get_answer<S: Store>(
+    answer_id: i32,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_answer(answer_id).await {
+        Ok(answer) => Ok(warp::reply::json(&answer)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Answers to a question, either as a flat list or, with `?nested=true`,
+/// as reply trees
+pub async fn get_answers<S: Store>(
+    question_id: i32,
+    params: HashMap<String, String>,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let nested = match params.get("nested") {
+        Some(nested) => nested.parse::<bool>().map_err(|_| {
+            handle_errors::Error::InvalidParameter(format!(
+                "nested must be true or false, got `{}`",
+                nested
+            ))
+        })?,
+        None => false,
+    };
+
+    match store.get_answers(question_id).await {
+        Ok(answers) if nested => Ok(warp::reply::json(&nest_answers(answers))),
+        Ok(answers) => Ok(warp::reply::json(&answers)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Moves a misplaced answer, with its replies, to another question. For
+/// admins only.
+pub async fn move_answer<S: Store>(
+    answer_id: i32,
+    _session: Session,
+    store: S,
+    target: MoveAnswer,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.move_answer(answer_id, target.question_id.0).await {
+        Ok(answer) => Ok(warp::reply::json(&answer)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+#[cfg(test)]
+mod answer_tests {
+    use serde_json::Value;
+
+    use crate::build_routes;
+    use crate::config::Config;
+    use crate::routes::authentication::issue_token;
+    use crate::store::{MemoryStore, Store};
+    use crate::test_utils::censoring_api;
+    use crate::types::account::AccountId;
+    use crate::types::answer::{AnswerId, NewAnswer};
+    use crate::types::question::{NewQuestion, QuestionId};
+
+    #[tokio::test]
+    async fn oversized_form_body_is_rejected_with_413() {
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            max_body_bytes: 64,
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "test@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/answers")
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(format!("question_id=1&content={}", "a".repeat(100)))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 413);
+        assert_eq!(res.body(), "Request body too large");
+    }
+
+    #[tokio::test]
+    async fn answers_only_take_the_allowed_content_types() {
+        unsafe {
+            std::env::set_var("BAD_WORDS_API_KEY", "YES");
+        }
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            profanity_api_url: censoring_api(),
+            answer_content_types: vec!["application/json".to_string()],
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "test@email.com".to_string(),
+            false,
+        );
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Formats".to_string(),
+                    content: "Which bodies are taken?".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let routes = build_routes(config, store.clone()).await;
+        let post = |content_type: &'static str, body: &'static str| {
+            warp::test::request()
+                .method("POST")
+                .path("/answers")
+                .header("Authorization", format!("Bearer {}", token))
+                .header("Content-Type", content_type)
+                .body(body)
+                .reply(&routes)
+        };
+
+        let res = post(
+            "application/json; charset=utf-8",
+            r#"{"question_id": 1, "content": "Only JSON"}"#,
+        )
+        .await;
+        assert_eq!(res.status(), 201);
+
+        let form = "application/x-www-form-urlencoded";
+        let res = post(form, "question_id=1&content=Not+a+form").await;
+        assert_eq!(res.status(), 415);
+        assert_eq!(res.body(), "Unsupported content type `application/x-www-form-urlencoded`");
+        assert_eq!(store.get_answers(1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn replies_are_checked_against_their_parents_question() {
+        unsafe {
+            std::env::set_var("BAD_WORDS_API_KEY", "YES");
+        }
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            profanity_api_url: censoring_api(),
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "test@email.com".to_string(),
+            false,
+        );
+        // Fewer rows than the question has answers, so a check against
+        // `get_answers` wouldn't see the last one
+        let store = MemoryStore::new().with_max_rows(2);
+        for title in ["Threads", "Elsewhere"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Can answers be replied to?".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        for (content, question_id) in [("One", 1), ("Two", 1), ("Three", 1), ("Four", 2)] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(question_id),
+                        parent_answer_id: None,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(2),
+                )
+                .await
+                .unwrap();
+        }
+        let routes = build_routes(config, store).await;
+        let reply = |parent: i32| {
+            warp::test::request()
+                .method("POST")
+                .path("/answers")
+                .header("Authorization", format!("Bearer {}", token))
+                .json(&serde_json::json!({
+                    "question_id": 1,
+                    "content": "A reply",
+                    "parent_answer_id": parent,
+                }))
+                .reply(&routes)
+        };
+
+        assert_eq!(reply(3).await.status(), 201);
+
+        for parent in [4, 99] {
+            let res = reply(parent).await;
+            assert_eq!(res.status(), 400);
+            let message = format!("answer {} is not part of question 1", parent);
+            assert!(String::from_utf8_lossy(res.body()).contains(&message));
+        }
+    }
+
+    #[tokio::test]
+    async fn all_answers_are_listed_past_the_row_cap() {
+        let store = MemoryStore::new().with_max_rows(2);
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Popular".to_string(),
+                    content: "Lots of answers here".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        for content in ["One", "Two", "Three"] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(1),
+                        parent_answer_id: None,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(2),
+                )
+                .await
+                .unwrap();
+        }
+        let routes = build_routes(Config::default(), store).await;
+
+        for path in ["/questions/1/answers", "/questions/1?include=answers"] {
+            let res = warp::test::request().path(path).reply(&routes).await;
+            assert_eq!(res.status(), 200, "{}", path);
+            let body: Value = serde_json::from_slice(res.body()).unwrap();
+            let answers = body.get("answers").unwrap_or(&body);
+            assert_eq!(answers.as_array().unwrap().len(), 3, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_are_fetched_by_id() {
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Deep links".to_string(),
+                    content: "Can answers be linked to?".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        store
+            .add_answer(
+                NewAnswer {
+                    content: "By their id".to_string(),
+                    question_id: QuestionId(1),
+                    parent_answer_id: None,
+                    client_id: None,
+                    attachments: Vec::new(),
+                },
+                AccountId(2),
+            )
+            .await
+            .unwrap();
+        let routes = build_routes(Config::default(), store).await;
+        let get = |path: &'static str| warp::test::request().method("GET").path(path);
+
+        let res = get("/answers/1").reply(&routes).await;
+        assert_eq!(res.status(), 200);
+        let answer: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(answer["content"], "By their id");
+        assert_eq!(answer["question_id"], 1);
+
+        assert_eq!(get("/answers/2").reply(&routes).await.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn retried_answer_with_same_client_id_is_stored_once() {
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Retries".to_string(),
+                    content: "What if the connection drops?".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+
+        let answer = NewAnswer {
+            content: "Post it again".to_string(),
+            question_id: QuestionId(1),
+            parent_answer_id: None,
+            client_id: Some("6f1c2b3a-7d4e-4f50-9a61-0b2c3d4e5f60".to_string()),
+            attachments: Vec::new(),
+        };
+        let first = store.add_answer(answer.clone(), AccountId(2)).await.unwrap();
+        let retry = store.add_answer(answer, AccountId(2)).await.unwrap();
+
+        assert_eq!(first.id, retry.id);
+        assert_eq!(store.get_answers(1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn client_ids_only_dedupe_within_one_account() {
+        let store = MemoryStore::new();
+        for title in ["Retries", "Other retries"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "What if the connection drops?".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        let answer = |question_id, content: &str| NewAnswer {
+            content: content.to_string(),
+            question_id: QuestionId(question_id),
+            parent_answer_id: None,
+            client_id: Some("6f1c2b3a-7d4e-4f50-9a61-0b2c3d4e5f60".to_string()),
+            attachments: Vec::new(),
+        };
+        let mine = store.add_answer(answer(1, "Mine"), AccountId(2)).await.unwrap();
+        let theirs = store.add_answer(answer(2, "Theirs"), AccountId(3)).await.unwrap();
+
+        assert_ne!(mine.id, theirs.id);
+        assert_eq!(theirs.content, "Theirs");
+        assert_eq!(theirs.question_id, QuestionId(2));
+        assert_eq!(store.get_answers(2).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn client_ids_past_their_ttl_no_longer_dedupe() {
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Retries".to_string(),
+                    content: "What if the connection drops?".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let answer = |content: &str, client_id: &str| NewAnswer {
+            content: content.to_string(),
+            question_id: QuestionId(1),
+            parent_answer_id: None,
+            client_id: Some(client_id.to_string()),
+            attachments: Vec::new(),
+        };
+        let old_id = "6f1c2b3a-7d4e-4f50-9a61-0b2c3d4e5f60";
+        let fresh_id = "0d9e8f7a-6b5c-4d3e-8f21-a0b1c2d3e4f5";
+        let old = store.add_answer(answer("Old", old_id), AccountId(2)).await.unwrap();
+
+        let cutoff = chrono::Utc::now();
+        let fresh = store.add_answer(answer("Fresh", fresh_id), AccountId(2)).await.unwrap();
+        assert_eq!(store.expire_client_ids(cutoff).await.unwrap(), 1);
+
+        let reused = store.add_answer(answer("Reused", old_id), AccountId(2)).await.unwrap();
+        assert_ne!(reused.id, old.id);
+        let retry = store.add_answer(answer("Fresh", fresh_id), AccountId(2)).await.unwrap();
+        assert_eq!(retry.id, fresh.id);
+        assert_eq!(store.get_answers(1).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn replies_are_returned_nested() {
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Threads".to_string(),
+                    content: "Can answers be replied to?".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        for (content, parent) in [("Yes", None), ("Are you sure?", Some(AnswerId(1)))] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(1),
+                        parent_answer_id: parent,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(2),
+                )
+                .await
+                .unwrap();
+        }
+
+        let routes = build_routes(Config::default(), store).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/1/answers?nested=true")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let threads: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(threads.as_array().unwrap().len(), 1);
+        assert_eq!(threads[0]["content"], "Yes");
+        assert_eq!(threads[0]["replies"][0]["content"], "Are you sure?");
+        assert_eq!(threads[0]["replies"][0]["parent_answer_id"], 1);
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/1/answers")
+            .reply(&routes)
+            .await;
+        let flat: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(flat.as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn invalid_attachment_url_is_rejected_with_400() {
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "test@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/answers")
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("question_id=1&content=Look+here&attachments=https%3A%2F%2Fok.com+javascript%3Aalert(1)")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn attachments_are_returned_with_the_answer() {
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Attachments".to_string(),
+                    content: "Where is the diagram?".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let attachments = vec![
+            "https://example.com/diagram.png".to_string(),
+            "https://example.com/notes.txt".to_string(),
+        ];
+        store
+            .add_answer(
+                NewAnswer {
+                    content: "Right here".to_string(),
+                    question_id: QuestionId(1),
+                    parent_answer_id: None,
+                    client_id: None,
+                    attachments: attachments.clone(),
+                },
+                AccountId(2),
+            )
+            .await
+            .unwrap();
+
+        let routes = build_routes(Config::default(), store).await;
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/1/answers")
+            .reply(&routes)
+            .await;
+        let answers: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(answers[0]["attachments"], serde_json::json!(attachments));
+    }
+
+    /// Two questions, the first with an answer and a reply to it
+    async fn store_with_thread() -> MemoryStore {
+        let store = MemoryStore::new();
+        for title in ["Misplaced", "Right place"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        for (content, parent) in [("Belongs elsewhere", None), ("Agreed", Some(AnswerId(1)))] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(1),
+                        parent_answer_id: parent,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(2),
+                )
+                .await
+                .unwrap();
+        }
+        store
+    }
+
+    fn admin_config() -> (Config, String) {
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(3),
+            "admin@email.com".to_string(),
+            true,
+        );
+        (config, token)
+    }
+
+    #[tokio::test]
+    async fn admins_move_answers_with_their_replies() {
+        let store = store_with_thread().await;
+        let (config, token) = admin_config();
+        let routes = build_routes(config, store.clone()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/answers/1/move")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&serde_json::json!({ "question_id": 2 }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        let moved: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(moved["question_id"], 2);
+
+        assert!(store.get_answers(1).await.unwrap().is_empty());
+        let answers = store.get_answers(2).await.unwrap();
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[1].parent_answer_id, Some(AnswerId(1)));
+    }
+
+    #[tokio::test]
+    async fn moving_to_a_missing_question_is_404() {
+        let store = store_with_thread().await;
+        let (config, token) = admin_config();
+        let routes = build_routes(config, store.clone()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/answers/1/move")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&serde_json::json!({ "question_id": 99 }))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 404);
+        assert_eq!(store.get_answers(1).await.unwrap().len(), 2);
+    }
+}
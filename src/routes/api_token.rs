@@ -0,0 +1,186 @@
+use handle_errors::{Error, FieldError};
+use uuid::Uuid;
+
+use crate::routes::authentication::{API_TOKEN_PREFIX, hash_api_token};
+use crate::store::Store;
+use crate::types::account::Session;
+use crate::types::api_token::{MintedApiToken, NewApiToken};
+
+/// `POST /accounts/me/tokens`: mints a named API token for the account
+pub async fn add_api_token<S: Store>(
+    session: Session,
+    store: S,
+    new_token: NewApiToken,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let name = new_token.name.trim().to_string();
+    if name.is_empty() {
+        return Err(warp::reject::custom(Error::Validation(vec![FieldError::new(
+            "name",
+            "must not be empty",
+        )])));
+    }
+
+    let token = format!("{}{}", API_TOKEN_PREFIX, Uuid::new_v4().to_simple());
+
+    match store
+        .add_api_token(&session.account_id, name, hash_api_token(&token))
+        .await
+    {
+        Ok(api_token) => Ok(warp::reply::json(&MintedApiToken { api_token, token })),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+pub async fn get_api_tokens<S: Store>(
+    session: Session,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_api_tokens(&session.account_id).await {
+        Ok(api_tokens) => Ok(warp::reply::json(&api_tokens)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+pub async fn delete_api_token<S: Store>(
+    id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.delete_api_token(id, &session.account_id).await {
+        Ok(_) => Ok(warp::reply::json(&format!("API token {} revoked", id))),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+#[cfg(test)]
+mod api_token_tests {
+    use serde_json::{Value, json};
+
+    use crate::build_routes;
+    use crate::config::Config;
+    use crate::routes::authentication::{hash_api_token, issue_token};
+    use crate::store::{MemoryStore, Store};
+    use crate::types::account::Account;
+    use crate::types::api_token::{ApiToken, MintedApiToken};
+
+    #[tokio::test]
+    async fn api_tokens_can_be_minted_used_and_revoked() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "bot@email.com".to_string(),
+                password: "hash".to_string(),
+                is_admin: false,
+                verified: true,
+            })
+            .await
+            .unwrap();
+        let account = store.get_account("bot@email.com".to_string()).await.unwrap();
+
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let login = issue_token(
+            &config.token_keys(),
+            account.id.unwrap(),
+            account.email,
+            false,
+        );
+        let routes = build_routes(config, store).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/accounts/me/tokens")
+            .header("Authorization", format!("Bearer {}", login))
+            .json(&json!({ "name": "ci" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        let minted: MintedApiToken = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(minted.api_token.name, "ci");
+
+        // The API token works wherever a login token does
+        let res = warp::test::request()
+            .method("GET")
+            .path("/accounts/me")
+            .header("Authorization", format!("Bearer {}", minted.token))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/accounts/me/tokens")
+            .header("Authorization", format!("Bearer {}", login))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        let listed: Vec<ApiToken> = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, minted.api_token.id);
+        let raw: Value = serde_json::from_slice(res.body()).unwrap();
+        assert!(raw[0].get("token").is_none());
+
+        let res = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/accounts/me/tokens/{}", minted.api_token.id.0))
+            .header("Authorization", format!("Bearer {}", login))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/accounts/me")
+            .header("Authorization", format!("Bearer {}", minted.token))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn api_tokens_cannot_manage_api_tokens() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "bot@email.com".to_string(),
+                password: "hash".to_string(),
+                is_admin: false,
+                verified: true,
+            })
+            .await
+            .unwrap();
+        let account_id = store
+            .get_account("bot@email.com".to_string())
+            .await
+            .unwrap()
+            .id
+            .unwrap();
+        let api_token = store
+            .add_api_token(&account_id, "ci".to_string(), hash_api_token("api_leaked"))
+            .await
+            .unwrap();
+        let routes = build_routes(Config::default(), store.clone()).await;
+
+        for (method, path) in [
+            ("POST", "/accounts/me/tokens".to_string()),
+            ("GET", "/accounts/me/tokens".to_string()),
+            ("DELETE", format!("/accounts/me/tokens/{}", api_token.id.0)),
+        ] {
+            let res = warp::test::request()
+                .method(method)
+                .path(&path)
+                .header("Authorization", "Bearer api_leaked")
+                .json(&json!({ "name": "escalated" }))
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), 401, "{} {}", method, path);
+        }
+
+        let tokens = store.get_api_tokens(&account_id).await.unwrap();
+        assert_eq!(tokens.len(), 1);
+    }
+}
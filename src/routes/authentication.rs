@@ -1,60 +1,280 @@
-use argon2::{self, Config};
 use chrono::Utc;
 use rand::Rng;
-use std::env;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
 use warp::Filter;
-use warp::http::StatusCode;
 
+use crate::config::{Config, PasswordAlgorithm, Profile};
 use crate::store::Store;
-use crate::types::account::{Account, AccountId, Session};
+use crate::types::account::{
+    Account, AccountId, PasswordResetConfirm, PasswordResetRequest, Session, check_account_age,
+    validate_account, validate_password,
+};
 
-pub async fn register(store: Store, account: Account) -> Result<impl warp::Reply, warp::Rejection> {
-    let hashed_password = hash_password(account.password.as_bytes());
+pub async fn register<S: Store>(
+    store: S,
+    config: Arc<Config>,
+    account: Account,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    validate_account(&account)?;
+
+    if is_blocked_domain(&account.email, &config.blocked_email_domains) {
+        return Err(warp::reject::custom(
+            handle_errors::Error::BlockedEmailDomain,
+        ));
+    }
+
+    let hashed_password = hash_password(account.password.as_bytes(), config.password_algorithm)?;
 
     let account = Account {
         id: account.id,
         email: account.email,
         password: hashed_password,
+        is_admin: false,
+        verified: false,
+    };
+    let email = account.email.clone();
+
+    if let Err(e) = store.add_account_capped(account, config.max_accounts).await {
+        return Err(warp::reject::custom(e));
+    }
+
+    let account_id = match store.get_account(email.clone()).await {
+        Ok(account) => account.id.expect("id not found"),
+        Err(e) => return Err(warp::reject::custom(e)),
     };
+    let token = Uuid::new_v4().to_simple().to_string();
+    let ttl = Duration::from_secs(config.verification_token_ttl);
+    store
+        .add_verification_token(&account_id, &hash_api_token(&token), ttl)
+        .await?;
+
+    // There is no mailer yet. The link lets anyone verify the account, so
+    // it is only logged while developing.
+    if config.profile == Profile::Dev {
+        tracing::debug!(email = %email, "Verify the account at /verify?token={}", token);
+    }
+
+    Ok(warp::reply::json(&"Account added".to_string()))
+}
+
+/// `GET /verify?token=...`, following the link sent after registration
+pub async fn verify_email<S: Store>(
+    params: HashMap<String, String>,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let token = params
+        .get("token")
+        .ok_or(handle_errors::Error::MissingParameters)?;
+
+    match store.verify_account(&hash_api_token(token)).await {
+        Ok(_) => Ok(warp::reply::json(&"Email verified".to_string())),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
 
-    match store.add_account(account).await {
-        Ok(_) => Ok(warp::reply::json(&"Account added".to_string())),
+/// `POST /password-reset/request`. Answers the same whether or not the
+/// email belongs to an account, so it can't be used to probe for them.
+pub async fn request_password_reset<S: Store>(
+    store: S,
+    config: Arc<Config>,
+    request: PasswordResetRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if let Ok(account) = store.get_account(request.email.clone()).await {
+        let account_id = account.id.expect("id not found");
+        let token = Uuid::new_v4().to_simple().to_string();
+        let ttl = Duration::from_secs(config.password_reset_token_ttl);
+        store
+            .add_password_reset_token(&account_id, &hash_api_token(&token), ttl)
+            .await?;
+
+        // There is no mailer yet. The token takes over the account, so it is
+        // only logged while developing.
+        if config.profile == Profile::Dev {
+            tracing::debug!(email = %request.email, "Reset the password with token {}", token);
+        }
+    }
+
+    Ok(warp::reply::json(
+        &"If the account exists, a password reset token has been sent".to_string(),
+    ))
+}
+
+/// `POST /password-reset/confirm`, setting a new password with a token
+/// from `request_password_reset`. Each token works once.
+pub async fn confirm_password_reset<S: Store>(
+    store: S,
+    config: Arc<Config>,
+    confirm: PasswordResetConfirm,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    validate_password(&confirm.password)?;
+    let password_hash = hash_password(confirm.password.as_bytes(), config.password_algorithm)?;
+
+    match store
+        .reset_password(&hash_api_token(&confirm.token), &password_hash)
+        .await
+    {
+        Ok(_) => Ok(warp::reply::json(&"Password updated".to_string())),
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
 
-pub fn hash_password(password: &[u8]) -> String {
-    let salt = rand::thread_rng().r#gen::<[u8; 32]>();
-    let config = Config::default();
-    argon2::hash_encoded(password, &salt, &config).unwrap()
+fn is_blocked_domain(email: &str, blocked_domains: &[String]) -> bool {
+    match email.rsplit_once('@') {
+        Some((_, domain)) => blocked_domains
+            .iter()
+            .any(|blocked| blocked.trim().eq_ignore_ascii_case(domain.trim())),
+        None => false,
+    }
+}
+
+/// Hash a password with `algorithm`. Both produce self-describing PHC or
+/// modular crypt strings, which `verify_password` tells apart.
+pub fn hash_password(
+    password: &[u8],
+    algorithm: PasswordAlgorithm,
+) -> Result<String, handle_errors::Error> {
+    match algorithm {
+        PasswordAlgorithm::Argon2 => {
+            let salt = rand::thread_rng().r#gen::<[u8; 32]>();
+            let config = argon2::Config::default();
+            argon2::hash_encoded(password, &salt, &config)
+                .map_err(handle_errors::Error::ArgonLibraryError)
+        }
+        PasswordAlgorithm::Bcrypt => bcrypt::hash(password, bcrypt::DEFAULT_COST)
+            .map_err(handle_errors::Error::BcryptLibraryError),
+    }
 }
 
-pub async fn login(store: Store, login: Account) -> Result<impl warp::Reply, warp::Rejection> {
-    match store.get_account(login.email).await {
-        Ok(account) => match verify_passwrd(&account.password, login.password.as_bytes()) {
+pub async fn login<S: Store>(
+    store: S,
+    keys: TokenKeys,
+    remote: Option<SocketAddr>,
+    login: Account,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_account(login.email.clone()).await {
+        Ok(account) => match verify_password(&account.password, login.password.as_bytes()) {
             Ok(verified) => {
                 if verified {
+                    let account_id = account.id.expect("id not found");
+                    // Only informational, a failure here mustn't block the login
+                    let ip = remote.map(|addr| addr.ip().to_string());
+                    if let Err(e) = store.record_login(&account_id, ip).await {
+                        tracing::warn!("Could not record login: {}", e);
+                    }
+
                     Ok(warp::reply::json(&issue_token(
-                        account.id.expect("id not found"),
+                        &keys,
+                        account_id,
+                        account.email,
+                        account.is_admin,
                     )))
                 } else {
+                    log_auth_failure("wrong_password", Some(&login.email), remote);
                     Err(warp::reject::custom(handle_errors::Error::WrongPassword))
                 }
             }
-            Err(e) => Err(warp::reject::custom(
-                handle_errors::Error::ArgonLibraryError(e),
-            )),
+            Err(e) => Err(warp::reject::custom(e)),
         },
-        Err(e) => Err(warp::reject::custom(e)),
+        Err(e) => {
+            if let handle_errors::Error::DatabaseQueryError(sqlx::Error::RowNotFound) = e {
+                log_auth_failure("unknown_email", Some(&login.email), remote);
+            }
+            Err(warp::reject::custom(e))
+        }
     }
 }
 
-fn verify_passwrd(hash: &str, password: &[u8]) -> Result<bool, argon2::Error> {
-    argon2::verify_encoded(hash, password)
+/// Failed logins and rejected tokens go to their own `auth` target at WARN,
+/// with a machine-readable `reason`, so they can be alerted on apart from
+/// the request log
+fn log_auth_failure(reason: &str, email: Option<&str>, remote: Option<SocketAddr>) {
+    let ip = remote.map(|addr| addr.ip().to_string()).unwrap_or_default();
+    tracing::warn!(
+        target: "auth",
+        reason,
+        email = email.unwrap_or_default(),
+        ip = %ip,
+        "Authentication failed"
+    );
 }
 
-fn issue_token(account_id: AccountId) -> String {
-    let key = env::var("PASETO_KEY").unwrap();
+/// `POST /token/rotate`: swap a still-valid login token for a fresh one, so
+/// an active session keeps sliding forward without the password. Expired
+/// tokens and API tokens are turned away by `session_auth`. The account
+/// is re-read so a rotated token doesn't keep stale admin rights, and a
+/// token for an account that is gone can't be rotated.
+pub async fn rotate_token<S: Store>(
+    session: Session,
+    store: S,
+    keys: TokenKeys,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let account = match store.get_account_profile(&session.account_id).await {
+        Ok(account) => account,
+        Err(handle_errors::Error::DatabaseQueryError(sqlx::Error::RowNotFound)) => {
+            return Err(warp::reject::custom(handle_errors::Error::Unauthorized));
+        }
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    Ok(warp::reply::json(&issue_token(
+        &keys,
+        session.account_id,
+        account.email,
+        account.is_admin,
+    )))
+}
+
+/// Check `password` against a hash from `hash_password`, with whichever
+/// algorithm produced it
+pub fn verify_password(hash: &str, password: &[u8]) -> Result<bool, handle_errors::Error> {
+    if hash.starts_with("$argon2") {
+        argon2::verify_encoded(hash, password).map_err(handle_errors::Error::ArgonLibraryError)
+    } else {
+        bcrypt::verify(password, hash).map_err(handle_errors::Error::BcryptLibraryError)
+    }
+}
+
+/// Keys tokens are encrypted with. The first one signs new tokens, all of
+/// them are tried when verifying, so tokens issued under a retired key keep
+/// working while a rotation is rolled out.
+#[derive(Debug, Clone)]
+pub struct TokenKeys {
+    keys: Arc<Vec<String>>,
+    /// How far past `exp` (or before `nbf`) a token is still accepted, to
+    /// make up for clients with slightly-off clocks
+    leeway: chrono::Duration,
+}
+
+impl TokenKeys {
+    pub fn new(keys: Vec<String>) -> Self {
+        TokenKeys {
+            keys: Arc::new(keys),
+            leeway: chrono::Duration::zero(),
+        }
+    }
+
+    pub fn with_leeway(self, leeway: chrono::Duration) -> Self {
+        TokenKeys { leeway, ..self }
+    }
+
+    /// How many keys tokens are checked against
+    pub(crate) fn count(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+pub fn issue_token(
+    keys: &TokenKeys,
+    account_id: AccountId,
+    email: String,
+    is_admin: bool,
+) -> String {
+    let key = keys.keys.first().expect("No PASETO key configured");
 
     let current_data_time = Utc::now();
     let dt = current_data_time + chrono::Duration::days(1);
@@ -64,49 +284,948 @@ fn issue_token(account_id: AccountId) -> String {
         .set_expiration(&dt)
         .set_not_before(&Utc::now())
         .set_claim("account_id", serde_json::json!(account_id))
+        .set_claim("is_admin", serde_json::json!(is_admin))
+        .set_claim("email", serde_json::json!(email))
         .build()
         .expect("Failed to construct paseto token w/ builder!")
 }
 
-pub fn verify_token(token: String) -> Result<Session, handle_errors::Error> {
-    let key = env::var("PASETO_KEY").unwrap();
-    let token = paseto::tokens::validate_local_token(
-        &token,
-        None,
-        key.as_bytes(),
-        &paseto::tokens::TimeBackend::Chrono,
-    )
-    .map_err(|_| handle_errors::Error::CannotDecryptToken)?;
+/// Fails with the reason logged by `log_auth_failure`
+pub fn verify_token(keys: &TokenKeys, token: &str) -> Result<Session, &'static str> {
+    let claims = keys
+        .keys
+        .iter()
+        .find_map(|key| paseto::v2::local::decrypt_paseto(token, None, key.as_bytes()).ok())
+        .ok_or("bad_token")?;
+    let session = serde_json::from_str::<Session>(&claims).map_err(|_| "bad_token")?;
+
+    // Checked here instead of by paseto's `validate_local_token`, which
+    // allows no leeway
+    let now = Utc::now();
+    if session.exp + keys.leeway < now {
+        return Err("expired_token");
+    }
+    if session.nbf - keys.leeway > now {
+        return Err("token_not_yet_valid");
+    }
+
+    Ok(session)
+}
+
+/// Pulls the token out of an `Authorization` header. Besides the standard
+/// `Bearer <token>` form a bare token is still accepted, as that is what
+/// clients used to send.
+fn bearer_token(header: &str) -> Result<&str, handle_errors::Error> {
+    match header.trim().split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => {
+            match token.trim() {
+                "" => Err(handle_errors::Error::Unauthorized),
+                token => Ok(token),
+            }
+        }
+        Some(_) => Err(handle_errors::Error::Unauthorized),
+        None => Ok(header.trim()),
+    }
+}
+
+/// API tokens are told apart from PASETO login tokens by this prefix
+pub const API_TOKEN_PREFIX: &str = "api_";
+
+/// API tokens are stored as their SHA-256 digest only
+pub fn hash_api_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Fails with the reason for `log_auth_failure`
+async fn authenticate<S: Store>(
+    keys: &TokenKeys,
+    store: &S,
+    header: &str,
+) -> Result<Session, &'static str> {
+    let token = bearer_token(header).map_err(|_| "malformed_header")?;
+    if !token.starts_with(API_TOKEN_PREFIX) {
+        return verify_token(keys, token);
+    }
+
+    let account = store
+        .get_api_token_account(&hash_api_token(token))
+        .await
+        .map_err(|_| "api_token_lookup_failed")?;
+    match account {
+        // API tokens don't expire, exp and nbf just carry the request time
+        Some(account) => Ok(Session {
+            exp: Utc::now(),
+            account_id: account.id.expect("id not found"),
+            is_admin: account.is_admin,
+            email: account.email,
+            nbf: Utc::now(),
+        }),
+        None => Err("unknown_api_token"),
+    }
+}
+
+/// Accepts both PASETO login tokens and API tokens minted through
+/// `/accounts/me/tokens`
+pub fn auth<S: Store>(
+    keys: TokenKeys,
+    store: S,
+) -> impl Filter<Extract = (Session,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("Authorization")
+        .and(warp::addr::remote())
+        .and_then(move |header: Option<String>, remote: Option<SocketAddr>| {
+            let keys = keys.clone();
+            let store = store.clone();
+            async move {
+                let Some(header) = header else {
+                    log_auth_failure("missing_header", None, remote);
+                    return Err(warp::reject::custom(handle_errors::Error::Unauthorized));
+                };
+                match authenticate(&keys, &store, &header).await {
+                    Ok(session) => Ok(session), // 只返回 Session
+                    Err(reason) => {
+                        log_auth_failure(reason, None, remote);
+                        Err(warp::reject::custom(handle_errors::Error::Unauthorized))
+                    }
+                }
+            }
+        })
+}
+
+/// Like `auth`, but only accepts PASETO login tokens. Routes that hand out
+/// credentials use it, so a leaked API token can't be traded for a login
+/// token.
+pub fn session_auth(
+    keys: TokenKeys,
+) -> impl Filter<Extract = (Session,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("Authorization")
+        .and(warp::addr::remote())
+        .and_then(move |header: Option<String>, remote: Option<SocketAddr>| {
+            let keys = keys.clone();
+            async move {
+                let Some(header) = header else {
+                    log_auth_failure("missing_header", None, remote);
+                    return Err(warp::reject::custom(handle_errors::Error::Unauthorized));
+                };
+                let session = match bearer_token(&header) {
+                    Ok(token) if token.starts_with(API_TOKEN_PREFIX) => Err("api_token_refused"),
+                    Ok(token) => verify_token(&keys, token),
+                    Err(_) => Err("malformed_header"),
+                };
+                session.map_err(|reason| {
+                    log_auth_failure(reason, None, remote);
+                    warp::reject::custom(handle_errors::Error::Unauthorized)
+                })
+            }
+        })
+}
+
+/// Like `auth`, but only lets admin accounts through
+pub fn admin<S: Store>(
+    keys: TokenKeys,
+    store: S,
+) -> impl Filter<Extract = (Session,), Error = warp::Rejection> + Clone {
+    auth(keys, store).and_then(|session: Session| async move {
+        if session.is_admin {
+            Ok(session)
+        } else {
+            Err(warp::reject::custom(handle_errors::Error::Unauthorized))
+        }
+    })
+}
+
+/// Wraps a filter handing out a `Session` so that suspended accounts are
+/// refused, for routes that write
+pub fn unsuspended<S: Store, F>(
+    session: F,
+    store: S,
+) -> impl Filter<Extract = (Session,), Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (Session,), Error = warp::Rejection> + Clone,
+{
+    session.and_then(move |session: Session| {
+        let store = store.clone();
+        async move {
+            let suspended_until = store.get_suspension(&session.account_id).await?;
+            if suspended_until.is_some_and(|until| until > Utc::now()) {
+                return Err(warp::reject::custom(
+                    handle_errors::Error::AccountSuspended,
+                ));
+            }
+            Ok(session)
+        }
+    })
+}
+
+/// Like `auth`, for routes that write: suspended accounts are refused
+pub fn write_auth<S: Store>(
+    keys: TokenKeys,
+    store: S,
+) -> impl Filter<Extract = (Session,), Error = warp::Rejection> + Clone {
+    unsuspended(auth(keys, store.clone()), store)
+}
 
-    serde_json::from_value::<Session>(token).map_err(|_| handle_errors::Error::CannotDecryptToken)
+/// Like `write_auth`, but when `required` accounts that haven't verified
+/// their email address yet are refused too
+pub fn verified_auth<S: Store>(
+    keys: TokenKeys,
+    store: S,
+    required: bool,
+) -> impl Filter<Extract = (Session,), Error = warp::Rejection> + Clone {
+    write_auth(keys, store.clone()).and_then(move |session: Session| {
+        let store = store.clone();
+        async move {
+            if !required {
+                return Ok(session);
+            }
+            match store.get_account(session.email.clone()).await {
+                Ok(account) if account.verified => Ok(session),
+                Ok(_) => Err(warp::reject::custom(
+                    handle_errors::Error::EmailNotVerified,
+                )),
+                Err(e) => Err(warp::reject::custom(e)),
+            }
+        }
+    })
 }
 
-pub fn auth() -> impl Filter<Extract = (Session,), Error = warp::Rejection> + Clone {
-    warp::header::<String>("Authorization").and_then(|token: String| async move {
-        match verify_token(token) {
-            Ok(session) => Ok(session), // 只返回 Session
-            Err(_) => Err(warp::reject::custom(handle_errors::Error::Unauthorized)),
+/// Like `verified_auth`, for routes that ask questions or post answers:
+/// accounts younger than `min_age_minutes` are refused too
+pub fn posting_auth<S: Store>(
+    keys: TokenKeys,
+    store: S,
+    required: bool,
+    min_age_minutes: u64,
+) -> impl Filter<Extract = (Session,), Error = warp::Rejection> + Clone {
+    verified_auth(keys, store.clone(), required).and_then(move |session: Session| {
+        let store = store.clone();
+        async move {
+            if min_age_minutes > 0 {
+                let created_at = store.get_account_created_at(&session.account_id).await?;
+                check_account_age(created_at, Utc::now(), min_age_minutes)?;
+            }
+            Ok::<_, warp::Rejection>(session)
         }
     })
 }
 
+/// Like `auth`, but lets requests without an `Authorization` header through
+/// with no session. A header carrying an invalid token is still rejected.
+pub fn optional_auth<S: Store>(
+    keys: TokenKeys,
+    store: S,
+) -> impl Filter<Extract = (Option<Session>,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("Authorization")
+        .and(warp::addr::remote())
+        .and_then(move |header: Option<String>, remote: Option<SocketAddr>| {
+            let keys = keys.clone();
+            let store = store.clone();
+            async move {
+                let Some(header) = header else {
+                    return Ok(None);
+                };
+                match authenticate(&keys, &store, &header).await {
+                    Ok(session) => Ok(Some(session)),
+                    Err(reason) => {
+                        log_auth_failure(reason, None, remote);
+                        Err(warp::reject::custom(handle_errors::Error::Unauthorized))
+                    }
+                }
+            }
+        })
+}
+
 #[cfg(test)]
 mod authentication_tests {
-    use super::{AccountId, auth, env, issue_token};
+    use super::{
+        AccountId, Session, TokenKeys, auth, hash_api_token, hash_password, is_blocked_domain,
+        issue_token, posting_auth, verify_password, verify_token,
+    };
+    use crate::build_routes;
+    use crate::config::{Config, PasswordAlgorithm, Profile};
+    use crate::store::{MemoryStore, Store};
+    use crate::test_utils::capture_logs;
+    use crate::types::account::Account;
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use warp::Filter;
 
     #[tokio::test]
     async fn post_questions_auth() {
-        unsafe {
-            env::set_var("PASETO_KEY", "RANDOM WORDS WINTER MACINTOSH PC");
-        }
+        let keys = TokenKeys::new(vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()]);
+        let token = issue_token(&keys, AccountId(3), "test@email.com".to_string(), false);
 
-        let token = issue_token(AccountId(3));
-
-        let filter = auth();
+        let filter = auth(keys, MemoryStore::new());
 
         let res = warp::test::request()
             .header("Authorization", token)
             .filter(&filter);
         assert_eq!(res.await.unwrap().account_id, AccountId(3));
     }
+
+    #[tokio::test]
+    async fn new_accounts_wait_before_posting() {
+        let keys = TokenKeys::new(vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()]);
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "new@email.com".to_string(),
+                password: "password".to_string(),
+                is_admin: false,
+                verified: true,
+            })
+            .await
+            .unwrap();
+        let token = issue_token(&keys, AccountId(1), "new@email.com".to_string(), false);
+        let post = |min_age_minutes| {
+            let filter = posting_auth(keys.clone(), store.clone(), false, min_age_minutes);
+            let token = token.clone();
+            async move {
+                warp::test::request()
+                    .header("Authorization", token)
+                    .filter(&filter)
+                    .await
+            }
+        };
+
+        let rejection = post(10).await.unwrap_err();
+        assert!(matches!(
+            rejection.find::<handle_errors::Error>(),
+            Some(handle_errors::Error::AccountTooNew)
+        ));
+        assert_eq!(post(0).await.unwrap().account_id, AccountId(1));
+    }
+
+    #[tokio::test]
+    async fn auth_populates_session_fields() {
+        let keys = TokenKeys::new(vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()]);
+        let token = issue_token(&keys, AccountId(7), "admin@email.com".to_string(), true);
+
+        // A protected handler only sees what the auth filter hands it
+        let filter = auth(keys, MemoryStore::new()).map(|session: Session| {
+            (session.account_id, session.email, session.is_admin)
+        });
+
+        let res = warp::test::request()
+            .header("Authorization", token)
+            .filter(&filter)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            res,
+            (AccountId(7), "admin@email.com".to_string(), true)
+        );
+    }
+
+    #[tokio::test]
+    async fn auth_accepts_bearer_scheme() {
+        let keys = TokenKeys::new(vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()]);
+        let token = issue_token(&keys, AccountId(3), "test@email.com".to_string(), false);
+        let filter = auth(keys, MemoryStore::new());
+
+        let session = warp::test::request()
+            .header("Authorization", format!("Bearer {}", token))
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(session.account_id, AccountId(3));
+
+        let session = warp::test::request()
+            .header("Authorization", format!("bearer  {}", token))
+            .filter(&filter)
+            .await
+            .unwrap();
+        assert_eq!(session.account_id, AccountId(3));
+    }
+
+    #[tokio::test]
+    async fn auth_rejects_missing_or_malformed_header_with_401() {
+        let keys = TokenKeys::new(vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()]);
+        let token = issue_token(&keys, AccountId(3), "test@email.com".to_string(), false);
+        let filter = auth(keys, MemoryStore::new())
+            .map(|_session: Session| "ok")
+            .recover(handle_errors::return_error);
+
+        let res = warp::test::request().reply(&filter).await;
+        assert_eq!(res.status(), 401);
+
+        for header in [
+            format!("Basic {}", token),
+            "Bearer".to_string(),
+            "Bearer ".to_string(),
+            "Bearer not-a-token".to_string(),
+        ] {
+            let res = warp::test::request()
+                .header("Authorization", header)
+                .reply(&filter)
+                .await;
+            assert_eq!(res.status(), 401);
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_logins_are_logged_with_the_reason() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "me@email.com".to_string(),
+                password: hash_password(b"password", PasswordAlgorithm::Argon2).unwrap(),
+                is_admin: false,
+                verified: true,
+            })
+            .await
+            .unwrap();
+        let routes = build_routes(Config::default(), store).await;
+
+        let (logs, _guard) = capture_logs();
+        let res = warp::test::request()
+            .method("POST")
+            .path("/login")
+            .remote_addr("10.0.0.1:4000".parse::<SocketAddr>().unwrap())
+            .json(&json!({ "email": "me@email.com", "password": "not my password" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 401);
+
+        let line = logs
+            .contents()
+            .lines()
+            .find(|line| line.contains("Authentication failed"))
+            .map(str::to_string)
+            .expect("no authentication failure logged");
+        assert!(line.contains("WARN"), "{}", line);
+        assert!(line.contains("reason=\"wrong_password\""), "{}", line);
+        assert!(line.contains("email=\"me@email.com\""), "{}", line);
+        assert!(line.contains("ip=10.0.0.1"), "{}", line);
+    }
+
+    #[tokio::test]
+    async fn valid_tokens_rotate_into_fresh_ones() {
+        let store = MemoryStore::new();
+        let account_id = unverified_account(&store).await;
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let keys = config.token_keys();
+        let now = chrono::Utc::now();
+        let token = paseto::tokens::PasetoBuilder::new()
+            .set_encryption_key(b"RANDOM WORDS WINTER MACINTOSH PC")
+            .set_expiration(&(now + chrono::Duration::minutes(5)))
+            .set_not_before(&(now - chrono::Duration::hours(1)))
+            .set_claim("account_id", json!(account_id))
+            .set_claim("is_admin", json!(false))
+            .set_claim("email", json!("new@email.com"))
+            .build()
+            .unwrap();
+        let routes = build_routes(config, store).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/token/rotate")
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let rotated: String = serde_json::from_slice(res.body()).unwrap();
+        let session = verify_token(&keys, &rotated).unwrap();
+        assert_eq!(session.account_id, account_id);
+        assert!(session.exp > now + chrono::Duration::hours(23));
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_do_not_rotate() {
+        let key = "RANDOM WORDS WINTER MACINTOSH PC";
+        let config = Config {
+            paseto_keys: vec![key.to_string()],
+            ..Config::default()
+        };
+        let now = chrono::Utc::now();
+        let token = paseto::tokens::PasetoBuilder::new()
+            .set_encryption_key(key.as_bytes())
+            .set_expiration(&(now - chrono::Duration::minutes(5)))
+            .set_not_before(&(now - chrono::Duration::days(1)))
+            .set_claim("account_id", json!(1))
+            .set_claim("is_admin", json!(false))
+            .set_claim("email", json!("new@email.com"))
+            .build()
+            .unwrap();
+        let store = MemoryStore::new();
+        unverified_account(&store).await;
+        let routes = build_routes(config, store).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/token/rotate")
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn tokens_of_missing_accounts_do_not_rotate() {
+        let key = "RANDOM WORDS WINTER MACINTOSH PC";
+        let config = Config {
+            paseto_keys: vec![key.to_string()],
+            ..Config::default()
+        };
+        let now = chrono::Utc::now();
+        // The email belongs to an account, the id doesn't
+        let token = paseto::tokens::PasetoBuilder::new()
+            .set_encryption_key(key.as_bytes())
+            .set_expiration(&(now + chrono::Duration::minutes(5)))
+            .set_not_before(&(now - chrono::Duration::hours(1)))
+            .set_claim("account_id", json!(99))
+            .set_claim("is_admin", json!(false))
+            .set_claim("email", json!("new@email.com"))
+            .build()
+            .unwrap();
+        let store = MemoryStore::new();
+        unverified_account(&store).await;
+        let routes = build_routes(config, store).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/token/rotate")
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 401);
+    }
+
+    #[tokio::test]
+    async fn api_tokens_do_not_rotate_into_login_tokens() {
+        let store = MemoryStore::new();
+        let account_id = unverified_account(&store).await;
+        store
+            .add_api_token(&account_id, "ci".to_string(), hash_api_token("api_leaked"))
+            .await
+            .unwrap();
+        let routes = build_routes(Config::default(), store).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/token/rotate")
+            .header("Authorization", "Bearer api_leaked")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 401);
+    }
+
+    #[test]
+    fn tokens_signed_with_a_rotated_out_key_still_verify() {
+        let old_key = "RANDOM WORDS WINTER MACINTOSH PC".to_string();
+        let new_key = "ANOTHER KEY FOR ROTATION TESTS!!".to_string();
+
+        let before = TokenKeys::new(vec![old_key.clone()]);
+        let token = issue_token(&before, AccountId(3), "test@email.com".to_string(), false);
+
+        let rotating = TokenKeys::new(vec![new_key.clone(), old_key]);
+        let session = verify_token(&rotating, &token).unwrap();
+        assert_eq!(session.account_id, AccountId(3));
+
+        // New tokens are signed with the new primary key only
+        let fresh = issue_token(&rotating, AccountId(4), "new@email.com".to_string(), false);
+        assert!(verify_token(&before, &fresh).is_err());
+
+        let rotated = TokenKeys::new(vec![new_key]);
+        assert!(verify_token(&rotated, &token).is_err());
+    }
+
+    #[test]
+    fn recently_expired_tokens_are_accepted_within_the_leeway() {
+        let key = "RANDOM WORDS WINTER MACINTOSH PC";
+        let now = chrono::Utc::now();
+        let token = paseto::tokens::PasetoBuilder::new()
+            .set_encryption_key(key.as_bytes())
+            .set_expiration(&(now - chrono::Duration::seconds(5)))
+            .set_not_before(&(now - chrono::Duration::days(1)))
+            .set_claim("account_id", json!(3))
+            .set_claim("is_admin", json!(false))
+            .set_claim("email", json!("test@email.com"))
+            .build()
+            .unwrap();
+
+        let keys = TokenKeys::new(vec![key.to_string()]);
+        let lenient = keys.clone().with_leeway(chrono::Duration::seconds(60));
+        assert_eq!(verify_token(&lenient, &token).unwrap().account_id, AccountId(3));
+
+        let strict = keys.with_leeway(chrono::Duration::seconds(2));
+        assert!(verify_token(&strict, &token).is_err());
+    }
+
+    #[test]
+    fn blocked_domain_matches_case_insensitively() {
+        let blocked = vec!["mailinator.com".to_string()];
+
+        assert!(is_blocked_domain("spam@Mailinator.COM", &blocked));
+        assert!(!is_blocked_domain("user@example.com", &blocked));
+        assert!(!is_blocked_domain("user@example.com", &[]));
+    }
+
+    #[tokio::test]
+    async fn registration_reports_all_field_errors() {
+        let routes = build_routes(Config::default(), MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/registration")
+            .json(&json!({ "email": "not-an-email", "password": "short" }))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 422);
+        let errors: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(errors.as_array().unwrap().len(), 2);
+        assert_eq!(errors[0]["field"], "email");
+        assert_eq!(errors[1]["field"], "password");
+    }
+
+    #[tokio::test]
+    async fn registration_checks_blocked_domains() {
+        let config = Config {
+            blocked_email_domains: vec!["mailinator.com".to_string()],
+            ..Config::default()
+        };
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/registration")
+            .json(&json!({ "email": "spam@mailinator.com", "password": "password" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 422);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/registration")
+            .json(&json!({ "email": "user@example.com", "password": "password" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn registration_closes_at_the_account_limit() {
+        let config = Config {
+            max_accounts: 2,
+            ..Config::default()
+        };
+        let routes = build_routes(config, MemoryStore::new()).await;
+        let register = |email: &str| {
+            warp::test::request()
+                .method("POST")
+                .path("/registration")
+                .json(&json!({ "email": email, "password": "password" }))
+        };
+
+        for email in ["first@example.com", "second@example.com"] {
+            assert_eq!(register(email).reply(&routes).await.status(), 200);
+        }
+
+        let res = register("third@example.com").reply(&routes).await;
+        assert_eq!(res.status(), 403);
+        assert_eq!(res.body(), "Registration is closed");
+    }
+
+    async fn unverified_account(store: &MemoryStore) -> AccountId {
+        store
+            .add_account(Account {
+                id: None,
+                email: "new@email.com".to_string(),
+                password: "hash".to_string(),
+                is_admin: false,
+                verified: false,
+            })
+            .await
+            .unwrap();
+        store
+            .get_account("new@email.com".to_string())
+            .await
+            .unwrap()
+            .id
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn verification_token_verifies_the_account_once() {
+        let store = MemoryStore::new();
+        let account_id = unverified_account(&store).await;
+        store
+            .add_verification_token(&account_id, &hash_api_token("abc"), Duration::from_secs(60))
+            .await
+            .unwrap();
+        let routes = build_routes(Config::default(), store.clone()).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/verify?token=abc")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        let account = store.get_account("new@email.com".to_string()).await.unwrap();
+        assert!(account.verified);
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/verify?token=abc")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn expired_or_unknown_verification_token_is_rejected() {
+        let store = MemoryStore::new();
+        let account_id = unverified_account(&store).await;
+        store
+            .add_verification_token(&account_id, &hash_api_token("expired"), Duration::ZERO)
+            .await
+            .unwrap();
+        let routes = build_routes(Config::default(), store.clone()).await;
+
+        for path in ["/verify?token=expired", "/verify?token=unknown"] {
+            let res = warp::test::request()
+                .method("GET")
+                .path(path)
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), 400, "{}", path);
+        }
+        let account = store.get_account("new@email.com".to_string()).await.unwrap();
+        assert!(!account.verified);
+    }
+
+    #[tokio::test]
+    async fn verification_links_are_only_logged_in_dev() {
+        for profile in [Profile::Prod, Profile::Dev] {
+            let config = Config {
+                profile,
+                ..Config::default()
+            };
+            let store = MemoryStore::new();
+            let routes = build_routes(config, store.clone()).await;
+
+            let (logs, _guard) = capture_logs();
+            let res = warp::test::request()
+                .method("POST")
+                .path("/registration")
+                .json(&json!({ "email": "new@email.com", "password": "a long password" }))
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), 200);
+            let logs = logs.contents();
+            let token = logs
+                .split("/verify?token=")
+                .nth(1)
+                .map(|rest| rest.split_whitespace().next().unwrap().to_string());
+
+            match profile {
+                Profile::Dev => {
+                    // Only the hash was stored, but the logged token verifies
+                    let path = format!("/verify?token={}", token.unwrap());
+                    let res = warp::test::request().method("GET").path(&path).reply(&routes).await;
+                    assert_eq!(res.status(), 200);
+                }
+                _ => assert_eq!(token, None),
+            }
+        }
+    }
+
+    async fn reset_token(store: &MemoryStore, token: &str, ttl: Duration) {
+        let account_id = unverified_account(store).await;
+        store
+            .add_password_reset_token(&account_id, &hash_api_token(token), ttl)
+            .await
+            .unwrap();
+    }
+
+    async fn confirm_reset(store: &MemoryStore, token: &str) -> u16 {
+        let routes = build_routes(Config::default(), store.clone()).await;
+        warp::test::request()
+            .method("POST")
+            .path("/password-reset/confirm")
+            .json(&json!({ "token": token, "password": "a new password" }))
+            .reply(&routes)
+            .await
+            .status()
+            .as_u16()
+    }
+
+    #[tokio::test]
+    async fn password_reset_request_does_not_reveal_accounts() {
+        let store = MemoryStore::new();
+        unverified_account(&store).await;
+        let routes = build_routes(Config::default(), store).await;
+
+        for email in ["new@email.com", "nobody@email.com"] {
+            let res = warp::test::request()
+                .method("POST")
+                .path("/password-reset/request")
+                .json(&json!({ "email": email }))
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), 200, "{}", email);
+        }
+    }
+
+    #[tokio::test]
+    async fn password_reset_tokens_are_only_logged_in_dev() {
+        for profile in [Profile::Prod, Profile::Dev] {
+            let config = Config {
+                profile,
+                ..Config::default()
+            };
+            let store = MemoryStore::new();
+            unverified_account(&store).await;
+            let routes = build_routes(config, store.clone()).await;
+
+            let (logs, _guard) = capture_logs();
+            let res = warp::test::request()
+                .method("POST")
+                .path("/password-reset/request")
+                .json(&json!({ "email": "new@email.com" }))
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), 200);
+            let logs = logs.contents();
+            let token = logs
+                .split("Reset the password with token ")
+                .nth(1)
+                .map(|rest| rest.split_whitespace().next().unwrap().to_string());
+
+            match profile {
+                Profile::Dev => assert_eq!(confirm_reset(&store, &token.unwrap()).await, 200),
+                _ => assert_eq!(token, None),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn password_reset_sets_the_new_password() {
+        let store = MemoryStore::new();
+        reset_token(&store, "reset", Duration::from_secs(60)).await;
+
+        assert_eq!(confirm_reset(&store, "reset").await, 200);
+        let account = store.get_account("new@email.com".to_string()).await.unwrap();
+        assert!(verify_password(&account.password, b"a new password").unwrap());
+    }
+
+    #[tokio::test]
+    async fn expired_password_reset_token_is_rejected() {
+        let store = MemoryStore::new();
+        reset_token(&store, "expired", Duration::ZERO).await;
+
+        assert_eq!(confirm_reset(&store, "expired").await, 400);
+        let account = store.get_account("new@email.com".to_string()).await.unwrap();
+        assert_eq!(account.password, "hash");
+    }
+
+    #[tokio::test]
+    async fn password_reset_token_works_once() {
+        let store = MemoryStore::new();
+        reset_token(&store, "reset", Duration::from_secs(60)).await;
+
+        assert_eq!(confirm_reset(&store, "reset").await, 200);
+        assert_eq!(confirm_reset(&store, "reset").await, 400);
+    }
+
+    #[tokio::test]
+    async fn unverified_accounts_cannot_post_when_required() {
+        let store = MemoryStore::new();
+        let account_id = unverified_account(&store).await;
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            require_verified_email: true,
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            account_id,
+            "new@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, store).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/questions")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({ "title": "Title", "content": "Some content" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 403);
+    }
+
+    #[test]
+    fn passwords_round_trip_with_either_algorithm() {
+        for (algorithm, prefix) in [
+            (PasswordAlgorithm::Argon2, "$argon2"),
+            (PasswordAlgorithm::Bcrypt, "$2b$"),
+        ] {
+            let hash = hash_password(b"correct horse", algorithm).unwrap();
+            assert!(hash.starts_with(prefix));
+            assert!(verify_password(&hash, b"correct horse").unwrap());
+            assert!(!verify_password(&hash, b"battery staple").unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_bcrypt_hashes_fail_logins_with_500() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "legacy@email.com".to_string(),
+                password: "$2b$12$truncated".to_string(),
+                is_admin: false,
+                verified: true,
+            })
+            .await
+            .unwrap();
+        let routes = build_routes(Config::default(), store).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/login")
+            .json(&json!({ "email": "legacy@email.com", "password": "a long password" }))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 500);
+    }
+
+    #[tokio::test]
+    async fn malformed_argon2_hashes_fail_logins_with_500() {
+        let store = MemoryStore::new();
+        store
+            .add_account(Account {
+                id: None,
+                email: "broken@email.com".to_string(),
+                password: "$argon2i$truncated".to_string(),
+                is_admin: false,
+                verified: true,
+            })
+            .await
+            .unwrap();
+        let routes = build_routes(Config::default(), store).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/login")
+            .json(&json!({ "email": "broken@email.com", "password": "a long password" }))
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 500);
+    }
 }
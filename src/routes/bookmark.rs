@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::Reply;
+
+use crate::config::Config;
+use crate::store::Store;
+use crate::types::account::Session;
+use crate::types::pagination::{Pagination, extract_pagination};
+
+/// `POST /questions/:id/bookmark`: save the question for later. Bookmarking
+/// it twice is fine.
+pub async fn add_bookmark<S: Store>(
+    question_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    // A bookmark of a missing question is a 404 rather than a foreign key
+    // violation
+    store.get_question(question_id).await?;
+    store.add_bookmark(question_id, session.account_id).await?;
+
+    Ok(warp::reply::json(&format!("Question {} bookmarked", question_id)))
+}
+
+/// `DELETE /questions/:id/bookmark`: forget a saved question
+pub async fn remove_bookmark<S: Store>(
+    question_id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.remove_bookmark(question_id, session.account_id).await?;
+
+    Ok(warp::reply::json(&format!("Bookmark of question {} removed", question_id)))
+}
+
+/// `GET /accounts/me/bookmarks`: the account's saved questions, most
+/// recently bookmarked first and paged like `/questions`
+pub async fn get_bookmarks<S: Store>(
+    session: Session,
+    params: HashMap<String, String>,
+    store: S,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut pagination = Pagination::default();
+
+    if params.contains_key("limit") || params.contains_key("offset") {
+        pagination = extract_pagination(params)?;
+    }
+    let pagination = pagination.clamp(config.max_page_size);
+
+    let questions = store
+        .get_bookmarks(&session.account_id, pagination.limit, pagination.offset)
+        .await?;
+
+    let mut response = warp::reply::json(&questions).into_response();
+    pagination.add_headers(response.headers_mut());
+    Ok(response)
+}
+
+#[cfg(test)]
+mod bookmark_tests {
+    use crate::build_routes;
+    use crate::config::{Config, DisabledRouteStatus};
+    use crate::routes::authentication::issue_token;
+    use crate::store::{MemoryStore, Store};
+    use crate::types::account::AccountId;
+    use crate::types::question::{NewQuestion, Question};
+
+    #[tokio::test]
+    async fn bookmarks_are_listed_newest_first_until_removed() {
+        let store = MemoryStore::new();
+        for title in ["first", "second", "third"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(2),
+            "me@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, store).await;
+        let request = |method: &str, path: &str| {
+            warp::test::request()
+                .method(method)
+                .path(path)
+                .header("Authorization", format!("Bearer {}", token))
+        };
+        let listed = |path: &'static str| {
+            let res = request("GET", path).reply(&routes);
+            async move {
+                let res = res.await;
+                assert_eq!(res.status(), 200);
+                let questions: Vec<Question> = serde_json::from_slice(res.body()).unwrap();
+                questions.into_iter().map(|q| q.id.0).collect::<Vec<i32>>()
+            }
+        };
+
+        for id in [1, 3, 3] {
+            let path = format!("/questions/{}/bookmark", id);
+            assert_eq!(request("POST", &path).reply(&routes).await.status(), 200);
+        }
+        let res = request("POST", "/questions/9/bookmark").reply(&routes).await;
+        assert_eq!(res.status(), 404);
+
+        // Bookmarking question 3 twice kept a single bookmark
+        assert_eq!(listed("/accounts/me/bookmarks").await, vec![3, 1]);
+        assert_eq!(listed("/accounts/me/bookmarks?limit=1&offset=1").await, vec![1]);
+
+        let res = request("DELETE", "/questions/3/bookmark").reply(&routes).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(listed("/accounts/me/bookmarks").await, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn bookmarks_need_a_login() {
+        let routes = build_routes(Config::default(), MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/accounts/me/bookmarks")
+            .reply(&routes)
+            .await;
+
+        assert_eq!(res.status(), 401);
+    }
+    #[tokio::test]
+    async fn bookmarks_are_refused_in_read_only_mode() {
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "first".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        store.add_bookmark(1, AccountId(2)).await.unwrap();
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            read_only: true,
+            disabled_route_status: DisabledRouteStatus::Forbidden,
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(2),
+            "me@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, store.clone()).await;
+
+        for method in ["POST", "DELETE"] {
+            let res = warp::test::request()
+                .method(method)
+                .path("/questions/1/bookmark")
+                .header("Authorization", format!("Bearer {}", token))
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), 403, "{}", method);
+        }
+        let bookmarks = store.get_bookmarks(&AccountId(2), None, 0).await.unwrap();
+        assert_eq!(bookmarks.len(), 1);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::store::Store;
+use crate::types::account::Session;
+use crate::types::feature_flag::{FeatureFlag, FlagStatus, FlagUpdate};
+
+/// `GET /admin/flags`: every feature flag with its current value, the
+/// default one for flags that were never set
+pub async fn get_flags<S: Store>(
+    _session: Session,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut flags = Vec::new();
+    for flag in FeatureFlag::ALL {
+        flags.push(FlagStatus {
+            name: flag.name().to_string(),
+            enabled: store.flag_enabled(flag).await?,
+        });
+    }
+
+    Ok(warp::reply::json(&flags))
+}
+
+/// `PUT /admin/flags/:name`: flip a flag, taking effect on the next request
+pub async fn set_flag<S: Store>(
+    name: String,
+    session: Session,
+    store: S,
+    update: FlagUpdate,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let flag: FeatureFlag = name
+        .parse()
+        .map_err(handle_errors::Error::InvalidParameter)?;
+    store.set_flag(flag.name(), update.enabled).await?;
+    tracing::info!(
+        moderator = session.account_id.0,
+        flag = flag.name(),
+        enabled = update.enabled,
+        "Feature flag changed by a moderator"
+    );
+
+    Ok(warp::reply::json(&FlagStatus {
+        name: flag.name().to_string(),
+        enabled: update.enabled,
+    }))
+}
+
+#[cfg(test)]
+mod feature_flag_tests {
+    use serde_json::json;
+
+    use crate::build_routes;
+    use crate::config::Config;
+    use crate::routes::authentication::issue_token;
+    use crate::store::MemoryStore;
+    use crate::types::account::AccountId;
+    use crate::types::feature_flag::FlagStatus;
+
+    #[tokio::test]
+    async fn flags_set_by_admins_switch_behavior() {
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let keys = config.token_keys();
+        let routes = build_routes(config, MemoryStore::new()).await;
+        let set = |is_admin: bool, name: &str, enabled: bool| {
+            let token = issue_token(&keys, AccountId(7), "mod@email.com".to_string(), is_admin);
+            warp::test::request()
+                .method("PUT")
+                .path(&format!("/admin/flags/{}", name))
+                .header("Authorization", format!("Bearer {}", token))
+                .json(&json!({ "enabled": enabled }))
+        };
+        let register = |email: &str| {
+            warp::test::request()
+                .method("POST")
+                .path("/registration")
+                .json(&json!({ "email": email, "password": "password" }))
+        };
+
+        assert_eq!(register("first@email.com").reply(&routes).await.status(), 200);
+
+        let res = set(false, "registration_open", false).reply(&routes).await;
+        assert_eq!(res.status(), 401);
+        let res = set(true, "registraton_open", false).reply(&routes).await;
+        assert_eq!(res.status(), 400);
+        let res = set(true, "registration_open", false).reply(&routes).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(register("second@email.com").reply(&routes).await.status(), 404);
+
+        let token = issue_token(&keys, AccountId(7), "mod@email.com".to_string(), true);
+        let res = warp::test::request()
+            .method("GET")
+            .path("/admin/flags")
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(&routes)
+            .await;
+        let flags: Vec<FlagStatus> = serde_json::from_slice(res.body()).unwrap();
+        let flag = |name: &str, enabled| FlagStatus {
+            name: name.to_string(),
+            enabled,
+        };
+        assert_eq!(
+            flags,
+            vec![flag("registration_open", false), flag("maintenance_mode", false)]
+        );
+
+        let res = set(true, "registration_open", true).reply(&routes).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(register("second@email.com").reply(&routes).await.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn maintenance_mode_blocks_writes() {
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(7),
+            "mod@email.com".to_string(),
+            true,
+        );
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("PUT")
+            .path("/admin/flags/maintenance_mode")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({ "enabled": true }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/questions")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({ "title": "Title", "content": "Some content" }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 404);
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+    }
+
+    #[tokio::test]
+    async fn flags_are_fixed_in_read_only_mode_but_maintenance_can_be_lifted() {
+        let keys = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        }
+        .token_keys();
+        let token = issue_token(&keys, AccountId(7), "mod@email.com".to_string(), true);
+        let set = |enabled: bool| {
+            warp::test::request()
+                .method("PUT")
+                .path("/admin/flags/maintenance_mode")
+                .header("Authorization", format!("Bearer {}", token))
+                .json(&json!({ "enabled": enabled }))
+        };
+
+        for (read_only, status) in [(true, 404), (false, 200)] {
+            let config = Config {
+                paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+                read_only,
+                ..Config::default()
+            };
+            let routes = build_routes(config, MemoryStore::new()).await;
+            assert_eq!(set(true).reply(&routes).await.status(), status, "{}", read_only);
+            assert_eq!(set(false).reply(&routes).await.status(), status, "{}", read_only);
+        }
+    }
+}
@@ -1,101 +1,402 @@
+use futures::StreamExt;
+use futures::stream;
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io;
+use std::sync::Arc;
+use std::time::Instant;
 
+use tokio::sync::broadcast::error::RecvError;
 use tracing::{Level, event, instrument};
+use warp::http::header::{CACHE_CONTROL, CONTENT_TYPE, HeaderValue};
 use warp::http::StatusCode;
+use warp::hyper::Body;
+use warp::Reply;
+use warp::reply::Response;
+use warp::sse::Event;
 
-use crate::profanity::check_profanity;
+use crate::config::{Config, Profile};
+use crate::feed::QuestionFeed;
+use crate::rate_limit::QuestionRateLimiter;
+use crate::profanity::{check_profanity_when, record_censorship};
 use crate::store::Store;
 use crate::types::account::Session;
 use crate::types::pagination::{Pagination, extract_pagination};
-use crate::types::question::{NewQuestion, Question};
+use crate::types::question::{
+    NewQuestion, Question, QuestionUpdate, QuestionWithAnswerCount, QuestionWithAnswers, check_content_length,
+    check_edit_window, check_unmodified_since, parse_content_format, validate_question,
+};
+use crate::types::search::{SearchCount, extract_search_term};
+use crate::types::sort::extract_sort;
+use crate::types::tag::{dedupe_tags, extract_tag_filter};
+use crate::types::vote::QuestionWithVote;
+
+/// Longest `?delay_ms=` the dev profile sleeps for
+const MAX_DEBUG_DELAY_MS: u64 = 10_000;
+
+/// The `?delay_ms=` to hold a response back by, for trying out client
+/// timeouts. Only the dev profile honours it, capped at
+/// `MAX_DEBUG_DELAY_MS`; other profiles ignore the param altogether.
+fn debug_delay(
+    params: &HashMap<String, String>,
+    profile: Profile,
+) -> Result<Option<std::time::Duration>, handle_errors::Error> {
+    if profile != Profile::Dev {
+        return Ok(None);
+    }
+    let Some(delay_ms) = params.get("delay_ms") else {
+        return Ok(None);
+    };
+    let delay_ms = delay_ms.parse::<u64>().map_err(|_| {
+        handle_errors::Error::InvalidParameter(format!(
+            "delay_ms must be a number of milliseconds, got `{}`",
+            delay_ms
+        ))
+    })?;
+
+    Ok(Some(std::time::Duration::from_millis(delay_ms.min(MAX_DEBUG_DELAY_MS))))
+}
+
 #[instrument]
-pub async fn get_questions(
+pub async fn get_questions<S: Store>(
     params: HashMap<String, String>,
-    store: Store,
+    session: Option<Session>,
+    store: S,
+    config: Arc<Config>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     event!(target: "practical_rust_book", Level::INFO, "querying questions");
     let mut pagination = Pagination::default();
+    let delay = debug_delay(&params, config.profile)?;
+    let sort = extract_sort(&params, config.questions_default_sort())?;
+    let tags = extract_tag_filter(&params)?;
+    let include_archived = match params.get("include_expired").map(String::as_str) {
+        Some("true") => true,
+        Some("false") | None => false,
+        Some(other) => {
+            return Err(warp::reject::custom(handle_errors::Error::InvalidParameter(
+                format!("include_expired must be true or false, got `{}`", other),
+            )));
+        }
+    };
+    // Expired questions are archived, only admins get to see them
+    if include_archived && !session.as_ref().is_some_and(|session| session.is_admin) {
+        return Err(warp::reject::custom(handle_errors::Error::Unauthorized));
+    }
 
-    if !params.is_empty() {
+    if params.contains_key("limit") || params.contains_key("offset") {
         event!(Level::INFO, pagination = true);
         pagination = extract_pagination(params)?;
     }
+    let pagination = pagination.clamp(config.max_page_size);
 
-    match store
-        .get_questions(pagination.limit, pagination.offset)
+    // Listings carrying the caller's votes must never be shared
+    let cache_control = match (&session, config.questions_cache_max_age) {
+        (Some(_), _) | (None, 0) => HeaderValue::from_static("no-store"),
+        (None, max_age) => HeaderValue::from_str(&format!("public, max-age={}", max_age))
+            .expect("cache-control is a valid header value"),
+    };
+    let finish = |mut response: Response| {
+        response.headers_mut().insert(CACHE_CONTROL, cache_control.clone());
+        pagination.add_headers(response.headers_mut());
+        response
+    };
+
+    let questions = match store
+        .get_questions(pagination.limit, pagination.offset, sort, include_archived, tags)
         .await
     {
-        Ok(res) => Ok(warp::reply::json(&res)),
+        Ok(res) => res,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+
+    if questions.is_empty() && config.empty_list_no_content {
+        let reply = warp::reply::with_status(warp::reply(), StatusCode::NO_CONTENT);
+        return Ok(finish(reply.into_response()));
+    }
+
+    let Some(session) = session else {
+        return Ok(finish(warp::reply::json(&questions).into_response()));
+    };
+
+    let ids: Vec<i32> = questions.iter().map(|question| question.id.0).collect();
+    let votes = match store.get_votes(&session.account_id, &ids).await {
+        Ok(votes) => votes,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    let questions: Vec<QuestionWithVote> = questions
+        .into_iter()
+        .map(|question| QuestionWithVote {
+            my_vote: votes.get(&question.id.0).copied().into(),
+            question,
+        })
+        .collect();
+
+    Ok(finish(warp::reply::json(&questions).into_response()))
+}
+
+/// A single question with its answer count; `?include=answers` bundles
+/// the answers themselves instead
+pub async fn get_question<S: Store>(
+    id: i32,
+    params: HashMap<String, String>,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let include_answers = match params.get("include").map(String::as_str) {
+        Some("answers") => true,
+        Some(other) => {
+            return Err(warp::reject::custom(
+                handle_errors::Error::InvalidParameter(format!(
+                    "cannot include `{}`",
+                    other
+                )),
+            ));
+        }
+        None => false,
+    };
+
+    let question = match store.get_question(id).await {
+        Ok(question) => question,
+        Err(e) => return Err(warp::reject::custom(e)),
+    };
+
+    if !include_answers {
+        let answer_count = store.count_answers(id).await?;
+        return Ok(warp::reply::json(&QuestionWithAnswerCount {
+            question,
+            answer_count,
+        }));
+    }
+
+    match store.get_answers(id).await {
+        Ok(answers) => Ok(warp::reply::json(&QuestionWithAnswers { question, answers })),
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
 
-pub async fn add_question(
+/// `GET /questions/random`: one listed question, 404 when there are none
+pub async fn random_question<S: Store>(store: S) -> Result<impl warp::Reply, warp::Rejection> {
+    let question = store.random_question().await?;
+
+    Ok(warp::reply::json(&question))
+}
+
+/// `GET /questions/search/count?q=...`: how many listed questions match
+/// the search term, without fetching them
+pub async fn count_search_results<S: Store>(
+    params: HashMap<String, String>,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let term = extract_search_term(&params)?;
+    let count = store.count_search_results(&term).await?;
+
+    Ok(warp::reply::json(&SearchCount { count }))
+}
+
+/// `GET /questions/stream`: every question as newline-delimited JSON,
+/// written out as rows arrive. Takes an optional `limit`.
+pub async fn stream_questions<S: Store>(
+    params: HashMap<String, String>,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let limit = match params.get("limit") {
+        Some(limit) => Some(
+            limit
+                .parse::<u32>()
+                .map_err(handle_errors::Error::ParseError)?,
+        ),
+        None => None,
+    };
+
+    let questions = store.stream_questions(limit).await?;
+    let lines = questions.map(|question| match question {
+        Ok(question) => {
+            let mut line = serde_json::to_vec(&question)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            line.push(b'\n');
+            Ok(line)
+        }
+        Err(e) => Err(io::Error::other(e.to_string())),
+    });
+
+    let mut response = Response::new(Body::wrap_stream(lines));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    Ok(response)
+}
+
+/// `GET /questions/live`: server-sent events, one per newly posted question
+pub async fn live_questions(feed: QuestionFeed) -> Result<impl warp::Reply, warp::Rejection> {
+    let subscription = feed.subscribe()?;
+
+    let events = stream::unfold(subscription, |mut subscription| async move {
+        loop {
+            match subscription.receiver.recv().await {
+                Ok(question) => match Event::default().json_data(&question) {
+                    Ok(event) => return Some((Ok::<_, Infallible>(event), subscription)),
+                    Err(_) => continue,
+                },
+                // Fell behind, carry on with what's still buffered
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+}
+
+pub async fn add_question<S: Store>(
     session: Session,
-    store: Store,
+    store: S,
+    config: Arc<Config>,
+    feed: QuestionFeed,
+    limiter: QuestionRateLimiter,
     new_question: NewQuestion,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let account_id = session.account_id;
-    let title = match check_profanity(new_question.title).await {
+    limiter.check(&account_id, Instant::now())?;
+    validate_question(&new_question.title, &new_question.content, &new_question.tags)?;
+    parse_content_format(new_question.content_format.as_deref())?;
+    check_content_length(&new_question.content, config.question_min_length)?;
+
+    let allow_list = &config.profanity_allow_list;
+    let api = config.profanity_api();
+
+    let title = match check_profanity_when(
+        !config.disable_title_profanity_check,
+        api.clone(),
+        new_question.title.clone(),
+        allow_list.clone(),
+    )
+    .await
+    {
         Ok(res) => res,
         Err(e) => return Err(warp::reject::custom(e)),
     };
 
-    let content = match check_profanity(new_question.content).await {
+    let content = match check_profanity_when(
+        !config.disable_content_profanity_check,
+        api.clone(),
+        new_question.content.clone(),
+        allow_list.clone(),
+    )
+    .await
+    {
         Ok(res) => res,
         Err(e) => return Err(warp::reject::custom(e)),
     };
 
+    let audit = config.censorship_audit;
+    record_censorship(&store, audit, &account_id, "question", &new_question.title, &title).await?;
+    record_censorship(&store, audit, &account_id, "question", &new_question.content, &content)
+        .await?;
+
     let question = NewQuestion {
         title,
         content,
-        tags: new_question.tags,
+        tags: dedupe_tags(new_question.tags),
+        expires_at: new_question.expires_at,
+        content_format: new_question.content_format,
     };
 
     match store.add_question(question, account_id).await {
-        Ok(question) => Ok(warp::reply::json(&question)),
+        Ok(question) => {
+            feed.publish(&question);
+            Ok(warp::reply::json(&question))
+        }
         Err(e) => Err(warp::reject::custom(e)),
     }
 }
 
-pub async fn update_question(
+/// `PUT /questions/:id`. With an `If-Unmodified-Since` header the update
+/// is refused with a 412 when the question changed after that date.
+pub async fn update_question<S: Store>(
     id: i32,
     session: Session,
-    store: Store,
-    question: Question,
+    store: S,
+    config: Arc<Config>,
+    if_unmodified_since: Option<String>,
+    question: QuestionUpdate,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let account_id = session.account_id;
+    validate_question(&question.title, &question.content, &question.tags)?;
+    check_content_length(&question.content, config.question_min_length)?;
+    let content_format = match question.content_format.as_deref() {
+        Some(format) => Some(parse_content_format(Some(format))?),
+        None => None,
+    };
+
     if store.is_question_owner(id, &account_id).await? {
-        let title = tokio::spawn(check_profanity(question.title.clone()));
-        let content = tokio::spawn(check_profanity(question.content.clone()));
+        check_edit_window(
+            store.question_created_on(id).await?,
+            chrono::Utc::now(),
+            config.question_edit_window,
+            session.is_admin,
+        )?;
+        check_unmodified_since(
+            store.question_modified_on(id).await?,
+            if_unmodified_since.as_deref(),
+        )?;
+
+        let allow_list = &config.profanity_allow_list;
+        let api = config.profanity_api();
+        let title = tokio::spawn(check_profanity_when(
+            !config.disable_title_profanity_check,
+            api.clone(),
+            question.title.clone(),
+            allow_list.clone(),
+        ));
+        let content = tokio::spawn(check_profanity_when(
+            !config.disable_content_profanity_check,
+            api.clone(),
+            question.content.clone(),
+            allow_list.clone(),
+        ));
 
         let (title, content) = (title.await.unwrap(), content.await.unwrap());
 
-        if title.is_ok() && content.is_ok() {
-            let question = Question {
-                id: question.id,
-                title: title.unwrap(),
-                content: content.unwrap(),
-                tags: question.tags,
-            };
-            match store.update_question(question, id, account_id).await {
-                Ok(res) => Ok(warp::reply::json(&res)),
-                Err(e) => Err(warp::reject::custom(e)),
+        match (title, content) {
+            (Ok(title), Ok(content)) => {
+                let audit = config.censorship_audit;
+                let resource = "question";
+                record_censorship(&store, audit, &account_id, resource, &question.title, &title)
+                    .await?;
+                record_censorship(&store, audit, &account_id, resource, &question.content, &content)
+                    .await?;
+                let content_format = match content_format {
+                    Some(content_format) => content_format,
+                    None => store.get_question(id).await?.content_format,
+                };
+                let question = Question {
+                    id: question.id,
+                    title,
+                    content,
+                    tags: dedupe_tags(question.tags),
+                    expires_at: question.expires_at,
+                    content_format,
+                };
+                match store.update_question(question, id, account_id).await {
+                    Ok(res) => Ok(warp::reply::json(&res)),
+                    Err(e) => Err(warp::reject::custom(e)),
+                }
             }
-        } else {
-            Err(warp::reject::custom(
-                title.expect_err("Expected API call to have failed here"),
-            ))
+            (Err(e), _) | (_, Err(e)) => Err(warp::reject::custom(e)),
         }
     } else {
         Err(warp::reject::custom(handle_errors::Error::Unauthorized))
     }
 }
 
-pub async fn delete_question(
+pub async fn delete_question<S: Store>(
     id: i32,
     session: Session,
-    store: Store,
+    store: S,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let account_id = session.account_id;
     if store.is_question_owner(id, &account_id).await? {
@@ -110,3 +411,1036 @@ pub async fn delete_question(
         Err(warp::reject::custom(handle_errors::Error::Unauthorized))
     }
 }
+
+/// `DELETE /admin/questions/:id`: remove any question, whoever owns it.
+/// For admins only.
+pub async fn remove_question<S: Store>(
+    id: i32,
+    session: Session,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.remove_question(id).await?;
+    tracing::info!(
+        moderator = session.account_id.0,
+        question_id = id,
+        "Question removed by a moderator"
+    );
+
+    Ok(warp::reply::with_status(
+        format!("Question {} deleted", id),
+        StatusCode::OK,
+    ))
+}
+
+/// `POST /admin/questions/:id/pin` and `/unpin`: moderators put a question
+/// ahead of every listing, or let it back into its sorted place
+pub async fn set_pinned<S: Store>(
+    id: i32,
+    pinned: bool,
+    session: Session,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    store.set_pinned(id, pinned).await?;
+    tracing::info!(
+        moderator = session.account_id.0,
+        question_id = id,
+        pinned,
+        "Question pinning changed by a moderator"
+    );
+
+    let action = if pinned { "pinned" } else { "unpinned" };
+    Ok(warp::reply::json(&format!("Question {} {}", id, action)))
+}
+
+#[cfg(test)]
+mod question_tests {
+    use chrono::{Duration, Utc};
+    use serde_json::{Value, json};
+
+    use crate::build_routes;
+    use crate::config::{Config, DisabledRouteStatus, Profile};
+    use crate::routes::authentication::issue_token;
+    use crate::store::{MemoryStore, Store};
+    use crate::test_utils::{capture_logs, censoring_api};
+    use crate::types::account::AccountId;
+    use crate::types::answer::{AnswerId, NewAnswer};
+    use crate::types::question::{
+        ContentFormat, NewQuestion, Question, QuestionId, QuestionWithAnswerCount,
+    };
+    use crate::types::search::SearchCount;
+    use crate::types::sort::SortDirection;
+    use crate::types::vote::Vote;
+
+    async fn seed(store: &MemoryStore, titles: &[&str]) {
+        for title in titles {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Some content".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn listed_ids(routes_config: Config, store: MemoryStore, path: &str) -> Vec<i32> {
+        let routes = build_routes(routes_config, store).await;
+        let res = warp::test::request()
+            .method("GET")
+            .path(path)
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        serde_json::from_slice::<Vec<Question>>(res.body())
+            .unwrap()
+            .into_iter()
+            .map(|question| question.id.0)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn listings_stop_at_the_row_cap_with_a_warning() {
+        let store = MemoryStore::new().with_max_rows(2);
+        seed(&store, &["first", "second", "third"]).await;
+
+        let (logs, _guard) = capture_logs();
+        let ids = listed_ids(Config::default(), store.clone(), "/questions").await;
+        assert_eq!(ids, vec![1, 2]);
+        let logs = logs.contents();
+        assert!(logs.contains("WARN"), "{}", logs);
+        assert!(logs.contains("Row cap reached"), "{}", logs);
+        assert!(logs.contains("method=\"get_questions\""), "{}", logs);
+
+        // A page within the cap isn't reported
+        let (logs, _guard) = capture_logs();
+        let ids = listed_ids(Config::default(), store, "/questions?limit=2&offset=0").await;
+        assert_eq!(ids, vec![1, 2]);
+        assert!(!logs.contents().contains("Row cap reached"));
+    }
+
+    #[tokio::test]
+    async fn configured_default_order_applies_without_sort_param() {
+        let store = MemoryStore::new();
+        seed(&store, &["first", "second", "third"]).await;
+
+        let config = Config {
+            questions_order: SortDirection::Desc,
+            ..Config::default()
+        };
+
+        let ids = listed_ids(config.clone(), store.clone(), "/questions").await;
+        assert_eq!(ids, vec![3, 2, 1]);
+
+        let ids = listed_ids(config, store, "/questions?order=asc").await;
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn tags_any_matches_questions_with_one_of_the_tags() {
+        let store = MemoryStore::new();
+        for tags in [vec!["rust", "warp"], vec!["go"], vec!["python"]] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: "Tagged".to_string(),
+                        content: "Some content".to_string(),
+                        tags: Some(tags.into_iter().map(String::from).collect()),
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+
+        let path = "/questions?sort=id&order=asc&tags_any=rust,go";
+        let ids = listed_ids(Config::default(), store.clone(), path).await;
+        assert_eq!(ids, vec![1, 2]);
+
+        let path = "/questions?sort=id&order=asc&tags=rust,go";
+        let ids = listed_ids(Config::default(), store.clone(), path).await;
+        assert!(ids.is_empty());
+
+        let routes = build_routes(Config::default(), store).await;
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions?tags=rust&tags_any=go")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn repeated_tags_are_stored_once() {
+        unsafe {
+            std::env::set_var("BAD_WORDS_API_KEY", "YES");
+        }
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            profanity_api_url: censoring_api(),
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "me@email.com".to_string(),
+            false,
+        );
+        let store = MemoryStore::new();
+        let routes = build_routes(config, store.clone()).await;
+        let tags = |tags: &[&str]| Some(tags.iter().map(|tag| tag.to_string()).collect());
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/questions")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&json!({
+                "title": "Tagged twice",
+                "content": "Some content",
+                "tags": ["rust", "Rust", "web", "rust"],
+            }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        let mut question: Question = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(question.tags, tags(&["rust", "web"]));
+        assert_eq!(store.get_question(1).await.unwrap().tags, tags(&["rust", "web"]));
+
+        question.tags = tags(&["web", "warp", "WEB"]);
+        let res = warp::test::request()
+            .method("PUT")
+            .path("/questions/1")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&question)
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(store.get_question(1).await.unwrap().tags, tags(&["web", "warp"]));
+    }
+
+    #[tokio::test]
+    async fn search_count_matches_titles_and_content() {
+        let store = MemoryStore::new();
+        seed(&store, &["Warp filters", "Tokio runtimes", "Rejections in WARP"]).await;
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Archived".to_string(),
+                    content: "Old warp question".to_string(),
+                    tags: None,
+                    expires_at: Some(Utc::now() - Duration::hours(1)),
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        store.archive_expired_questions(Utc::now()).await.unwrap();
+        let routes = build_routes(Config::default(), store).await;
+        let count = |q: &'static str| {
+            warp::test::request()
+                .method("GET")
+                .path(&format!("/questions/search/count?q={}", q))
+        };
+
+        let res = count("warp").reply(&routes).await;
+        assert_eq!(res.status(), 200);
+        let body: SearchCount = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(body.count, 2);
+
+        let res = count("some%20content").reply(&routes).await;
+        let body: SearchCount = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(body.count, 3);
+
+        assert_eq!(count("x").reply(&routes).await.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn random_question_is_a_seeded_one_or_404() {
+        let store = MemoryStore::new();
+        let routes = build_routes(Config::default(), store.clone()).await;
+        let random = || warp::test::request().method("GET").path("/questions/random");
+
+        assert_eq!(random().reply(&routes).await.status(), 404);
+
+        seed(&store, &["first", "second"]).await;
+        let res = random().reply(&routes).await;
+        assert_eq!(res.status(), 200);
+        let question: Question = serde_json::from_slice(res.body()).unwrap();
+        assert!(["first", "second"].contains(&question.title.as_str()));
+    }
+
+    #[tokio::test]
+    async fn sort_by_answers_lists_most_discussed_first() {
+        let store = MemoryStore::new();
+        seed(&store, &["one answer", "two answers", "one answer, newer"]).await;
+        for (question_id, content) in [(1, "first"), (2, "second"), (2, "third"), (3, "fourth")] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(question_id),
+                        parent_answer_id: None,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+
+        let ids = listed_ids(Config::default(), store, "/questions?sort=answers").await;
+        assert_eq!(ids, vec![2, 3, 1]);
+    }
+
+    #[tokio::test]
+    async fn sort_by_top_lists_best_voted_first() {
+        let store = MemoryStore::new();
+        seed(&store, &["no votes", "liked", "disliked", "liked, newer"]).await;
+        for (question_id, account_id, vote) in [
+            (2, 1, Vote::Up),
+            (2, 2, Vote::Up),
+            (2, 3, Vote::Down),
+            (3, 1, Vote::Down),
+            (4, 1, Vote::Up),
+        ] {
+            store
+                .cast_vote(question_id, AccountId(account_id), vote)
+                .await
+                .unwrap();
+        }
+
+        let ids = listed_ids(Config::default(), store.clone(), "/questions?sort=top").await;
+        assert_eq!(ids, vec![4, 2, 1, 3]);
+        let page = store.get_questions_by_score(Some(2), 1, false, None).await.unwrap();
+        let page: Vec<i32> = page.into_iter().map(|question| question.id.0).collect();
+        assert_eq!(page, vec![2, 1]);
+    }
+
+    #[tokio::test]
+    async fn delay_ms_holds_responses_back_in_dev_only() {
+        let get = |profile: Profile| async move {
+            let config = Config {
+                profile,
+                ..Config::default()
+            };
+            let routes = build_routes(config, MemoryStore::new()).await;
+            let started = std::time::Instant::now();
+            let res = warp::test::request()
+                .method("GET")
+                .path("/questions?delay_ms=300")
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), 200);
+            started.elapsed()
+        };
+
+        assert!(get(Profile::Dev).await >= std::time::Duration::from_millis(300));
+        assert!(get(Profile::Prod).await < std::time::Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn expired_questions_drop_out_of_listings() {
+        let store = MemoryStore::new();
+        seed(&store, &["evergreen"]).await;
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Who is playing tonight?".to_string(),
+                    content: "Some content".to_string(),
+                    tags: None,
+                    expires_at: Some(Utc::now() - Duration::hours(1)),
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+
+        let ids = listed_ids(Config::default(), store.clone(), "/questions").await;
+        assert_eq!(ids, vec![1, 2]);
+
+        assert_eq!(store.archive_expired_questions(Utc::now()).await.unwrap(), 1);
+        let ids = listed_ids(Config::default(), store.clone(), "/questions").await;
+        assert_eq!(ids, vec![1]);
+
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let keys = config.token_keys();
+        let routes = build_routes(config, store).await;
+        for (is_admin, status) in [(false, 401), (true, 200)] {
+            let token = issue_token(&keys, AccountId(1), "test@email.com".to_string(), is_admin);
+            let res = warp::test::request()
+                .method("GET")
+                .path("/questions?include_expired=true")
+                .header("Authorization", format!("Bearer {}", token))
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), status);
+        }
+    }
+
+    #[tokio::test]
+    async fn admins_remove_questions_they_dont_own() {
+        let store = MemoryStore::new();
+        seed(&store, &["against the rules"]).await;
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let keys = config.token_keys();
+        let routes = build_routes(config, store.clone()).await;
+        let remove = |is_admin: bool, id: i32| {
+            let token = issue_token(&keys, AccountId(7), "mod@email.com".to_string(), is_admin);
+            warp::test::request()
+                .method("DELETE")
+                .path(&format!("/admin/questions/{}", id))
+                .header("Authorization", format!("Bearer {}", token))
+        };
+
+        assert_eq!(remove(false, 1).reply(&routes).await.status(), 401);
+        assert!(store.get_question(1).await.is_ok());
+
+        assert_eq!(remove(true, 1).reply(&routes).await.status(), 200);
+        assert!(store.get_question(1).await.is_err());
+
+        assert_eq!(remove(true, 1).reply(&routes).await.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn questions_are_not_removed_in_read_only_mode() {
+        let store = MemoryStore::new();
+        seed(&store, &["against the rules"]).await;
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            read_only: true,
+            disabled_route_status: DisabledRouteStatus::Forbidden,
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(7),
+            "mod@email.com".to_string(),
+            true,
+        );
+        let routes = build_routes(config, store.clone()).await;
+
+        let res = warp::test::request()
+            .method("DELETE")
+            .path("/admin/questions/1")
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 403);
+        assert!(store.get_question(1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn pinned_questions_lead_the_default_listing() {
+        let store = MemoryStore::new();
+        seed(&store, &["first", "second", "third"]).await;
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let keys = config.token_keys();
+        let routes = build_routes(config.clone(), store.clone()).await;
+        let pin = |is_admin: bool, path: &str| {
+            let token = issue_token(&keys, AccountId(7), "mod@email.com".to_string(), is_admin);
+            warp::test::request()
+                .method("POST")
+                .path(path)
+                .header("Authorization", format!("Bearer {}", token))
+        };
+
+        assert_eq!(pin(false, "/admin/questions/3/pin").reply(&routes).await.status(), 401);
+        assert_eq!(pin(true, "/admin/questions/9/pin").reply(&routes).await.status(), 404);
+        assert_eq!(pin(true, "/admin/questions/3/pin").reply(&routes).await.status(), 200);
+        let ids = listed_ids(config.clone(), store.clone(), "/questions").await;
+        assert_eq!(ids, vec![3, 1, 2]);
+
+        assert_eq!(pin(true, "/admin/questions/3/unpin").reply(&routes).await.status(), 200);
+        let ids = listed_ids(config, store, "/questions").await;
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn questions_are_not_pinned_in_read_only_mode() {
+        let store = MemoryStore::new();
+        seed(&store, &["first", "second"]).await;
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            read_only: true,
+            disabled_route_status: DisabledRouteStatus::Forbidden,
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(7),
+            "mod@email.com".to_string(),
+            true,
+        );
+        let routes = build_routes(config.clone(), store.clone()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/admin/questions/2/pin")
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 403);
+        assert_eq!(listed_ids(config, store, "/questions").await, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn titles_and_content_are_checked_as_configured() {
+        unsafe {
+            std::env::set_var("BAD_WORDS_API_KEY", "YES");
+        }
+        let api_url = censoring_api();
+        let post = |check_title: bool, check_content: bool| {
+            let config = Config {
+                paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+                profanity_api_url: api_url.clone(),
+                disable_title_profanity_check: !check_title,
+                disable_content_profanity_check: !check_content,
+                ..Config::default()
+            };
+            async move {
+                let token = issue_token(
+                    &config.token_keys(),
+                    AccountId(1),
+                    "me@email.com".to_string(),
+                    false,
+                );
+                let routes = build_routes(config, MemoryStore::new()).await;
+                let mut stored = Vec::new();
+                for (title, content) in [
+                    ("A shitty title", "Fine content"),
+                    ("A fine title", "Some shitty content"),
+                ] {
+                    let res = warp::test::request()
+                        .method("POST")
+                        .path("/questions")
+                        .header("Authorization", format!("Bearer {}", token))
+                        .json(&json!({ "title": title, "content": content }))
+                        .reply(&routes)
+                        .await;
+                    assert_eq!(res.status(), 200);
+                    let question: Question = serde_json::from_slice(res.body()).unwrap();
+                    stored.push((question.title, question.content));
+                }
+                stored
+            }
+        };
+        let pair = |title: &str, content: &str| (title.to_string(), content.to_string());
+
+        assert_eq!(
+            post(true, true).await,
+            vec![
+                pair("A ****** title", "Fine content"),
+                pair("A fine title", "Some ****** content"),
+            ]
+        );
+        assert_eq!(
+            post(true, false).await,
+            vec![
+                pair("A ****** title", "Fine content"),
+                pair("A fine title", "Some shitty content"),
+            ]
+        );
+        assert_eq!(
+            post(false, true).await,
+            vec![
+                pair("A shitty title", "Fine content"),
+                pair("A fine title", "Some ****** content"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn updates_of_questions_modified_since_get_412() {
+        let store = MemoryStore::new();
+        seed(&store, &["edited meanwhile"]).await;
+        let question = store.get_question(1).await.unwrap();
+        store.update_question(question.clone(), 1, AccountId(1)).await.unwrap();
+
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "test@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, store).await;
+
+        let res = warp::test::request()
+            .method("PUT")
+            .path("/questions/1")
+            .header("Authorization", format!("Bearer {}", token))
+            .header("If-Unmodified-Since", (Utc::now() - Duration::hours(1)).to_rfc2822())
+            .json(&question)
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 412);
+    }
+
+    #[tokio::test]
+    async fn applied_pagination_is_echoed_in_headers() {
+        let store = MemoryStore::new();
+        seed(&store, &["first", "second", "third"]).await;
+        let config = Config {
+            max_page_size: 2,
+            ..Config::default()
+        };
+        let routes = build_routes(config, store).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions?limit=50&offset=0")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["x-pagination-limit"], "2");
+        assert_eq!(res.headers()["x-pagination-offset"], "0");
+        let questions: Vec<Question> = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(questions.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn unpaginated_listing_is_not_capped_by_default() {
+        let store = MemoryStore::new();
+        seed(&store, &["first", "second", "third"]).await;
+        let routes = build_routes(Config::default(), store).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        assert!(res.headers().get("x-pagination-limit").is_none());
+        let questions: Vec<Question> = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(questions.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn authenticated_listing_includes_my_vote() {
+        unsafe {
+            std::env::set_var("PASETO_KEY", "RANDOM WORDS WINTER MACINTOSH PC");
+        }
+
+        let store = MemoryStore::new();
+        seed(&store, &["voted", "not voted"]).await;
+        store.cast_vote(1, AccountId(5), Vote::Up).await.unwrap();
+
+        let routes = build_routes(Config::default(), store).await;
+        let keys = Config::default().token_keys();
+        let token = issue_token(&keys, AccountId(5), "voter@email.com".to_string(), false);
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .header("Authorization", token)
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let questions: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(questions[0]["id"], 1);
+        assert_eq!(questions[0]["my_vote"], "up");
+        assert_eq!(questions[1]["my_vote"], "none");
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .reply(&routes)
+            .await;
+        let questions: Value = serde_json::from_slice(res.body()).unwrap();
+        assert!(questions[0].get("my_vote").is_none());
+    }
+
+    #[tokio::test]
+    async fn listing_cache_control_follows_config() {
+        let store = MemoryStore::new();
+        seed(&store, &["cached"]).await;
+
+        let config = Config {
+            questions_cache_max_age: 60,
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "test@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, store.clone()).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.headers()["cache-control"], "public, max-age=60");
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .header("Authorization", format!("Bearer {}", token))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.headers()["cache-control"], "no-store");
+
+        let routes = build_routes(Config::default(), store).await;
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.headers()["cache-control"], "no-store");
+    }
+
+    #[tokio::test]
+    async fn question_can_include_its_answers() {
+        let store = MemoryStore::new();
+        seed(&store, &["with answers"]).await;
+        store
+            .add_answer(
+                NewAnswer {
+                    content: "An answer".to_string(),
+                    question_id: QuestionId(1),
+                    parent_answer_id: None,
+                    client_id: None,
+                    attachments: Vec::new(),
+                },
+                AccountId(2),
+            )
+            .await
+            .unwrap();
+        let routes = build_routes(Config::default(), store).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/1?include=answers")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        let body: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(body["question"]["title"], "with answers");
+        assert_eq!(body["answers"][0]["content"], "An answer");
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/1")
+            .reply(&routes)
+            .await;
+        let body: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(body["title"], "with answers");
+        assert_eq!(body["answer_count"], 1);
+        assert!(body.get("answers").is_none());
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/9")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn answer_count_covers_replies_and_only_this_question() {
+        let store = MemoryStore::new();
+        seed(&store, &["discussed", "quiet"]).await;
+        for (content, parent) in [("An answer", None), ("A reply", Some(AnswerId(1)))] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(1),
+                        parent_answer_id: parent,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(2),
+                )
+                .await
+                .unwrap();
+        }
+        let routes = build_routes(Config::default(), store).await;
+
+        for (path, count) in [("/questions/1", 2), ("/questions/2", 0)] {
+            let res = warp::test::request().path(path).reply(&routes).await;
+            assert_eq!(res.status(), 200);
+            let body: QuestionWithAnswerCount = serde_json::from_slice(res.body()).unwrap();
+            assert_eq!(body.answer_count, count, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn questions_stream_as_ndjson() {
+        let store = MemoryStore::new();
+        seed(&store, &["first", "second", "third"]).await;
+        let routes = build_routes(Config::default(), store).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/stream?limit=2")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.headers()["content-type"], "application/x-ndjson");
+
+        let body = std::str::from_utf8(res.body()).unwrap();
+        let questions: Vec<Question> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(questions.len(), 2);
+        assert_eq!(questions[0].title, "first");
+        assert_eq!(questions[1].title, "second");
+    }
+
+    #[tokio::test]
+    async fn archived_questions_are_left_out_of_the_stream() {
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "expired".to_string(),
+                    content: "Some content".to_string(),
+                    tags: None,
+                    expires_at: Some(Utc::now() - Duration::minutes(5)),
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        seed(&store, &["current"]).await;
+        assert_eq!(store.archive_expired_questions(Utc::now()).await.unwrap(), 1);
+        let routes = build_routes(Config::default(), store).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/stream")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let body = std::str::from_utf8(res.body()).unwrap();
+        let questions: Vec<Question> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(questions.len(), 1);
+        assert_eq!(questions[0].title, "current");
+    }
+
+    #[tokio::test]
+    async fn empty_listing_status_is_configurable() {
+        let routes = build_routes(Config::default(), MemoryStore::new()).await;
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(res.body(), "[]");
+
+        let config = Config {
+            empty_list_no_content: true,
+            ..Config::default()
+        };
+        let routes = build_routes(config, MemoryStore::new()).await;
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 204);
+        assert!(res.body().is_empty());
+    }
+
+    #[tokio::test]
+    async fn short_question_content_is_rejected_with_400() {
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "test@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/questions")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&serde_json::json!({ "title": "Short", "content": "   why?   " }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn question_posts_beyond_the_rate_limit_get_429() {
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            question_rate_limit: 1,
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "test@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, MemoryStore::new()).await;
+        let post = || {
+            warp::test::request()
+                .method("POST")
+                .path("/questions")
+                .header("Authorization", format!("Bearer {}", token))
+                .json(&serde_json::json!({ "title": "Spam", "content": "short" }))
+        };
+
+        // Every attempt counts, even one failing validation
+        assert_eq!(post().reply(&routes).await.status(), 400);
+
+        let res = post().reply(&routes).await;
+        assert_eq!(res.status(), 429);
+        assert_eq!(res.headers()["retry-after"], "3600");
+    }
+
+    #[tokio::test]
+    async fn unknown_content_format_is_rejected() {
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "test@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("POST")
+            .path("/questions")
+            .header("Authorization", format!("Bearer {}", token))
+            .json(&serde_json::json!({
+                "title": "Rendering",
+                "content": "How do I render this content?",
+                "content_format": "html",
+            }))
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn markdown_format_is_stored_and_returned() {
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Formatted".to_string(),
+                    content: "Some *emphasis*".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: Some("markdown".to_string()),
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+
+        let routes = build_routes(Config::default(), store).await;
+        let res = warp::test::request()
+            .method("GET")
+            .path("/questions/1")
+            .reply(&routes)
+            .await;
+        let question: Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(question["content_format"], "markdown");
+    }
+
+    #[tokio::test]
+    async fn updates_keep_the_format_unless_a_valid_one_is_given() {
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Formatted".to_string(),
+                    content: "Some *emphasis*".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: Some("markdown".to_string()),
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            disable_title_profanity_check: true,
+            disable_content_profanity_check: true,
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(1),
+            "test@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, store.clone()).await;
+        let put = |body: Value| {
+            warp::test::request()
+                .method("PUT")
+                .path("/questions/1")
+                .header("Authorization", format!("Bearer {}", token))
+                .json(&body)
+                .reply(&routes)
+        };
+
+        let res = put(json!({
+            "id": 1,
+            "title": "Formatted",
+            "content": "Some **strong emphasis**",
+        }))
+        .await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(store.get_question(1).await.unwrap().content_format, ContentFormat::Markdown);
+
+        let res = put(json!({
+            "id": 1,
+            "title": "Formatted",
+            "content": "Some **strong emphasis**",
+            "content_format": "html",
+        }))
+        .await;
+        assert_eq!(res.status(), 400);
+        assert_eq!(store.get_question(1).await.unwrap().content_format, ContentFormat::Markdown);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::types::account::Session;
+use crate::types::service::ServiceDescriptor;
+
+/// `GET /`: a short description of the service, for health checkers and
+/// anyone poking at the API by hand
+pub async fn get_root(config: Arc<Config>) -> Result<impl warp::Reply, warp::Rejection> {
+    let links = [
+        ("questions", "/questions"),
+        ("tags", "/tags"),
+        ("registration", "/registration"),
+        ("login", "/login"),
+    ]
+    .into_iter()
+    .map(|(rel, href)| (rel.to_string(), href.to_string()))
+    .collect::<BTreeMap<_, _>>();
+
+    Ok(warp::reply::json(&ServiceDescriptor {
+        name: config.service_name.clone(),
+        version: env!("RUST_WEB_DEV_VERSION").to_string(),
+        links,
+    }))
+}
+
+/// `GET /admin/config`: the settings this instance actually runs with,
+/// secrets redacted
+pub async fn get_config(
+    _session: Session,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&config.sanitized()))
+}
+
+#[cfg(test)]
+mod service_tests {
+    use crate::build_routes;
+    use crate::config::Config;
+    use crate::routes::authentication::issue_token;
+    use crate::store::MemoryStore;
+    use crate::types::account::AccountId;
+    use crate::types::service::ServiceDescriptor;
+
+    #[tokio::test]
+    async fn root_describes_the_service() {
+        let config = Config {
+            service_name: "Test Q&A".to_string(),
+            ..Config::default()
+        };
+        let routes = build_routes(config, MemoryStore::new()).await;
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+
+        let descriptor: ServiceDescriptor = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(descriptor.name, "Test Q&A");
+        assert_eq!(descriptor.version, env!("RUST_WEB_DEV_VERSION"));
+        assert_eq!(descriptor.links["questions"], "/questions");
+    }
+
+    #[tokio::test]
+    async fn admins_see_the_config_without_secrets() {
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            db_password: "hunter2-db-password".to_string(),
+            max_page_size: 25,
+            ..Config::default()
+        };
+        let keys = config.token_keys();
+        let routes = build_routes(config, MemoryStore::new()).await;
+        let get = |is_admin: bool| {
+            let token = issue_token(&keys, AccountId(7), "mod@email.com".to_string(), is_admin);
+            warp::test::request()
+                .method("GET")
+                .path("/admin/config")
+                .header("Authorization", format!("Bearer {}", token))
+                .reply(&routes)
+        };
+
+        assert_eq!(get(false).await.status(), 401);
+
+        let res = get(true).await;
+        assert_eq!(res.status(), 200);
+        let body = String::from_utf8_lossy(res.body()).to_string();
+        assert!(!body.contains("hunter2-db-password"), "{}", body);
+        assert!(!body.contains("MACINTOSH"), "{}", body);
+
+        let config: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(config["max_page_size"], 25);
+        assert_eq!(config["db_password"], "[redacted]");
+    }
+}
@@ -0,0 +1,153 @@
+use crate::store::Store;
+use crate::types::account::{AccountId, Session};
+
+/// System-wide totals, for admins only
+pub async fn get_stats<S: Store>(
+    _session: Session,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_stats().await {
+        Ok(stats) => Ok(warp::reply::json(&stats)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+/// Public activity counts for an account's profile. Only aggregates are
+/// exposed, so no authentication is needed.
+pub async fn get_account_stats<S: Store>(
+    account_id: i32,
+    store: S,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match store.get_account_stats(&AccountId(account_id)).await {
+        Ok(stats) => Ok(warp::reply::json(&stats)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use crate::build_routes;
+    use crate::config::Config;
+    use crate::routes::authentication::issue_token;
+    use crate::store::{MemoryStore, Store};
+    use crate::types::account::{Account, AccountId};
+    use crate::types::answer::NewAnswer;
+    use crate::types::question::{NewQuestion, QuestionId};
+    use crate::types::stats::{AccountStats, Stats};
+
+    #[tokio::test]
+    async fn admins_get_system_totals() {
+        unsafe {
+            std::env::set_var("PASETO_KEY", "RANDOM WORDS WINTER MACINTOSH PC");
+        }
+
+        let store = seeded_store().await;
+        let routes = build_routes(Config::default(), store).await;
+
+        let keys = Config::default().token_keys();
+        let admin = issue_token(&keys, AccountId(1), "a@email.com".to_string(), true);
+        let res = warp::test::request()
+            .method("GET")
+            .path("/stats")
+            .header("Authorization", admin)
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            serde_json::from_slice::<Stats>(res.body()).unwrap(),
+            Stats {
+                questions: 3,
+                answers: 4,
+                accounts: 2,
+            }
+        );
+
+        let user = issue_token(&keys, AccountId(2), "b@email.com".to_string(), false);
+        let res = warp::test::request()
+            .method("GET")
+            .path("/stats")
+            .header("Authorization", user)
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 401);
+    }
+
+    /// Two accounts: the first asked three questions, the second posted
+    /// four answers
+    async fn seeded_store() -> MemoryStore {
+        let store = MemoryStore::new();
+        for email in ["a@email.com", "b@email.com"] {
+            store
+                .add_account(Account {
+                    id: None,
+                    email: email.to_string(),
+                    password: "hash".to_string(),
+                    is_admin: false,
+                    verified: false,
+                })
+                .await
+                .unwrap();
+        }
+        for title in ["first", "second", "third"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Some content".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        for content in ["one", "two", "three", "four"] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(1),
+                        parent_answer_id: None,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(2),
+                )
+                .await
+                .unwrap();
+        }
+
+        store
+    }
+
+    #[tokio::test]
+    async fn account_stats_are_public() {
+        let routes = build_routes(Config::default(), seeded_store().await).await;
+
+        for (account_id, questions, answers) in [(1, 3, 0), (2, 0, 4)] {
+            let res = warp::test::request()
+                .method("GET")
+                .path(&format!("/accounts/{}/stats", account_id))
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), 200);
+            assert_eq!(
+                serde_json::from_slice::<AccountStats>(res.body()).unwrap(),
+                AccountStats {
+                    account_id: AccountId(account_id),
+                    questions,
+                    answers,
+                }
+            );
+        }
+
+        let res = warp::test::request()
+            .method("GET")
+            .path("/accounts/9/stats")
+            .reply(&routes)
+            .await;
+        assert_eq!(res.status(), 404);
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use warp::Reply;
+
+use crate::config::Config;
+use crate::store::Store;
+use crate::types::pagination::{Pagination, extract_pagination};
+
+/// Tags with their question counts, paged like `/questions`
+pub async fn get_tags<S: Store>(
+    params: HashMap<String, String>,
+    store: S,
+    config: Arc<Config>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut pagination = Pagination::default();
+
+    if params.contains_key("limit") || params.contains_key("offset") {
+        pagination = extract_pagination(params)?;
+    }
+    let pagination = pagination.clamp(config.max_page_size);
+
+    match store.get_tags(pagination.limit, pagination.offset).await {
+        Ok(tags) => {
+            let mut response = warp::reply::json(&tags).into_response();
+            pagination.add_headers(response.headers_mut());
+            Ok(response)
+        }
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+#[cfg(test)]
+mod tag_tests {
+    use std::collections::HashSet;
+
+    use crate::build_routes;
+    use crate::config::Config;
+    use crate::store::{MemoryStore, Store};
+    use crate::types::account::AccountId;
+    use crate::types::question::NewQuestion;
+    use crate::types::tag::TagCount;
+
+    #[tokio::test]
+    async fn paging_through_tags_is_stable() {
+        let store = MemoryStore::new();
+        for tags in [
+            vec!["rust", "warp"],
+            vec!["rust", "sqlx"],
+            vec!["rust", "async", "warp"],
+            vec!["tokio"],
+        ] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: "Tagged".to_string(),
+                        content: "Some content".to_string(),
+                        tags: Some(tags.into_iter().map(String::from).collect()),
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        let routes = build_routes(Config::default(), store).await;
+
+        let mut paged = Vec::new();
+        for offset in [0, 2, 4, 6] {
+            let res = warp::test::request()
+                .method("GET")
+                .path(&format!("/tags?limit=2&offset={}", offset))
+                .reply(&routes)
+                .await;
+            assert_eq!(res.status(), 200);
+            paged.extend(serde_json::from_slice::<Vec<TagCount>>(res.body()).unwrap());
+        }
+
+        let names: Vec<&str> = paged.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(names, vec!["rust", "warp", "async", "sqlx", "tokio"]);
+        let unique: HashSet<&str> = names.iter().copied().collect();
+        assert_eq!(unique.len(), names.len());
+        assert_eq!(paged[0].count, 3);
+    }
+}
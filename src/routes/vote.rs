@@ -0,0 +1,92 @@
+use crate::store::Store;
+use crate::types::account::Session;
+use crate::types::vote::{NewVote, VoteScore, VoteStatus};
+
+/// `POST /questions/:id/vote`: up- or downvote a question, replacing the
+/// account's earlier vote, and get the new score back
+pub async fn cast_vote<S: Store>(
+    question_id: i32,
+    session: Session,
+    store: S,
+    new_vote: NewVote,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    // Turns a vote on a missing question into a 404 rather than a foreign
+    // key violation
+    store.get_question(question_id).await?;
+    let score = store
+        .cast_vote(question_id, session.account_id, new_vote.vote)
+        .await?;
+
+    Ok(warp::reply::json(&VoteScore {
+        score,
+        my_vote: VoteStatus::from(Some(new_vote.vote)),
+    }))
+}
+
+#[cfg(test)]
+mod vote_tests {
+    use serde_json::json;
+
+    use crate::build_routes;
+    use crate::config::Config;
+    use crate::routes::authentication::issue_token;
+    use crate::store::{MemoryStore, Store};
+    use crate::types::account::AccountId;
+    use crate::types::question::NewQuestion;
+    use crate::types::vote::{Vote, VoteScore, VoteStatus};
+
+    #[tokio::test]
+    async fn vote_returns_the_updated_score() {
+        let store = MemoryStore::new();
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Popular".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        store.cast_vote(1, AccountId(2), Vote::Up).await.unwrap();
+        store.cast_vote(1, AccountId(3), Vote::Up).await.unwrap();
+
+        let config = Config {
+            paseto_keys: vec!["RANDOM WORDS WINTER MACINTOSH PC".to_string()],
+            ..Config::default()
+        };
+        let token = issue_token(
+            &config.token_keys(),
+            AccountId(4),
+            "me@email.com".to_string(),
+            false,
+        );
+        let routes = build_routes(config, store).await;
+        let vote = |vote: &'static str| {
+            warp::test::request()
+                .method("POST")
+                .path("/questions/1/vote")
+                .header("Authorization", format!("Bearer {}", token))
+                .json(&json!({ "vote": vote }))
+        };
+
+        let res = vote("up").reply(&routes).await;
+        assert_eq!(res.status(), 200);
+        assert_eq!(
+            serde_json::from_slice::<VoteScore>(res.body()).unwrap(),
+            VoteScore {
+                score: 3,
+                my_vote: VoteStatus::Up,
+            }
+        );
+
+        // Changing the vote replaces the earlier one
+        let res = vote("down").reply(&routes).await;
+        let score: VoteScore = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(score.score, 1);
+        assert_eq!(score.my_vote, VoteStatus::Down);
+    }
+}
@@ -0,0 +1,137 @@
+use handle_errors::Error;
+
+use crate::config::{Config, Profile};
+use crate::routes::authentication::hash_password;
+use crate::store::Store;
+use crate::types::account::Account;
+use crate::types::answer::NewAnswer;
+use crate::types::question::NewQuestion;
+
+const ACCOUNTS: &[&str] = &["alice@example.com", "bob@example.com"];
+
+/// (title, content, tags, answers)
+const QUESTIONS: &[(&str, &str, &[&str], &[&str])] = &[
+    (
+        "How do I share state between warp filters?",
+        "I want every handler to see the same database pool.",
+        &["rust", "warp"],
+        &["Clone it into a `warp::any().map(...)` filter."],
+    ),
+    (
+        "What does async-trait do?",
+        "Why can't I just write `async fn` in my trait?",
+        &["rust", "async"],
+        &[
+            "It boxes the returned futures so the trait stays object safe.",
+            "Recent compilers support async fn in traits natively.",
+        ],
+    ),
+];
+
+/// Password every sample account logs in with
+pub const SEED_PASSWORD: &str = "password";
+
+/// Fills an empty store with a few accounts, questions and answers so a
+/// development server has something to show. Returns whether anything was
+/// inserted; a store that already holds data and the prod profile are left
+/// alone.
+pub async fn seed<S: Store>(config: &Config, store: &S) -> Result<bool, Error> {
+    if config.profile == Profile::Prod {
+        tracing::warn!("Refusing to seed sample data in the prod profile");
+        return Ok(false);
+    }
+
+    let stats = store.get_stats().await?;
+    if stats.questions > 0 || stats.answers > 0 || stats.accounts > 0 {
+        tracing::info!("Store already holds data, skipping seed");
+        return Ok(false);
+    }
+
+    let mut account_ids = Vec::new();
+    for email in ACCOUNTS {
+        store
+            .add_account(Account {
+                id: None,
+                email: email.to_string(),
+                password: hash_password(SEED_PASSWORD.as_bytes(), config.password_algorithm)?,
+                is_admin: false,
+                verified: true,
+            })
+            .await?;
+        let account = store.get_account(email.to_string()).await?;
+        account_ids.push(account.id.expect("id not found"));
+    }
+
+    for (index, (title, content, tags, answers)) in QUESTIONS.iter().enumerate() {
+        let question = store
+            .add_question(
+                NewQuestion {
+                    title: title.to_string(),
+                    content: content.to_string(),
+                    tags: Some(tags.iter().map(|tag| tag.to_string()).collect()),
+                    expires_at: None,
+                    content_format: None,
+                },
+                account_ids[index % account_ids.len()].clone(),
+            )
+            .await?;
+
+        for (answer_index, answer) in answers.iter().enumerate() {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: answer.to_string(),
+                        question_id: question.id.clone(),
+                        parent_answer_id: None,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    account_ids[(index + answer_index + 1) % account_ids.len()].clone(),
+                )
+                .await?;
+        }
+    }
+
+    tracing::info!("Seeded sample data");
+    Ok(true)
+}
+
+#[cfg(test)]
+mod seed_tests {
+    use super::seed;
+    use crate::config::{Config, Profile};
+    use crate::store::{MemoryStore, Store};
+    use crate::types::stats::Stats;
+
+    #[tokio::test]
+    async fn seeding_fills_an_empty_store_once() {
+        let store = MemoryStore::new();
+        let config = Config::default();
+
+        assert!(seed(&config, &store).await.unwrap());
+        let stats = store.get_stats().await.unwrap();
+        assert_eq!(
+            stats,
+            Stats {
+                questions: 2,
+                answers: 3,
+                accounts: 2,
+            }
+        );
+
+        assert!(!seed(&config, &store).await.unwrap());
+        assert_eq!(store.get_stats().await.unwrap(), stats);
+    }
+
+    #[tokio::test]
+    async fn prod_profile_is_never_seeded() {
+        let store = MemoryStore::new();
+        let config = Config {
+            profile: Profile::Prod,
+            ..Config::default()
+        };
+
+        assert!(!seed(&config, &store).await.unwrap());
+        assert_eq!(store.get_stats().await.unwrap().accounts, 0);
+    }
+}
@@ -1,238 +1,372 @@
-use sqlx::Row;
-use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use std::collections::HashMap;
+use std::time::Duration;
 
-use crate::types::account::AccountId;
 use crate::types::{
-    account::Account,
-    answer::{Answer, AnswerId, NewAnswer},
-    question::{NewQuestion, Question, QuestionId},
+    account::{Account, AccountId, AccountProfile, DeletedContent, NotificationPreferences},
+    activity::Activity,
+    api_token::ApiToken,
+    answer::{Answer, NewAnswer},
+    feature_flag::FeatureFlag,
+    question::{NewQuestion, Question},
+    search::SearchTerm,
+    sort::{Sort, SortColumn, SortDirection},
+    stats::{AccountStats, Stats},
+    tag::{TagCount, TagFilter},
+    vote::Vote,
 };
 use handle_errors::Error;
 
-#[derive(Debug, Clone)]
-pub struct Store {
-    pub connection: PgPool,
+/// `Config::max_rows` default, used by stores that weren't given one
+pub const DEFAULT_MAX_ROWS: usize = 1000;
+
+/// `LIMIT` for a list query: the requested one, but never more than one
+/// row past `max_rows`, which lets `cap_rows` tell when the cap cut in
+pub(crate) fn row_limit(limit: Option<u32>, max_rows: usize) -> i64 {
+    let cap = max_rows as i64 + 1;
+    limit.map_or(cap, |limit| i64::from(limit).min(cap))
 }
 
-impl Store {
-    pub async fn new(db_url: &str) -> Result<Self, sqlx::Error> {
-        tracing::warn!("{}", db_url);
-        let db_pool = PgPoolOptions::new()
-            .max_connections(5)
-            .connect(db_url)
-            .await?;
-
-        Ok(Store {
-            connection: db_pool,
-        })
+/// Safety net under pagination: drops the rows past `max_rows`, with a
+/// warning naming the list `method` that ran into it
+pub(crate) fn cap_rows<T>(mut rows: Vec<T>, max_rows: usize, method: &str) -> Vec<T> {
+    if rows.len() > max_rows {
+        tracing::warn!(method, max_rows, "Row cap reached, results truncated");
+        rows.truncate(max_rows);
     }
+    rows
+}
+
+#[cfg(any(test, feature = "memory-store"))]
+mod memory;
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(any(test, feature = "memory-store"))]
+pub use memory::MemoryStore;
+pub use postgres::PgStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
 
-    pub async fn is_question_owner(
+/// Persistence used by the route handlers. `PgStore` is the production
+/// backend, `SqliteStore` (behind the `sqlite` feature) suits single-node
+/// deployments, and `MemoryStore` (behind the `memory-store` feature)
+/// keeps everything in process for tests and demos.
+#[async_trait]
+pub trait Store: std::fmt::Debug + Clone + Send + Sync + 'static {
+    async fn is_question_owner(
         &self,
         question_id: i32,
         account_id: &AccountId,
-    ) -> Result<bool, Error> {
-        match sqlx::query("SELECT * from questions where id = $1 and account_id = $2")
-            .bind(question_id)
-            .bind(account_id.0)
-            .fetch_optional(&self.connection)
-            .await
-        {
-            Ok(question) => Ok(question.is_some()),
-            Err(e) => {
-                tracing::event!(tracing::Level::ERROR, "{:?}", e);
-                Err(Error::DatabaseQueryError(e))
-            }
-        }
-    }
-    pub async fn get_questions(
+    ) -> Result<bool, Error>;
+
+    async fn question_created_on(&self, question_id: i32) -> Result<DateTime<Utc>, Error>;
+
+    /// When the question was last edited, or created if it never was
+    async fn question_modified_on(&self, question_id: i32) -> Result<DateTime<Utc>, Error>;
+
+    /// Archived questions are left out unless `include_archived` is set.
+    /// `tags` keeps only the questions it matches. Pinned questions come
+    /// first, each group in `sort` order.
+    async fn get_questions(
+        &self,
+        limit: Option<u32>,
+        offset: u32,
+        sort: Sort,
+        include_archived: bool,
+        tags: Option<TagFilter>,
+    ) -> Result<Vec<Question>, Error>;
+
+    /// Listed questions with the best net vote score first, the newest
+    /// first among equal scores; `get_questions` with `sort=top`
+    async fn get_questions_by_score(
         &self,
         limit: Option<u32>,
         offset: u32,
+        include_archived: bool,
+        tags: Option<TagFilter>,
     ) -> Result<Vec<Question>, Error> {
-        match sqlx::query("SELECT * FROM questions LIMIT $1 OFFSET $2")
-            .bind(limit)
-            .bind(offset)
-            .map(|row: PgRow| Question {
-                id: QuestionId(row.get("id")),
-                title: row.get("title"),
-                content: row.get("content"),
-                tags: row.get("tags"),
-            })
-            .fetch_all(&self.connection)
+        let sort = Sort {
+            column: SortColumn::Top,
+            direction: SortDirection::Desc,
+        };
+        self.get_questions(limit, offset, sort, include_archived, tags)
             .await
-        {
-            Ok(questions) => Ok(questions),
-            Err(error) => {
-                tracing::event!(tracing::Level::ERROR, "{:?}", error);
-                Err(Error::DatabaseQueryError(error))
-            }
-        }
     }
 
-    pub async fn add_question(
+    async fn get_question(&self, question_id: i32) -> Result<Question, Error>;
+
+    /// A listed question picked at random
+    async fn random_question(&self) -> Result<Question, Error>;
+
+    /// Number of listed questions whose title or content contains `term`
+    async fn count_search_results(&self, term: &SearchTerm) -> Result<i64, Error>;
+
+    /// All questions ordered by id, yielded one at a time so exports don't
+    /// have to hold the whole table in memory
+    async fn stream_questions(
+        &self,
+        limit: Option<u32>,
+    ) -> Result<BoxStream<'static, Result<Question, Error>>, Error>;
+
+    async fn add_question(
         &self,
         new_question: NewQuestion,
         account_id: AccountId,
-    ) -> Result<Question, Error> {
-        match sqlx::query(
-            "INSERT INTO questions (title, content, tags, account_id) 
-                VALUES ($1, $2, $3, $4) 
-                RETURNING id, title, content, tags",
-        )
-        .bind(new_question.title)
-        .bind(new_question.content)
-        .bind(new_question.tags)
-        .bind(account_id.0)
-        .map(|row: PgRow| Question {
-            id: QuestionId(row.get("id")),
-            title: row.get("title"),
-            content: row.get("content"),
-            tags: row.get("tags"),
-        })
-        .fetch_one(&self.connection)
-        .await
-        {
-            Ok(question) => Ok(question),
-            Err(error) => {
-                tracing::event!(tracing::Level::ERROR, "{:?}", error);
-                Err(Error::DatabaseQueryError(error))
-            }
-        }
-    }
+    ) -> Result<Question, Error>;
 
-    pub async fn update_question(
+    /// Changes title, content, tags and format; the expiry is kept as it was
+    async fn update_question(
         &self,
         question: Question,
         question_id: i32,
         account_id: AccountId,
-    ) -> Result<Question, Error> {
-        match sqlx::query(
-            "UPDATE questions SET 
-                title = $1, 
-                content = $2, 
-                tags = $3 
-            WHERE id = $4 AND account_id = $5
-            RETURNING id, title, content, tags",
-        )
-        .bind(question.title)
-        .bind(question.content)
-        .bind(question.tags)
-        .bind(question_id)
-        .bind(account_id.0)
-        .map(|row: PgRow| Question {
-            id: QuestionId(row.get("id")),
-            title: row.get("title"),
-            content: row.get("content"),
-            tags: row.get("tags"),
-        })
-        .fetch_one(&self.connection)
-        .await
-        {
-            Ok(question) => Ok(question),
-            Err(error) => {
-                tracing::event!(tracing::Level::ERROR, "{:?}", error);
-                Err(Error::DatabaseQueryError(error))
-            }
-        }
-    }
+    ) -> Result<Question, Error>;
+
+    /// Archive every question whose expiry has passed by `now`, returning
+    /// how many were archived
+    async fn archive_expired_questions(&self, now: DateTime<Utc>) -> Result<u64, Error>;
 
-    pub async fn delete_question(
+    async fn delete_question(
         &self,
         question_id: i32,
         account_id: AccountId,
-    ) -> Result<(), Error> {
-        match sqlx::query(
-            "DELETE FROM questions 
-            WHERE id = $1 AND account_id = $2",
-        )
-        .bind(question_id)
-        .bind(account_id.0)
-        .execute(&self.connection)
-        .await
-        {
-            Ok(_) => Ok(()),
-            Err(error) => {
-                tracing::event!(tracing::Level::ERROR, "{:?}", error);
-                Err(Error::DatabaseQueryError(error))
-            }
-        }
-    }
+    ) -> Result<(), Error>;
 
-    pub async fn add_answer(
+    /// Deletes the question whoever owns it, for moderators. Fails with
+    /// `QuestionNotFound` when there is no such question.
+    async fn remove_question(&self, question_id: i32) -> Result<(), Error>;
+
+    /// Pins the question to the top of `get_questions`, or unpins it. Fails
+    /// with `QuestionNotFound` when there is no such question.
+    async fn set_pinned(&self, question_id: i32, pinned: bool) -> Result<(), Error>;
+
+    async fn add_answer(
         &self,
         new_answer: NewAnswer,
         account_id: AccountId,
-    ) -> Result<Answer, Error> {
-        match sqlx::query(
-            "INSERT INTO answers (content, question_id, account_id) 
-                VALUES ($1, $2, $3) 
-                RETURNING id, content, question_id",
-        )
-        .bind(new_answer.content)
-        .bind(new_answer.question_id.0)
-        .bind(account_id.0)
-        .map(|row: PgRow| Answer {
-            id: AnswerId(row.get("id")),
-            content: row.get("content"),
-            question_id: QuestionId(row.get("question_id")),
-        })
-        .fetch_one(&self.connection)
-        .await
-        {
-            Ok(answer) => Ok(answer),
-            Err(error) => {
-                tracing::event!(tracing::Level::ERROR, "{:?}", error);
-                Err(Error::DatabaseQueryError(error))
-            }
-        }
-    }
+    ) -> Result<Answer, Error>;
 
-    pub async fn add_account(self, account: Account) -> Result<bool, Error> {
-        match sqlx::query(
-            "INSERT INTO accounts (email, password)
-            VALUES ($1, $2)",
-        )
-        .bind(account.email)
-        .bind(account.password)
-        .execute(&self.connection)
-        .await
-        {
-            Ok(_) => Ok(true),
-            Err(error) => {
-                tracing::event!(
-                    tracing::Level::ERROR,
-                    code = error
-                        .as_database_error()
-                        .unwrap()
-                        .code()
-                        .unwrap()
-                        .parse::<i32>()
-                        .unwrap(),
-                    db_message = error.as_database_error().unwrap().message(),
-                    constraint = error.as_database_error().unwrap().constraint().unwrap()
-                );
-                Err(Error::DatabaseQueryError(error))
-            }
-        }
-    }
+    async fn get_answer(&self, answer_id: i32) -> Result<Answer, Error>;
 
-    pub async fn get_account(&self, email: String) -> Result<Account, Error> {
-        match sqlx::query("SELECT * from accounts where email = $1")
-            .bind(email)
-            .map(|row: PgRow| Account {
-                id: Some(AccountId(row.get("id"))),
-                email: row.get("email"),
-                password: row.get("password"),
-            })
-            .fetch_one(&self.connection)
-            .await
-        {
-            Ok(account) => Ok(account),
-            Err(error) => {
-                tracing::event!(tracing::Level::ERROR, "{:?}", error);
-                Err(Error::DatabaseQueryError(error))
-            }
-        }
+    /// All answers to a question, replies included, oldest first. Not held
+    /// to `max_rows`: neither `GET /questions/:id/answers` nor
+    /// `?include=answers` pages, so capped answers couldn't be reached.
+    async fn get_answers(&self, question_id: i32) -> Result<Vec<Answer>, Error>;
+
+    /// The answers to each of the given questions, oldest first, fetched
+    /// in one go. Every id is in the map, with no answers if it has none.
+    async fn get_answers_for_questions(
+        &self,
+        question_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<Answer>>, Error>;
+
+    /// Forget the `client_id` of answers created before `before`, so it
+    /// can be sent with a new answer, returning how many were forgotten
+    async fn expire_client_ids(&self, before: DateTime<Utc>) -> Result<u64, Error>;
+
+    /// Number of answers to a question, replies included
+    async fn count_answers(&self, question_id: i32) -> Result<i64, Error>;
+
+    /// What happened to a question, oldest first: its creation, latest
+    /// edit, answers and votes
+    async fn get_question_activity(
+        &self,
+        question_id: i32,
+        limit: Option<u32>,
+        offset: u32,
+    ) -> Result<Vec<Activity>, Error>;
+
+    /// Moves an answer, and the replies below it, to another question. The
+    /// answer's parent stays behind, so it becomes a top-level answer.
+    /// Fails with `AnswerNotFound` or `QuestionNotFound` when either side
+    /// is missing.
+    async fn move_answer(&self, answer_id: i32, question_id: i32) -> Result<Answer, Error>;
+
+    /// Tags used on questions with their usage counts, most used first and
+    /// ties broken by name so pages are stable
+    async fn get_tags(&self, limit: Option<u32>, offset: u32) -> Result<Vec<TagCount>, Error>;
+
+    /// Records the account's vote on a question, replacing an earlier one,
+    /// and returns the question's score (upvotes minus downvotes) with the
+    /// vote counted
+    async fn cast_vote(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+        vote: Vote,
+    ) -> Result<i64, Error>;
+
+    /// The account's votes on the given questions, keyed by question id.
+    /// Questions the account hasn't voted on are missing from the map.
+    async fn get_votes(
+        &self,
+        account_id: &AccountId,
+        question_ids: &[i32],
+    ) -> Result<HashMap<i32, Vote>, Error>;
+
+    /// Every vote the account cast, keyed by question id
+    async fn get_account_votes(&self, account_id: &AccountId) -> Result<HashMap<i32, Vote>, Error>;
+
+    /// Saves the question for the account; bookmarking it again is a no-op
+    async fn add_bookmark(&self, question_id: i32, account_id: AccountId) -> Result<(), Error>;
+
+    /// Removes the account's bookmark, if there is one
+    async fn remove_bookmark(&self, question_id: i32, account_id: AccountId)
+        -> Result<(), Error>;
+
+    /// The questions the account bookmarked, most recently bookmarked first
+    async fn get_bookmarks(
+        &self,
+        account_id: &AccountId,
+        limit: Option<u32>,
+        offset: u32,
+    ) -> Result<Vec<Question>, Error>;
+
+    async fn add_account(&self, account: Account) -> Result<bool, Error>;
+
+    /// Like `add_account`, but fails with `RegistrationClosed` once
+    /// `max_accounts` accounts exist. Counting and inserting happen as one
+    /// step, so concurrent registrations can't overshoot. 0 means no cap.
+    async fn add_account_capped(&self, account: Account, max_accounts: u64) -> Result<bool, Error>;
+
+    /// Adds a row to the censorship audit trail
+    async fn record_censorship(
+        &self,
+        account_id: &AccountId,
+        resource: &str,
+        preview: &str,
+    ) -> Result<(), Error>;
+
+    /// Totals of questions, answers and accounts
+    async fn get_stats(&self) -> Result<Stats, Error>;
+
+    /// Counts of the account's listed questions and its answers. Fails
+    /// with `AccountNotFound` for unknown accounts.
+    async fn get_account_stats(&self, account_id: &AccountId) -> Result<AccountStats, Error>;
+
+    async fn get_account(&self, email: String) -> Result<Account, Error>;
+
+    async fn get_account_profile(&self, account_id: &AccountId) -> Result<AccountProfile, Error>;
+
+    async fn set_preferences(
+        &self,
+        account_id: &AccountId,
+        preferences: &NotificationPreferences,
+    ) -> Result<(), Error>;
+
+    /// Suspends the account until `until`, or lifts the suspension for
+    /// `None`. Fails with `AccountNotFound` for unknown accounts.
+    async fn suspend_account(
+        &self,
+        account_id: &AccountId,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<(), Error>;
+
+    /// When the account's suspension ends, `None` if it was never
+    /// suspended or doesn't exist
+    async fn get_suspension(&self, account_id: &AccountId) -> Result<Option<DateTime<Utc>>, Error>;
+
+    /// When the account was created, `None` if it predates that being
+    /// recorded or doesn't exist
+    async fn get_account_created_at(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<Option<DateTime<Utc>>, Error>;
+
+    /// Every question the account asked, archived ones included, by id
+    async fn get_account_questions(&self, account_id: &AccountId) -> Result<Vec<Question>, Error>;
+
+    /// Every answer the account posted, by id
+    async fn get_account_answers(&self, account_id: &AccountId) -> Result<Vec<Answer>, Error>;
+
+    /// Notes the time of a successful login and the address it came from
+    async fn record_login(&self, account_id: &AccountId, ip: Option<String>) -> Result<(), Error>;
+
+    /// Mark the account to be deleted once `due_at` has passed
+    async fn schedule_account_deletion(
+        &self,
+        account_id: &AccountId,
+        due_at: DateTime<Utc>,
+    ) -> Result<(), Error>;
+
+    /// Fails with `DeletionNotScheduled` if there was nothing to cancel
+    async fn cancel_account_deletion(&self, account_id: &AccountId) -> Result<(), Error>;
+
+    /// Remove the account and its bookmarks in one transaction, deleting
+    /// its questions and answers or handing them to `DELETED_ACCOUNT` as
+    /// `content` says. Fails with `AccountNotFound` for unknown accounts.
+    async fn delete_account(
+        &self,
+        account_id: &AccountId,
+        content: DeletedContent,
+    ) -> Result<(), Error>;
+
+    /// Remove every account whose deletion is due by `now`, as
+    /// `delete_account` does, returning how many went
+    async fn delete_due_accounts(
+        &self,
+        now: DateTime<Utc>,
+        content: DeletedContent,
+    ) -> Result<u64, Error>;
+
+    async fn add_api_token(
+        &self,
+        account_id: &AccountId,
+        name: String,
+        token_hash: String,
+    ) -> Result<ApiToken, Error>;
+
+    /// The account's API tokens, oldest first
+    async fn get_api_tokens(&self, account_id: &AccountId) -> Result<Vec<ApiToken>, Error>;
+
+    /// Revokes one of the account's API tokens, `Error::ApiTokenNotFound`
+    /// when it has no token with that id
+    async fn delete_api_token(&self, token_id: i32, account_id: &AccountId) -> Result<(), Error>;
+
+    /// The account owning the API token with the given hash, if any
+    async fn get_api_token_account(&self, token_hash: &str) -> Result<Option<Account>, Error>;
+
+    /// Stores the hash of a token that verifies the account's email until
+    /// `ttl` passes
+    async fn add_verification_token(
+        &self,
+        account_id: &AccountId,
+        token_hash: &str,
+        ttl: Duration,
+    ) -> Result<(), Error>;
+
+    /// Marks the account the token hash was issued for as verified and uses
+    /// the token up. Unknown and expired tokens give
+    /// `Error::InvalidVerificationToken`.
+    async fn verify_account(&self, token_hash: &str) -> Result<(), Error>;
+
+    /// Stores the hash of a password reset token, valid until `ttl` passes
+    async fn add_password_reset_token(
+        &self,
+        account_id: &AccountId,
+        token_hash: &str,
+        ttl: Duration,
+    ) -> Result<(), Error>;
+
+    /// Replaces the password of the account the token was issued for and
+    /// uses the token up. Fails with `InvalidResetToken` for unknown,
+    /// expired or already used tokens.
+    async fn reset_password(&self, token_hash: &str, password_hash: &str) -> Result<(), Error>;
+
+    /// The stored value of a feature flag, `None` when it was never set
+    async fn get_flag(&self, name: &str) -> Result<Option<bool>, Error>;
+
+    async fn set_flag(&self, name: &str, enabled: bool) -> Result<(), Error>;
+
+    /// `get_flag`, falling back to the flag's default
+    async fn flag_enabled(&self, flag: FeatureFlag) -> Result<bool, Error> {
+        Ok(self
+            .get_flag(flag.name())
+            .await?
+            .unwrap_or(flag.default_value()))
     }
 }
@@ -0,0 +1,1250 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+use rand::seq::IteratorRandom;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use super::{DEFAULT_MAX_ROWS, Store, cap_rows, row_limit};
+use crate::types::{
+    account::{
+        Account, AccountId, AccountProfile, DELETED_ACCOUNT, DeletedContent,
+        NotificationPreferences,
+    },
+    activity::{Activity, ActivityKind},
+    api_token::{ApiToken, ApiTokenId},
+    answer::{Answer, AnswerId, NewAnswer, normalized_content},
+    question::{NewQuestion, Question, QuestionId},
+    search::SearchTerm,
+    sort::{Sort, SortColumn, SortDirection},
+    stats::{AccountStats, Stats},
+    tag::{TagCount, TagFilter},
+    vote::Vote,
+};
+use handle_errors::Error;
+
+#[derive(Debug, Clone)]
+struct QuestionRow {
+    question: Question,
+    account_id: AccountId,
+    created_on: DateTime<Utc>,
+    updated_on: Option<DateTime<Utc>>,
+    archived: bool,
+    pinned: bool,
+}
+
+#[derive(Debug, Clone)]
+struct AnswerRow {
+    answer: Answer,
+    account_id: AccountId,
+    client_id: Option<String>,
+    created_on: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct ApiTokenRow {
+    api_token: ApiToken,
+    account_id: AccountId,
+    token_hash: String,
+}
+
+/// Mirrors the Postgres tables, including their serial id counters
+#[derive(Debug, Default)]
+struct Tables {
+    questions: BTreeMap<i32, QuestionRow>,
+    answers: BTreeMap<i32, AnswerRow>,
+    accounts: Vec<Account>,
+    /// Keyed by (account id, question id), the votes table's primary key
+    votes: HashMap<(i32, i32), Vote>,
+    /// Same keys as `votes`, when the account first voted
+    vote_times: HashMap<(i32, i32), DateTime<Utc>>,
+    /// Keyed by (account id, question id), when the question was bookmarked
+    bookmarks: HashMap<(i32, i32), DateTime<Utc>>,
+    api_tokens: BTreeMap<i32, ApiTokenRow>,
+    /// Account id -> (last login, address it came from)
+    logins: HashMap<i32, (DateTime<Utc>, Option<String>)>,
+    /// Account id -> when its scheduled deletion is due
+    deletions: HashMap<i32, DateTime<Utc>>,
+    /// Account id -> when its suspension ends
+    suspensions: HashMap<i32, DateTime<Utc>>,
+    /// Account id -> when it was created
+    account_created: HashMap<i32, DateTime<Utc>>,
+    /// Account id -> preferences, for accounts that changed theirs
+    preferences: HashMap<i32, NotificationPreferences>,
+    /// (account id, resource, preview) in the order they were censored
+    censorship_audit: Vec<(i32, String, String)>,
+    /// Verification token -> (account id, expiry)
+    verifications: HashMap<String, (i32, SystemTime)>,
+    /// Password reset token hash -> (account id, expiry)
+    password_resets: HashMap<String, (i32, SystemTime)>,
+    /// Feature flag name -> whether it is on, for flags that were set
+    flags: HashMap<String, bool>,
+    question_seq: i32,
+    answer_seq: i32,
+    account_seq: i32,
+    api_token_seq: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    tables: Arc<RwLock<Tables>>,
+    max_rows: usize,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore {
+            tables: Arc::default(),
+            max_rows: DEFAULT_MAX_ROWS,
+        }
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Caps what list methods return, see `cap_rows`
+    pub fn with_max_rows(self, max_rows: usize) -> Self {
+        MemoryStore { max_rows, ..self }
+    }
+}
+
+fn row_not_found() -> Error {
+    Error::DatabaseQueryError(sqlx::Error::RowNotFound)
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn is_question_owner(
+        &self,
+        question_id: i32,
+        account_id: &AccountId,
+    ) -> Result<bool, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .questions
+            .get(&question_id)
+            .is_some_and(|row| &row.account_id == account_id))
+    }
+
+    async fn question_created_on(&self, question_id: i32) -> Result<DateTime<Utc>, Error> {
+        let tables = self.tables.read().unwrap();
+        tables
+            .questions
+            .get(&question_id)
+            .map(|row| row.created_on)
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn question_modified_on(&self, question_id: i32) -> Result<DateTime<Utc>, Error> {
+        let tables = self.tables.read().unwrap();
+        tables
+            .questions
+            .get(&question_id)
+            .map(|row| row.updated_on.unwrap_or(row.created_on))
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn get_questions(
+        &self,
+        limit: Option<u32>,
+        offset: u32,
+        sort: Sort,
+        include_archived: bool,
+        tags: Option<TagFilter>,
+    ) -> Result<Vec<Question>, Error> {
+        let tables = self.tables.read().unwrap();
+        let mut questions: Vec<Question> = tables
+            .questions
+            .values()
+            .filter(|row| include_archived || !row.archived)
+            .filter(|row| {
+                tags.as_ref().is_none_or(|filter| {
+                    filter.matches(row.question.tags.as_deref().unwrap_or_default())
+                })
+            })
+            .map(|row| row.question.clone())
+            .collect();
+
+        // Ids are handed out in insertion order, so they double as the
+        // creation timestamp here
+        match sort.column {
+            SortColumn::Id | SortColumn::CreatedOn => questions.sort_by_key(|q| q.id.0),
+            SortColumn::Title => questions.sort_by(|a, b| a.title.cmp(&b.title)),
+            SortColumn::Answers | SortColumn::Top => {
+                let mut counts: HashMap<i32, i64> = HashMap::new();
+                if sort.column == SortColumn::Answers {
+                    for row in tables.answers.values() {
+                        *counts.entry(row.answer.question_id.0).or_default() += 1;
+                    }
+                } else {
+                    for ((_, question_id), vote) in &tables.votes {
+                        *counts.entry(*question_id).or_default() += i64::from(vote.value());
+                    }
+                }
+                let count = |q: &Question| counts.get(&q.id.0).copied().unwrap_or(0);
+                // Newest first within equal counts, whatever the direction
+                questions.sort_by(|a, b| {
+                    let by_count = count(a).cmp(&count(b));
+                    let by_count = match sort.direction {
+                        SortDirection::Asc => by_count,
+                        SortDirection::Desc => by_count.reverse(),
+                    };
+                    by_count.then(b.id.0.cmp(&a.id.0))
+                });
+            }
+        }
+        if sort.direction == SortDirection::Desc
+            && !matches!(sort.column, SortColumn::Answers | SortColumn::Top)
+        {
+            questions.reverse();
+        }
+        // Stable, so each group keeps the order above
+        questions.sort_by_key(|q| !tables.questions[&q.id.0].pinned);
+
+        let questions = questions
+            .into_iter()
+            .skip(offset as usize)
+            .take(row_limit(limit, self.max_rows) as usize)
+            .collect();
+        Ok(cap_rows(questions, self.max_rows, "get_questions"))
+    }
+
+    async fn get_question(&self, question_id: i32) -> Result<Question, Error> {
+        let tables = self.tables.read().unwrap();
+        tables
+            .questions
+            .get(&question_id)
+            .map(|row| row.question.clone())
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn random_question(&self) -> Result<Question, Error> {
+        let tables = self.tables.read().unwrap();
+        tables
+            .questions
+            .values()
+            .filter(|row| !row.archived)
+            .choose(&mut rand::thread_rng())
+            .map(|row| row.question.clone())
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn count_search_results(&self, term: &SearchTerm) -> Result<i64, Error> {
+        let tables = self.tables.read().unwrap();
+        let term = term.as_str().to_lowercase();
+        Ok(tables
+            .questions
+            .values()
+            .filter(|row| !row.archived)
+            .filter(|row| {
+                row.question.title.to_lowercase().contains(&term)
+                    || row.question.content.to_lowercase().contains(&term)
+            })
+            .count() as i64)
+    }
+
+    async fn stream_questions(
+        &self,
+        limit: Option<u32>,
+    ) -> Result<BoxStream<'static, Result<Question, Error>>, Error> {
+        let tables = self.tables.read().unwrap();
+        let questions: Vec<Result<Question, Error>> = tables
+            .questions
+            .values()
+            .filter(|row| !row.archived)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .map(|row| Ok(row.question.clone()))
+            .collect();
+
+        Ok(stream::iter(questions).boxed())
+    }
+
+    async fn add_question(
+        &self,
+        new_question: NewQuestion,
+        account_id: AccountId,
+    ) -> Result<Question, Error> {
+        let content_format = new_question.format();
+        let mut tables = self.tables.write().unwrap();
+        tables.question_seq += 1;
+        let question = Question {
+            id: QuestionId(tables.question_seq),
+            title: new_question.title,
+            content: new_question.content,
+            tags: new_question.tags,
+            expires_at: new_question.expires_at,
+            content_format,
+        };
+        tables.questions.insert(
+            question.id.0,
+            QuestionRow {
+                question: question.clone(),
+                account_id,
+                created_on: Utc::now(),
+                updated_on: None,
+                archived: false,
+                pinned: false,
+            },
+        );
+
+        Ok(question)
+    }
+
+    async fn update_question(
+        &self,
+        question: Question,
+        question_id: i32,
+        account_id: AccountId,
+    ) -> Result<Question, Error> {
+        let mut tables = self.tables.write().unwrap();
+        match tables.questions.get_mut(&question_id) {
+            Some(row) if row.account_id == account_id => {
+                row.question.title = question.title;
+                row.question.content = question.content;
+                row.question.tags = question.tags;
+                row.question.content_format = question.content_format;
+                row.updated_on = Some(Utc::now());
+                Ok(row.question.clone())
+            }
+            _ => Err(row_not_found()),
+        }
+    }
+
+    async fn archive_expired_questions(&self, now: DateTime<Utc>) -> Result<u64, Error> {
+        let mut tables = self.tables.write().unwrap();
+        let mut archived = 0;
+        for row in tables.questions.values_mut() {
+            if !row.archived && row.question.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                row.archived = true;
+                archived += 1;
+            }
+        }
+
+        Ok(archived)
+    }
+
+    async fn delete_question(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        if tables
+            .questions
+            .get(&question_id)
+            .is_some_and(|row| row.account_id == account_id)
+        {
+            tables.questions.remove(&question_id);
+        }
+
+        Ok(())
+    }
+
+    async fn remove_question(&self, question_id: i32) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        match tables.questions.remove(&question_id) {
+            Some(_) => Ok(()),
+            None => Err(Error::QuestionNotFound),
+        }
+    }
+
+    async fn set_pinned(&self, question_id: i32, pinned: bool) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        match tables.questions.get_mut(&question_id) {
+            Some(row) => {
+                row.pinned = pinned;
+                Ok(())
+            }
+            None => Err(Error::QuestionNotFound),
+        }
+    }
+
+    async fn add_answer(
+        &self,
+        new_answer: NewAnswer,
+        account_id: AccountId,
+    ) -> Result<Answer, Error> {
+        let mut tables = self.tables.write().unwrap();
+        if !tables.questions.contains_key(&new_answer.question_id.0) {
+            return Err(row_not_found());
+        }
+
+        if let Some(client_id) = &new_answer.client_id {
+            let existing = tables
+                .answers
+                .values()
+                .find(|row| {
+                    row.account_id == account_id && row.client_id.as_ref() == Some(client_id)
+                });
+            if let Some(row) = existing {
+                return Ok(row.answer.clone());
+            }
+        }
+
+        let content = normalized_content(&new_answer.content);
+        let duplicate = tables.answers.values().any(|row| {
+            row.account_id == account_id
+                && row.answer.question_id == new_answer.question_id
+                && normalized_content(&row.answer.content) == content
+        });
+        if duplicate {
+            return Err(Error::DuplicateAnswer);
+        }
+
+        tables.answer_seq += 1;
+        let answer = Answer {
+            id: AnswerId(tables.answer_seq),
+            content: new_answer.content,
+            question_id: new_answer.question_id,
+            parent_answer_id: new_answer.parent_answer_id,
+            attachments: new_answer.attachments,
+        };
+        tables.answers.insert(
+            answer.id.0,
+            AnswerRow {
+                answer: answer.clone(),
+                account_id,
+                client_id: new_answer.client_id,
+                created_on: Utc::now(),
+            },
+        );
+
+        Ok(answer)
+    }
+
+    async fn get_answer(&self, answer_id: i32) -> Result<Answer, Error> {
+        let tables = self.tables.read().unwrap();
+        tables
+            .answers
+            .get(&answer_id)
+            .map(|row| row.answer.clone())
+            .ok_or(Error::AnswerNotFound)
+    }
+
+    async fn get_answers(&self, question_id: i32) -> Result<Vec<Answer>, Error> {
+        let tables = self.tables.read().unwrap();
+        let answers = tables
+            .answers
+            .values()
+            .filter(|row| row.answer.question_id.0 == question_id)
+            .map(|row| row.answer.clone())
+            .collect();
+        Ok(answers)
+    }
+
+    async fn get_answers_for_questions(
+        &self,
+        question_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<Answer>>, Error> {
+        let tables = self.tables.read().unwrap();
+        let mut grouped: HashMap<i32, Vec<Answer>> =
+            question_ids.iter().map(|id| (*id, Vec::new())).collect();
+        for row in tables.answers.values() {
+            if let Some(answers) = grouped.get_mut(&row.answer.question_id.0) {
+                answers.push(row.answer.clone());
+            }
+        }
+        Ok(grouped)
+    }
+
+    async fn expire_client_ids(&self, before: DateTime<Utc>) -> Result<u64, Error> {
+        let mut tables = self.tables.write().unwrap();
+        let mut expired = 0;
+        for row in tables.answers.values_mut() {
+            if row.created_on < before && row.client_id.take().is_some() {
+                expired += 1;
+            }
+        }
+        Ok(expired)
+    }
+
+    async fn count_answers(&self, question_id: i32) -> Result<i64, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .answers
+            .values()
+            .filter(|row| row.answer.question_id.0 == question_id)
+            .count() as i64)
+    }
+
+    async fn get_question_activity(
+        &self,
+        question_id: i32,
+        limit: Option<u32>,
+        offset: u32,
+    ) -> Result<Vec<Activity>, Error> {
+        let tables = self.tables.read().unwrap();
+        let Some(question) = tables.questions.get(&question_id) else {
+            return Ok(Vec::new());
+        };
+        let event = |kind, at, answer_id| Activity {
+            kind,
+            at,
+            answer_id,
+        };
+
+        let mut activity = vec![event(ActivityKind::Created, question.created_on, None)];
+        if let Some(updated_on) = question.updated_on {
+            activity.push(event(ActivityKind::Edited, updated_on, None));
+        }
+        activity.extend(
+            tables
+                .answers
+                .values()
+                .filter(|row| row.answer.question_id.0 == question_id)
+                .map(|row| event(ActivityKind::Answered, row.created_on, Some(row.answer.id.clone()))),
+        );
+        activity.extend(
+            tables
+                .vote_times
+                .iter()
+                .filter(|((_, voted_on), _)| *voted_on == question_id)
+                .map(|(_, at)| event(ActivityKind::Voted, *at, None)),
+        );
+        activity.sort_by_key(|event| (event.at, event.answer_id.as_ref().map(|id| id.0)));
+
+        let activity = activity
+            .into_iter()
+            .skip(offset as usize)
+            .take(row_limit(limit, self.max_rows) as usize)
+            .collect();
+        Ok(cap_rows(activity, self.max_rows, "get_question_activity"))
+    }
+
+    async fn move_answer(&self, answer_id: i32, question_id: i32) -> Result<Answer, Error> {
+        let mut tables = self.tables.write().unwrap();
+        if !tables.questions.contains_key(&question_id) {
+            return Err(Error::QuestionNotFound);
+        }
+        if !tables.answers.contains_key(&answer_id) {
+            return Err(Error::AnswerNotFound);
+        }
+
+        // Replies always have higher ids than the answer they reply to
+        let mut thread = vec![answer_id];
+        for row in tables.answers.range(answer_id + 1..).map(|(_, row)| row) {
+            if let Some(parent) = &row.answer.parent_answer_id
+                && thread.contains(&parent.0)
+            {
+                thread.push(row.answer.id.0);
+            }
+        }
+
+        let duplicate = thread.iter().any(|id| {
+            let moved = &tables.answers[id];
+            let content = normalized_content(&moved.answer.content);
+            tables.answers.values().any(|row| {
+                row.account_id == moved.account_id
+                    && row.answer.question_id.0 == question_id
+                    && normalized_content(&row.answer.content) == content
+            })
+        });
+        if duplicate {
+            return Err(Error::DuplicateAnswer);
+        }
+
+        for id in &thread {
+            let row = tables.answers.get_mut(id).unwrap();
+            row.answer.question_id = QuestionId(question_id);
+            if *id == answer_id {
+                row.answer.parent_answer_id = None;
+            }
+        }
+
+        Ok(tables.answers[&answer_id].answer.clone())
+    }
+
+    async fn get_tags(&self, limit: Option<u32>, offset: u32) -> Result<Vec<TagCount>, Error> {
+        let tables = self.tables.read().unwrap();
+        let mut counts: HashMap<&str, i64> = HashMap::new();
+        for tag in tables
+            .questions
+            .values()
+            .filter_map(|row| row.question.tags.as_ref())
+            .flatten()
+        {
+            *counts.entry(tag.as_str()).or_default() += 1;
+        }
+
+        let mut tags: Vec<TagCount> = counts
+            .into_iter()
+            .map(|(name, count)| TagCount {
+                name: name.to_string(),
+                count,
+            })
+            .collect();
+        tags.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+
+        let tags = tags
+            .into_iter()
+            .skip(offset as usize)
+            .take(row_limit(limit, self.max_rows) as usize)
+            .collect();
+        Ok(cap_rows(tags, self.max_rows, "get_tags"))
+    }
+
+    async fn cast_vote(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+        vote: Vote,
+    ) -> Result<i64, Error> {
+        let mut tables = self.tables.write().unwrap();
+        if !tables.questions.contains_key(&question_id) {
+            return Err(row_not_found());
+        }
+        tables.votes.insert((account_id.0, question_id), vote);
+        tables
+            .vote_times
+            .entry((account_id.0, question_id))
+            .or_insert_with(Utc::now);
+
+        Ok(tables
+            .votes
+            .iter()
+            .filter(|((_, voted_on), _)| *voted_on == question_id)
+            .map(|(_, vote)| i64::from(vote.value()))
+            .sum())
+    }
+
+    async fn get_votes(
+        &self,
+        account_id: &AccountId,
+        question_ids: &[i32],
+    ) -> Result<HashMap<i32, Vote>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(question_ids
+            .iter()
+            .filter_map(|id| {
+                tables
+                    .votes
+                    .get(&(account_id.0, *id))
+                    .map(|vote| (*id, *vote))
+            })
+            .collect())
+    }
+
+    async fn get_account_votes(&self, account_id: &AccountId) -> Result<HashMap<i32, Vote>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .votes
+            .iter()
+            .filter(|((voter, _), _)| *voter == account_id.0)
+            .map(|((_, question_id), vote)| (*question_id, *vote))
+            .collect())
+    }
+
+    async fn add_bookmark(&self, question_id: i32, account_id: AccountId) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        if !tables.questions.contains_key(&question_id) {
+            return Err(row_not_found());
+        }
+        tables
+            .bookmarks
+            .entry((account_id.0, question_id))
+            .or_insert_with(Utc::now);
+
+        Ok(())
+    }
+
+    async fn remove_bookmark(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables.bookmarks.remove(&(account_id.0, question_id));
+
+        Ok(())
+    }
+
+    async fn get_bookmarks(
+        &self,
+        account_id: &AccountId,
+        limit: Option<u32>,
+        offset: u32,
+    ) -> Result<Vec<Question>, Error> {
+        let tables = self.tables.read().unwrap();
+        // Bookmarks of deleted questions are left behind; the join drops them
+        let mut bookmarked: Vec<(DateTime<Utc>, &QuestionRow)> = tables
+            .bookmarks
+            .iter()
+            .filter(|((bookmarked_by, _), _)| *bookmarked_by == account_id.0)
+            .filter_map(|((_, question_id), at)| {
+                tables.questions.get(question_id).map(|row| (*at, row))
+            })
+            .collect();
+        bookmarked.sort_by(|(a_at, a), (b_at, b)| {
+            b_at.cmp(a_at).then(b.question.id.0.cmp(&a.question.id.0))
+        });
+
+        let questions = bookmarked
+            .into_iter()
+            .skip(offset as usize)
+            .take(row_limit(limit, self.max_rows) as usize)
+            .map(|(_, row)| row.question.clone())
+            .collect();
+        Ok(cap_rows(questions, self.max_rows, "get_bookmarks"))
+    }
+
+    async fn add_account(&self, account: Account) -> Result<bool, Error> {
+        self.add_account_capped(account, 0).await
+    }
+
+    async fn add_account_capped(
+        &self,
+        account: Account,
+        max_accounts: u64,
+    ) -> Result<bool, Error> {
+        let mut tables = self.tables.write().unwrap();
+        if max_accounts > 0 && tables.accounts.len() as u64 >= max_accounts {
+            return Err(Error::RegistrationClosed);
+        }
+        if tables.accounts.iter().any(|a| a.email == account.email) {
+            return Err(Error::DuplicateAccount);
+        }
+
+        tables.account_seq += 1;
+        let id = AccountId(tables.account_seq);
+        tables.account_created.insert(id.0, Utc::now());
+        tables.accounts.push(Account {
+            id: Some(id),
+            ..account
+        });
+
+        Ok(true)
+    }
+
+    async fn get_stats(&self) -> Result<Stats, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(Stats {
+            questions: tables.questions.len() as i64,
+            answers: tables.answers.len() as i64,
+            accounts: tables.accounts.len() as i64,
+        })
+    }
+
+    async fn get_account_stats(&self, account_id: &AccountId) -> Result<AccountStats, Error> {
+        let tables = self.tables.read().unwrap();
+        if !tables.accounts.iter().any(|a| a.id.as_ref() == Some(account_id)) {
+            return Err(Error::AccountNotFound);
+        }
+
+        Ok(AccountStats {
+            account_id: account_id.clone(),
+            questions: tables
+                .questions
+                .values()
+                .filter(|row| row.account_id == *account_id && !row.archived)
+                .count() as i64,
+            answers: tables
+                .answers
+                .values()
+                .filter(|row| row.account_id == *account_id)
+                .count() as i64,
+        })
+    }
+
+    async fn get_account(&self, email: String) -> Result<Account, Error> {
+        let tables = self.tables.read().unwrap();
+        tables
+            .accounts
+            .iter()
+            .find(|account| account.email == email)
+            .cloned()
+            .ok_or_else(row_not_found)
+    }
+
+    async fn get_account_profile(&self, account_id: &AccountId) -> Result<AccountProfile, Error> {
+        let tables = self.tables.read().unwrap();
+        let account = tables
+            .accounts
+            .iter()
+            .find(|account| account.id.as_ref() == Some(account_id))
+            .ok_or_else(row_not_found)?;
+        let login = tables.logins.get(&account_id.0);
+
+        Ok(AccountProfile {
+            id: account_id.clone(),
+            email: account.email.clone(),
+            is_admin: account.is_admin,
+            verified: account.verified,
+            last_login_at: login.map(|(at, _)| *at),
+            last_login_ip: login.and_then(|(_, ip)| ip.clone()),
+            deletion_due_at: tables.deletions.get(&account_id.0).copied(),
+            suspended_until: tables.suspensions.get(&account_id.0).copied(),
+            preferences: tables
+                .preferences
+                .get(&account_id.0)
+                .copied()
+                .unwrap_or_default(),
+        })
+    }
+
+    async fn record_censorship(
+        &self,
+        account_id: &AccountId,
+        resource: &str,
+        preview: &str,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables
+            .censorship_audit
+            .push((account_id.0, resource.to_string(), preview.to_string()));
+
+        Ok(())
+    }
+
+    async fn get_account_questions(&self, account_id: &AccountId) -> Result<Vec<Question>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .questions
+            .values()
+            .filter(|row| &row.account_id == account_id)
+            .map(|row| row.question.clone())
+            .collect())
+    }
+
+    async fn get_account_answers(&self, account_id: &AccountId) -> Result<Vec<Answer>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .answers
+            .values()
+            .filter(|row| &row.account_id == account_id)
+            .map(|row| row.answer.clone())
+            .collect())
+    }
+
+    async fn set_preferences(
+        &self,
+        account_id: &AccountId,
+        preferences: &NotificationPreferences,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables.preferences.insert(account_id.0, *preferences);
+
+        Ok(())
+    }
+
+    async fn suspend_account(
+        &self,
+        account_id: &AccountId,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        if !tables.accounts.iter().any(|account| account.id.as_ref() == Some(account_id)) {
+            return Err(Error::AccountNotFound);
+        }
+        match until {
+            Some(until) => tables.suspensions.insert(account_id.0, until),
+            None => tables.suspensions.remove(&account_id.0),
+        };
+
+        Ok(())
+    }
+
+    async fn get_suspension(&self, account_id: &AccountId) -> Result<Option<DateTime<Utc>>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables.suspensions.get(&account_id.0).copied())
+    }
+
+    async fn get_account_created_at(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<Option<DateTime<Utc>>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables.account_created.get(&account_id.0).copied())
+    }
+
+    async fn record_login(&self, account_id: &AccountId, ip: Option<String>) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables.logins.insert(account_id.0, (Utc::now(), ip));
+
+        Ok(())
+    }
+
+    async fn schedule_account_deletion(
+        &self,
+        account_id: &AccountId,
+        due_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables.deletions.insert(account_id.0, due_at);
+
+        Ok(())
+    }
+
+    async fn cancel_account_deletion(&self, account_id: &AccountId) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        match tables.deletions.remove(&account_id.0) {
+            Some(_) => Ok(()),
+            None => Err(Error::DeletionNotScheduled),
+        }
+    }
+
+    async fn delete_account(
+        &self,
+        account_id: &AccountId,
+        content: DeletedContent,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        if !tables.accounts.iter().any(|a| a.id.as_ref() == Some(account_id)) {
+            return Err(Error::AccountNotFound);
+        }
+
+        match content {
+            DeletedContent::Delete => {
+                let questions: Vec<i32> = tables
+                    .questions
+                    .iter()
+                    .filter(|(_, row)| row.account_id == *account_id)
+                    .map(|(id, _)| *id)
+                    .collect();
+                let mut answers: HashSet<i32> = tables
+                    .answers
+                    .iter()
+                    .filter(|(_, row)| {
+                        row.account_id == *account_id
+                            || questions.contains(&row.answer.question_id.0)
+                    })
+                    .map(|(id, _)| *id)
+                    .collect();
+                // Replies to removed answers, as parent_answer_id's
+                // ON DELETE CASCADE does in Postgres
+                loop {
+                    let replies: Vec<i32> = tables
+                        .answers
+                        .iter()
+                        .filter(|(id, row)| {
+                            !answers.contains(*id)
+                                && row
+                                    .answer
+                                    .parent_answer_id
+                                    .as_ref()
+                                    .is_some_and(|parent| answers.contains(&parent.0))
+                        })
+                        .map(|(id, _)| *id)
+                        .collect();
+                    if replies.is_empty() {
+                        break;
+                    }
+                    answers.extend(replies);
+                }
+
+                tables.answers.retain(|id, _| !answers.contains(id));
+                tables.questions.retain(|id, _| !questions.contains(id));
+                tables.votes.retain(|(_, id), _| !questions.contains(id));
+                tables.vote_times.retain(|(_, id), _| !questions.contains(id));
+                tables.bookmarks.retain(|(_, id), _| !questions.contains(id));
+            }
+            DeletedContent::Anonymize => {
+                for row in tables.questions.values_mut() {
+                    if row.account_id == *account_id {
+                        row.account_id = DELETED_ACCOUNT;
+                    }
+                }
+                for row in tables.answers.values_mut() {
+                    if row.account_id == *account_id {
+                        row.account_id = DELETED_ACCOUNT;
+                        row.client_id = None;
+                    }
+                }
+            }
+        }
+
+        // Votes stay, so scores don't shift when an account goes
+        let id = account_id.0;
+        tables.bookmarks.retain(|(account_id, _), _| *account_id != id);
+        // What the ON DELETE CASCADE foreign keys take care of in Postgres
+        tables.deletions.remove(&id);
+        tables.logins.remove(&id);
+        tables.suspensions.remove(&id);
+        tables.account_created.remove(&id);
+        tables.preferences.remove(&id);
+        tables.censorship_audit.retain(|(account_id, _, _)| *account_id != id);
+        tables.accounts.retain(|a| a.id.as_ref() != Some(&AccountId(id)));
+        tables.api_tokens.retain(|_, row| row.account_id.0 != id);
+        tables.verifications.retain(|_, (account_id, _)| *account_id != id);
+        tables.password_resets.retain(|_, (account_id, _)| *account_id != id);
+
+        Ok(())
+    }
+
+    async fn delete_due_accounts(
+        &self,
+        now: DateTime<Utc>,
+        content: DeletedContent,
+    ) -> Result<u64, Error> {
+        let due: Vec<i32> = self
+            .tables
+            .read()
+            .unwrap()
+            .deletions
+            .iter()
+            .filter(|(_, due_at)| **due_at <= now)
+            .map(|(account_id, _)| *account_id)
+            .collect();
+
+        let mut deleted = 0;
+        for account_id in due {
+            // Skips accounts whose deletion was cancelled since the scan
+            let still_due = self
+                .tables
+                .read()
+                .unwrap()
+                .deletions
+                .get(&account_id)
+                .is_some_and(|due_at| *due_at <= now);
+            if still_due {
+                self.delete_account(&AccountId(account_id), content).await?;
+                deleted += 1;
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    async fn add_api_token(
+        &self,
+        account_id: &AccountId,
+        name: String,
+        token_hash: String,
+    ) -> Result<ApiToken, Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables.api_token_seq += 1;
+        let api_token = ApiToken {
+            id: ApiTokenId(tables.api_token_seq),
+            name,
+            created_on: Utc::now(),
+        };
+        tables.api_tokens.insert(
+            api_token.id.0,
+            ApiTokenRow {
+                api_token: api_token.clone(),
+                account_id: account_id.clone(),
+                token_hash,
+            },
+        );
+
+        Ok(api_token)
+    }
+
+    async fn get_api_tokens(&self, account_id: &AccountId) -> Result<Vec<ApiToken>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables
+            .api_tokens
+            .values()
+            .filter(|row| &row.account_id == account_id)
+            .map(|row| row.api_token.clone())
+            .collect())
+    }
+
+    async fn delete_api_token(&self, token_id: i32, account_id: &AccountId) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        match tables.api_tokens.get(&token_id) {
+            Some(row) if &row.account_id == account_id => {
+                tables.api_tokens.remove(&token_id);
+                Ok(())
+            }
+            _ => Err(Error::ApiTokenNotFound),
+        }
+    }
+
+    async fn get_api_token_account(&self, token_hash: &str) -> Result<Option<Account>, Error> {
+        let tables = self.tables.read().unwrap();
+        let Some(row) = tables
+            .api_tokens
+            .values()
+            .find(|row| row.token_hash == token_hash)
+        else {
+            return Ok(None);
+        };
+
+        Ok(tables
+            .accounts
+            .iter()
+            .find(|account| account.id.as_ref() == Some(&row.account_id))
+            .cloned())
+    }
+
+    async fn add_verification_token(
+        &self,
+        account_id: &AccountId,
+        token_hash: &str,
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables
+            .verifications
+            .insert(token_hash.to_string(), (account_id.0, SystemTime::now() + ttl));
+
+        Ok(())
+    }
+
+    async fn verify_account(&self, token_hash: &str) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        let account_id = match tables.verifications.remove(token_hash) {
+            Some((account_id, expires_at)) if expires_at > SystemTime::now() => account_id,
+            _ => return Err(Error::InvalidVerificationToken),
+        };
+
+        match tables
+            .accounts
+            .iter_mut()
+            .find(|account| account.id == Some(AccountId(account_id)))
+        {
+            Some(account) => {
+                account.verified = true;
+                Ok(())
+            }
+            None => Err(Error::InvalidVerificationToken),
+        }
+    }
+
+    async fn add_password_reset_token(
+        &self,
+        account_id: &AccountId,
+        token_hash: &str,
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables
+            .password_resets
+            .insert(token_hash.to_string(), (account_id.0, SystemTime::now() + ttl));
+
+        Ok(())
+    }
+
+    async fn reset_password(&self, token_hash: &str, password_hash: &str) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        let account_id = match tables.password_resets.remove(token_hash) {
+            Some((account_id, expires_at)) if expires_at > SystemTime::now() => account_id,
+            _ => return Err(Error::InvalidResetToken),
+        };
+
+        match tables
+            .accounts
+            .iter_mut()
+            .find(|account| account.id == Some(AccountId(account_id)))
+        {
+            Some(account) => {
+                account.password = password_hash.to_string();
+                Ok(())
+            }
+            None => Err(Error::InvalidResetToken),
+        }
+    }
+
+    async fn get_flag(&self, name: &str) -> Result<Option<bool>, Error> {
+        let tables = self.tables.read().unwrap();
+        Ok(tables.flags.get(name).copied())
+    }
+
+    async fn set_flag(&self, name: &str, enabled: bool) -> Result<(), Error> {
+        let mut tables = self.tables.write().unwrap();
+        tables.flags.insert(name.to_string(), enabled);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod memory_tests {
+    use super::*;
+
+    /// Accounts 1 and 2 each ask a question and answer the other's, and
+    /// account 2 replies to account 1's answer
+    async fn store_with_two_posters() -> MemoryStore {
+        let store = MemoryStore::new();
+        for (email, asker) in [("one@email.com", 1), ("two@email.com", 2)] {
+            let account = Account {
+                id: None,
+                email: email.to_string(),
+                password: "hash".to_string(),
+                is_admin: false,
+                verified: true,
+            };
+            store.add_account(account).await.unwrap();
+            store
+                .add_question(
+                    NewQuestion {
+                        title: format!("Asked by {}", email),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(asker),
+                )
+                .await
+                .unwrap();
+        }
+        for (question, parent, author) in [(1, None, 2), (2, None, 1), (2, Some(AnswerId(2)), 2)] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: "Content of the answer".to_string(),
+                        question_id: QuestionId(question),
+                        parent_answer_id: parent,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(author),
+                )
+                .await
+                .unwrap();
+        }
+        store.add_bookmark(1, AccountId(2)).await.unwrap();
+        store.add_bookmark(2, AccountId(1)).await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn deleted_accounts_take_their_content_with_them() {
+        let store = store_with_two_posters().await;
+
+        store
+            .delete_account(&AccountId(1), DeletedContent::Delete)
+            .await
+            .unwrap();
+
+        assert!(store.get_account("one@email.com".to_string()).await.is_err());
+        // Question 1 went with the other account's answer to it, and the
+        // reply to the deleted answer went along with that answer
+        assert!(matches!(store.get_question(1).await, Err(Error::QuestionNotFound)));
+        assert!(store.get_answers(2).await.unwrap().is_empty());
+        assert!(store.get_account_answers(&AccountId(2)).await.unwrap().is_empty());
+        assert!(store
+            .get_bookmarks(&AccountId(2), None, 0)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            store.delete_account(&AccountId(1), DeletedContent::Delete).await,
+            Err(Error::AccountNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn anonymized_accounts_leave_their_content_to_the_deleted_user() {
+        let store = store_with_two_posters().await;
+
+        store
+            .delete_account(&AccountId(1), DeletedContent::Anonymize)
+            .await
+            .unwrap();
+
+        assert!(store.get_account("one@email.com".to_string()).await.is_err());
+        let questions = store.get_account_questions(&DELETED_ACCOUNT).await.unwrap();
+        assert_eq!(questions.iter().map(|q| q.id.0).collect::<Vec<_>>(), vec![1]);
+        let answers = store.get_account_answers(&DELETED_ACCOUNT).await.unwrap();
+        assert_eq!(answers.iter().map(|a| a.id.0).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(store.get_answers(1).await.unwrap().len(), 1);
+        assert_eq!(store.get_answers(2).await.unwrap().len(), 2);
+        let bookmarks = store.get_bookmarks(&AccountId(2), None, 0).await.unwrap();
+        assert_eq!(bookmarks.len(), 1);
+    }
+}
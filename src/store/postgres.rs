@@ -0,0 +1,1963 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use sqlx::Row;
+use sqlx::postgres::{PgConnectOptions, PgPool, PgRow, PgSslMode, Postgres};
+use sqlx::pool::PoolOptions;
+use sqlx::{Database, Transaction};
+
+use super::{DEFAULT_MAX_ROWS, Store, cap_rows, row_limit};
+use crate::config::DbSslMode;
+use crate::types::account::{
+    AccountId, AccountProfile, DELETED_ACCOUNT, DeletedContent, NotificationPreferences,
+};
+use crate::types::{
+    account::Account,
+    activity::Activity,
+    api_token::{ApiToken, ApiTokenId},
+    answer::{Answer, AnswerId, NewAnswer},
+    question::{NewQuestion, Question, QuestionId},
+    search::SearchTerm,
+    sort::{Sort, SortColumn},
+    stats::{AccountStats, Stats},
+    tag::{TagCount, TagFilter},
+    vote::Vote,
+};
+use handle_errors::Error;
+
+/// Unique index rejecting the same (normalized) answer content twice
+/// from one account on one question
+const ANSWERS_UNIQUE_CONTENT: &str = "answers_unique_content";
+
+fn answer_from_row(row: PgRow) -> Answer {
+    Answer {
+        id: AnswerId(row.get("id")),
+        content: row.get("content"),
+        question_id: QuestionId(row.get("question_id")),
+        parent_answer_id: row
+            .get::<Option<i32>, _>("parent_answer_id")
+            .map(AnswerId),
+        // Kept in answer_attachments, see `PgStore::load_attachments`
+        attachments: Vec::new(),
+    }
+}
+
+fn question_from_row(row: PgRow) -> Question {
+    Question {
+        id: QuestionId(row.get("id")),
+        title: row.get("title"),
+        content: row.get("content"),
+        tags: row.get("tags"),
+        expires_at: row.get("expires_at"),
+        content_format: row
+            .get::<String, _>("content_format")
+            .parse()
+            .unwrap_or_default(),
+    }
+}
+
+/// Points the question at its tags in question_tags, replacing any links
+/// it had and creating the tag rows which don't exist yet
+async fn link_tags(
+    tx: &mut Transaction<'_, Postgres>,
+    question_id: i32,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM question_tags WHERE question_id = $1")
+        .bind(question_id)
+        .execute(&mut *tx)
+        .await?;
+
+    // Tags inserted by the CTE aren't visible to the outer query's read
+    // of `tags`, so the two sets are combined
+    sqlx::query(
+        "WITH inserted AS (
+            INSERT INTO tags (name) SELECT DISTINCT unnest($2::text[])
+            ON CONFLICT (name) DO NOTHING
+            RETURNING id
+        )
+        INSERT INTO question_tags (question_id, tag_id)
+        SELECT $1, id FROM inserted
+        UNION SELECT $1, id FROM tags WHERE name = ANY($2)
+        ON CONFLICT DO NOTHING",
+    )
+    .bind(question_id)
+    .bind(tags)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+/// The statements behind `Store::delete_account`, returning how many
+/// accounts rows went (0 or 1)
+async fn remove_account(
+    tx: &mut Transaction<'_, Postgres>,
+    account_id: i32,
+    content: DeletedContent,
+) -> Result<u64, sqlx::Error> {
+    match content {
+        DeletedContent::Delete => {
+            // Replies to these answers follow through parent_answer_id's
+            // ON DELETE CASCADE, and the questions' votes, bookmarks and
+            // tags through question_id's
+            sqlx::query(
+                "DELETE FROM answers WHERE account_id = $1
+                OR question_id IN (SELECT id FROM questions WHERE account_id = $1)",
+            )
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("DELETE FROM questions WHERE account_id = $1")
+                .bind(account_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        DeletedContent::Anonymize => {
+            // Retry keys are only unique per account, so they'd clash with
+            // other deleted accounts' under DELETED_ACCOUNT
+            for statement in [
+                "UPDATE questions SET account_id = $1 WHERE account_id = $2",
+                "UPDATE answers SET account_id = $1, client_id = NULL WHERE account_id = $2",
+            ] {
+                sqlx::query(statement)
+                    .bind(DELETED_ACCOUNT.0)
+                    .bind(account_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+
+    // Votes stay, so scores don't shift when an account goes
+    sqlx::query("DELETE FROM bookmarks WHERE account_id = $1")
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+    let result = sqlx::query("DELETE FROM accounts WHERE id = $1")
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// `remove_account` for an account whose deletion is still due, returning
+/// 0 without touching anything when it was cancelled in the meantime
+async fn remove_due_account(
+    tx: &mut Transaction<'_, Postgres>,
+    account_id: i32,
+    now: DateTime<Utc>,
+    content: DeletedContent,
+) -> Result<u64, sqlx::Error> {
+    // The row lock makes a cancellation racing the sweep wait for it
+    let due = sqlx::query("SELECT id FROM accounts WHERE id = $1 AND deletion_due_at <= $2 FOR UPDATE")
+        .bind(account_id)
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if due.is_none() {
+        return Ok(0);
+    }
+
+    remove_account(tx, account_id, content).await
+}
+
+/// Rows buffered between the query and a slow reader of a question stream
+const STREAM_BUFFER: usize = 64;
+
+fn activity_from_row(row: PgRow) -> Activity {
+    Activity {
+        kind: row.get::<String, _>("kind").parse().unwrap_or_default(),
+        at: row.get("at"),
+        answer_id: row.get::<Option<i32>, _>("answer_id").map(AnswerId),
+    }
+}
+
+fn api_token_from_row(row: PgRow) -> ApiToken {
+    ApiToken {
+        id: ApiTokenId(row.get("id")),
+        name: row.get("name"),
+        created_on: row.get("created_on"),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PgStore {
+    pub connection: PgPool,
+    max_rows: usize,
+}
+
+/// Most connections the `PgStore` pool opens
+const MAX_CONNECTIONS: u32 = 5;
+
+/// Pool settings for `PgStore`. Connections idle for longer than
+/// `idle_timeout`, or open for longer than `max_lifetime`, are closed and
+/// replaced instead of being handed out after Postgres or a firewall may
+/// have dropped them. `None` keeps them open.
+///
+/// `warmup` connections (at most `MAX_CONNECTIONS`) are opened before the
+/// pool is handed out and kept open afterwards, so the first requests
+/// don't wait for connection setup. 0 opens them on demand.
+fn pool_options<DB: Database>(
+    idle_timeout: Option<Duration>,
+    max_lifetime: Option<Duration>,
+    warmup: u32,
+) -> PoolOptions<DB> {
+    PoolOptions::new()
+        .max_connections(MAX_CONNECTIONS)
+        .min_connections(warmup.min(MAX_CONNECTIONS))
+        .idle_timeout(idle_timeout)
+        .max_lifetime(max_lifetime)
+}
+
+impl From<DbSslMode> for PgSslMode {
+    fn from(mode: DbSslMode) -> Self {
+        match mode {
+            DbSslMode::Disable => PgSslMode::Disable,
+            DbSslMode::Prefer => PgSslMode::Prefer,
+            DbSslMode::Require => PgSslMode::Require,
+            DbSslMode::VerifyFull => PgSslMode::VerifyFull,
+        }
+    }
+}
+
+/// Connection settings for `PgStore`, keeping at most
+/// `statement_cache_capacity` prepared statements per connection.
+/// `ssl_mode` always wins over an `sslmode=` in `db_url`.
+///
+/// The root cert is only read once a connection is opened, so a missing
+/// file is reported here rather than as a failed TLS handshake later on.
+fn connect_options(
+    db_url: &str,
+    statement_cache_capacity: usize,
+    ssl_mode: DbSslMode,
+    ssl_root_cert: Option<&Path>,
+) -> Result<PgConnectOptions, sqlx::Error> {
+    let options = db_url
+        .parse::<PgConnectOptions>()?
+        .statement_cache_capacity(statement_cache_capacity)
+        .ssl_mode(ssl_mode.into());
+    match ssl_root_cert {
+        Some(path) => match std::fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => Ok(options.ssl_root_cert(path)),
+            Ok(_) => Err(sqlx::Error::Configuration(
+                format!("database root cert `{}` is not a file", path.display()).into(),
+            )),
+            Err(error) => Err(sqlx::Error::Configuration(
+                format!("database root cert `{}` can't be read: {}", path.display(), error)
+                    .into(),
+            )),
+        },
+        None => Ok(options),
+    }
+}
+
+impl PgStore {
+    pub async fn new(
+        db_url: &str,
+        idle_timeout: Option<Duration>,
+        max_lifetime: Option<Duration>,
+        statement_cache_capacity: usize,
+        warmup: u32,
+        ssl_mode: DbSslMode,
+        ssl_root_cert: Option<&Path>,
+    ) -> Result<Self, sqlx::Error> {
+        tracing::warn!("{}", db_url);
+        let options =
+            connect_options(db_url, statement_cache_capacity, ssl_mode, ssl_root_cert)?;
+        let db_pool = pool_options(idle_timeout, max_lifetime, warmup)
+            .connect_with(options)
+            .await?;
+
+        Ok(PgStore {
+            connection: db_pool,
+            max_rows: DEFAULT_MAX_ROWS,
+        })
+    }
+
+    /// Caps what list methods return, see `cap_rows`
+    pub fn with_max_rows(self, max_rows: usize) -> Self {
+        PgStore { max_rows, ..self }
+    }
+
+    /// Fill in the attachments of `answers` from answer_attachments
+    async fn load_attachments(&self, answers: &mut [Answer]) -> Result<(), Error> {
+        let ids: Vec<i32> = answers.iter().map(|answer| answer.id.0).collect();
+        let attachments = match sqlx::query(
+            "SELECT answer_id, url FROM answer_attachments
+            WHERE answer_id = ANY($1)
+            ORDER BY answer_id, position",
+        )
+        .bind(ids)
+        .map(|row: PgRow| (row.get::<i32, _>("answer_id"), row.get::<String, _>("url")))
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(attachments) => attachments,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        for (answer_id, url) in attachments {
+            if let Some(answer) = answers.iter_mut().find(|answer| answer.id.0 == answer_id) {
+                answer.attachments.push(url);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for PgStore {
+    async fn is_question_owner(
+        &self,
+        question_id: i32,
+        account_id: &AccountId,
+    ) -> Result<bool, Error> {
+        match sqlx::query("SELECT * from questions where id = $1 and account_id = $2")
+            .bind(question_id)
+            .bind(account_id.0)
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(question) => Ok(question.is_some()),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    async fn question_created_on(&self, question_id: i32) -> Result<DateTime<Utc>, Error> {
+        match sqlx::query(
+            "SELECT created_on AT TIME ZONE 'UTC' AS created_on FROM questions WHERE id = $1",
+        )
+        .bind(question_id)
+        .map(|row: PgRow| row.get("created_on"))
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(Some(created_on)) => Ok(created_on),
+            Ok(None) => Err(Error::QuestionNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    async fn question_modified_on(&self, question_id: i32) -> Result<DateTime<Utc>, Error> {
+        match sqlx::query(
+            "SELECT COALESCE(updated_on, created_on) AT TIME ZONE 'UTC' AS modified_on
+            FROM questions WHERE id = $1",
+        )
+        .bind(question_id)
+        .map(|row: PgRow| row.get("modified_on"))
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(Some(modified_on)) => Ok(modified_on),
+            Ok(None) => Err(Error::QuestionNotFound),
+            Err(e) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", e);
+                Err(Error::DatabaseQueryError(e))
+            }
+        }
+    }
+
+    async fn get_questions(
+        &self,
+        limit: Option<u32>,
+        offset: u32,
+        sort: Sort,
+        include_archived: bool,
+        tags: Option<TagFilter>,
+    ) -> Result<Vec<Question>, Error> {
+        // Column and direction come from a closed set of enum variants,
+        // so formatting them into the statement is safe
+        let source = match sort.column {
+            SortColumn::Answers => {
+                "questions LEFT JOIN (SELECT question_id AS question, COUNT(*) AS answer_count \
+                 FROM answers GROUP BY question_id) AS counts ON counts.question = questions.id"
+            }
+            SortColumn::Top => {
+                "questions LEFT JOIN (SELECT question_id AS question, SUM(value) AS score \
+                 FROM votes GROUP BY question_id) AS scores ON scores.question = questions.id"
+            }
+            _ => "questions",
+        };
+        let tag_condition = match &tags {
+            None => "",
+            Some(TagFilter::Any(_)) => {
+                " AND questions.id IN (SELECT question_id FROM question_tags
+                    JOIN tags ON tags.id = question_tags.tag_id WHERE tags.name = ANY($4))"
+            }
+            Some(TagFilter::All(_)) => {
+                " AND questions.id IN (SELECT question_id FROM question_tags
+                    JOIN tags ON tags.id = question_tags.tag_id WHERE tags.name = ANY($4)
+                    GROUP BY question_id HAVING COUNT(*) = cardinality($4))"
+            }
+        };
+        let query = format!(
+            "SELECT * FROM {} WHERE ($3 OR NOT archived){}
+            ORDER BY is_pinned DESC, {} LIMIT $1 OFFSET $2",
+            source,
+            tag_condition,
+            sort.order_by()
+        );
+
+        let mut query = sqlx::query(&query)
+            .bind(row_limit(limit, self.max_rows))
+            .bind(i64::from(offset))
+            .bind(include_archived);
+        if let Some(tags) = &tags {
+            query = query.bind(tags.tags());
+        }
+        match query
+            .map(question_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(questions) => Ok(cap_rows(questions, self.max_rows, "get_questions")),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_question(&self, question_id: i32) -> Result<Question, Error> {
+        match sqlx::query("SELECT * FROM questions WHERE id = $1")
+            .bind(question_id)
+            .map(question_from_row)
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(Some(question)) => Ok(question),
+            Ok(None) => Err(Error::QuestionNotFound),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn random_question(&self) -> Result<Question, Error> {
+        match sqlx::query("SELECT * FROM questions WHERE NOT archived ORDER BY random() LIMIT 1")
+            .map(question_from_row)
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(Some(question)) => Ok(question),
+            Ok(None) => Err(Error::QuestionNotFound),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn count_search_results(&self, term: &SearchTerm) -> Result<i64, Error> {
+        match sqlx::query(
+            "SELECT COUNT(*) AS count FROM questions
+            WHERE NOT archived AND (title ILIKE $1 OR content ILIKE $1)",
+        )
+        .bind(term.like_pattern())
+        .map(|row: PgRow| row.get("count"))
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(count) => Ok(count),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn stream_questions(
+        &self,
+        limit: Option<u32>,
+    ) -> Result<BoxStream<'static, Result<Question, Error>>, Error> {
+        // The row stream borrows the pool, so it is driven from a task of
+        // its own and handed over through a bounded channel
+        let pool = self.connection.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+
+        tokio::spawn(async move {
+            let mut rows = sqlx::query("SELECT * FROM questions WHERE NOT archived ORDER BY id LIMIT $1")
+                .bind(limit.map(i64::from))
+                .map(question_from_row)
+                .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                let row = row.map_err(|error| {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                    Error::DatabaseQueryError(error)
+                });
+                // The reader went away, stop querying
+                if tx.send(row).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|question| (question, rx))
+        })
+        .boxed())
+    }
+
+    async fn add_question(
+        &self,
+        new_question: NewQuestion,
+        account_id: AccountId,
+    ) -> Result<Question, Error> {
+        let content_format = new_question.format();
+        let tags = new_question.tags.clone().unwrap_or_default();
+
+        let mut tx = match self.connection.begin().await {
+            Ok(tx) => tx,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        let question = match sqlx::query(
+            "INSERT INTO questions (title, content, tags, account_id, expires_at, content_format) 
+                VALUES ($1, $2, $3, $4, $5, $6) 
+                RETURNING id, title, content, tags, expires_at, content_format",
+        )
+        .bind(new_question.title)
+        .bind(new_question.content)
+        .bind(new_question.tags)
+        .bind(account_id.0)
+        .bind(new_question.expires_at)
+        .bind(content_format.as_str())
+        .map(question_from_row)
+        .fetch_one(&mut tx)
+        .await
+        {
+            Ok(question) => question,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        let linked = link_tags(&mut tx, question.id.0, &tags).await;
+        match linked.and(tx.commit().await) {
+            Ok(_) => Ok(question),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn update_question(
+        &self,
+        question: Question,
+        question_id: i32,
+        account_id: AccountId,
+    ) -> Result<Question, Error> {
+        let tags = question.tags.clone().unwrap_or_default();
+
+        let mut tx = match self.connection.begin().await {
+            Ok(tx) => tx,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        let question = match sqlx::query(
+            "UPDATE questions SET 
+                title = $1, 
+                content = $2, 
+                tags = $3,
+                content_format = $6,
+                updated_on = NOW()
+            WHERE id = $4 AND account_id = $5
+            RETURNING id, title, content, tags, expires_at, content_format",
+        )
+        .bind(question.title)
+        .bind(question.content)
+        .bind(question.tags)
+        .bind(question_id)
+        .bind(account_id.0)
+        .bind(question.content_format.as_str())
+        .map(question_from_row)
+        .fetch_one(&mut tx)
+        .await
+        {
+            Ok(question) => question,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        let linked = link_tags(&mut tx, question_id, &tags).await;
+        match linked.and(tx.commit().await) {
+            Ok(_) => Ok(question),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn archive_expired_questions(&self, now: DateTime<Utc>) -> Result<u64, Error> {
+        match sqlx::query(
+            "UPDATE questions SET archived = true
+            WHERE expires_at <= $1 AND NOT archived",
+        )
+        .bind(now)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) => Ok(result.rows_affected()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn delete_question(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+    ) -> Result<(), Error> {
+        match sqlx::query(
+            "DELETE FROM questions 
+            WHERE id = $1 AND account_id = $2",
+        )
+        .bind(question_id)
+        .bind(account_id.0)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn remove_question(&self, question_id: i32) -> Result<(), Error> {
+        match sqlx::query("DELETE FROM questions WHERE id = $1")
+            .bind(question_id)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(Error::QuestionNotFound),
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn set_pinned(&self, question_id: i32, pinned: bool) -> Result<(), Error> {
+        match sqlx::query("UPDATE questions SET is_pinned = $1 WHERE id = $2")
+            .bind(pinned)
+            .bind(question_id)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(Error::QuestionNotFound),
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn add_answer(
+        &self,
+        new_answer: NewAnswer,
+        account_id: AccountId,
+    ) -> Result<Answer, Error> {
+        let client_id = new_answer.client_id.clone();
+        let attachments = new_answer.attachments.clone();
+
+        let mut tx = match self.connection.begin().await {
+            Ok(tx) => tx,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        // A client_id the account already used means this is a retry of an
+        // insert that went through, so the stored answer is handed back
+        let inserted = match sqlx::query(
+            "INSERT INTO answers (content, question_id, parent_answer_id, account_id, client_id) 
+                VALUES ($1, $2, $3, $4, $5) 
+                ON CONFLICT (account_id, client_id) DO NOTHING
+                RETURNING id, content, question_id, parent_answer_id",
+        )
+        .bind(new_answer.content)
+        .bind(new_answer.question_id.0)
+        .bind(new_answer.parent_answer_id.map(|parent| parent.0))
+        .bind(account_id.0)
+        .bind(&client_id)
+        .map(answer_from_row)
+        .fetch_optional(&mut tx)
+        .await
+        {
+            Ok(answer) => answer,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                let duplicate = error
+                    .as_database_error()
+                    .and_then(|e| e.constraint())
+                    == Some(ANSWERS_UNIQUE_CONTENT);
+                if duplicate {
+                    return Err(Error::DuplicateAnswer);
+                }
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        if let Some(mut answer) = inserted {
+            let stored = sqlx::query(
+                "INSERT INTO answer_attachments (answer_id, position, url)
+                SELECT $1, position, url
+                FROM unnest($2::text[]) WITH ORDINALITY AS attachment(url, position)",
+            )
+            .bind(answer.id.0)
+            .bind(&attachments)
+            .execute(&mut tx)
+            .await;
+
+            return match stored.and(tx.commit().await) {
+                Ok(_) => {
+                    answer.attachments = attachments;
+                    Ok(answer)
+                }
+                Err(error) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                    Err(Error::DatabaseQueryError(error))
+                }
+            };
+        }
+
+        let mut answer = match sqlx::query(
+            "SELECT id, content, question_id, parent_answer_id FROM answers
+            WHERE account_id = $1 AND client_id = $2",
+        )
+        .bind(account_id.0)
+        .bind(client_id)
+        .map(answer_from_row)
+        .fetch_one(&mut tx)
+        .await
+        {
+            Ok(answer) => answer,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+        self.load_attachments(std::slice::from_mut(&mut answer)).await?;
+
+        Ok(answer)
+    }
+
+    async fn get_answer(&self, answer_id: i32) -> Result<Answer, Error> {
+        let mut answer = match sqlx::query(
+            "SELECT id, content, question_id, parent_answer_id FROM answers WHERE id = $1",
+        )
+        .bind(answer_id)
+        .map(answer_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(Some(answer)) => answer,
+            Ok(None) => return Err(Error::AnswerNotFound),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+        self.load_attachments(std::slice::from_mut(&mut answer)).await?;
+
+        Ok(answer)
+    }
+
+    async fn get_answers(&self, question_id: i32) -> Result<Vec<Answer>, Error> {
+        match sqlx::query(
+            "SELECT id, content, question_id, parent_answer_id FROM answers
+            WHERE question_id = $1
+            ORDER BY id",
+        )
+        .bind(question_id)
+        .map(answer_from_row)
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(mut answers) => {
+                self.load_attachments(&mut answers).await?;
+                Ok(answers)
+            }
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_answers_for_questions(
+        &self,
+        question_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<Answer>>, Error> {
+        let mut answers = match sqlx::query(
+            "SELECT id, content, question_id, parent_answer_id FROM answers
+            WHERE question_id = ANY($1)
+            ORDER BY id",
+        )
+        .bind(question_ids.to_vec())
+        .map(answer_from_row)
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(answers) => answers,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+        self.load_attachments(&mut answers).await?;
+
+        let mut grouped: HashMap<i32, Vec<Answer>> =
+            question_ids.iter().map(|id| (*id, Vec::new())).collect();
+        for answer in answers {
+            grouped.entry(answer.question_id.0).or_default().push(answer);
+        }
+        Ok(grouped)
+    }
+
+    async fn expire_client_ids(&self, before: DateTime<Utc>) -> Result<u64, Error> {
+        match sqlx::query(
+            "UPDATE answers SET client_id = NULL
+            WHERE client_id IS NOT NULL AND created_on AT TIME ZONE 'UTC' < $1",
+        )
+        .bind(before)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) => Ok(result.rows_affected()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn count_answers(&self, question_id: i32) -> Result<i64, Error> {
+        match sqlx::query("SELECT COUNT(*) AS count FROM answers WHERE question_id = $1")
+            .bind(question_id)
+            .map(|row: PgRow| row.get("count"))
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(count) => Ok(count),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_question_activity(
+        &self,
+        question_id: i32,
+        limit: Option<u32>,
+        offset: u32,
+    ) -> Result<Vec<Activity>, Error> {
+        match sqlx::query(
+            "SELECT kind, at AT TIME ZONE 'UTC' AS at, answer_id FROM (
+                SELECT 'created' AS kind, created_on AS at, NULL::integer AS answer_id
+                FROM questions WHERE id = $1
+                UNION ALL
+                SELECT 'edited', updated_on, NULL FROM questions
+                WHERE id = $1 AND updated_on IS NOT NULL
+                UNION ALL
+                SELECT 'answered', created_on, id FROM answers WHERE question_id = $1
+                UNION ALL
+                SELECT 'voted', created_on, NULL FROM votes WHERE question_id = $1
+            ) activity
+            ORDER BY at, answer_id, kind
+            LIMIT $2 OFFSET $3",
+        )
+        .bind(question_id)
+        .bind(row_limit(limit, self.max_rows))
+        .bind(i64::from(offset))
+        .map(activity_from_row)
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(activity) => Ok(cap_rows(activity, self.max_rows, "get_question_activity")),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn move_answer(&self, answer_id: i32, question_id: i32) -> Result<Answer, Error> {
+        let mut tx = match self.connection.begin().await {
+            Ok(tx) => tx,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        match sqlx::query("SELECT id FROM questions WHERE id = $1")
+            .bind(question_id)
+            .fetch_optional(&mut tx)
+            .await
+        {
+            Ok(Some(_)) => (),
+            Ok(None) => return Err(Error::QuestionNotFound),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        }
+
+        let moved = match sqlx::query(
+            "WITH RECURSIVE thread AS (
+                SELECT id FROM answers WHERE id = $1
+                UNION ALL
+                SELECT answers.id FROM answers JOIN thread ON answers.parent_answer_id = thread.id
+            )
+            UPDATE answers SET
+                question_id = $2,
+                parent_answer_id = CASE WHEN id = $1 THEN NULL ELSE parent_answer_id END
+            WHERE id IN (SELECT id FROM thread)",
+        )
+        .bind(answer_id)
+        .bind(question_id)
+        .execute(&mut tx)
+        .await
+        {
+            Ok(result) => result.rows_affected(),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                let duplicate = error
+                    .as_database_error()
+                    .and_then(|e| e.constraint())
+                    == Some(ANSWERS_UNIQUE_CONTENT);
+                if duplicate {
+                    return Err(Error::DuplicateAnswer);
+                }
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+        if moved == 0 {
+            return Err(Error::AnswerNotFound);
+        }
+
+        let answer = sqlx::query(
+            "SELECT id, content, question_id, parent_answer_id FROM answers
+            WHERE id = $1",
+        )
+        .bind(answer_id)
+        .map(answer_from_row)
+        .fetch_one(&mut tx)
+        .await;
+
+        let mut answer = match answer {
+            Ok(answer) => match tx.commit().await {
+                Ok(_) => answer,
+                Err(error) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                    return Err(Error::DatabaseQueryError(error));
+                }
+            },
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+        self.load_attachments(std::slice::from_mut(&mut answer)).await?;
+
+        Ok(answer)
+    }
+
+    async fn get_tags(&self, limit: Option<u32>, offset: u32) -> Result<Vec<TagCount>, Error> {
+        match sqlx::query(
+            "SELECT tags.name, COUNT(*) AS count
+            FROM question_tags JOIN tags ON tags.id = question_tags.tag_id
+            GROUP BY tags.name
+            ORDER BY count DESC, name ASC
+            LIMIT $1 OFFSET $2",
+        )
+        .bind(row_limit(limit, self.max_rows))
+        .bind(i64::from(offset))
+        .map(|row: PgRow| TagCount {
+            name: row.get("name"),
+            count: row.get("count"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(tags) => Ok(cap_rows(tags, self.max_rows, "get_tags")),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn cast_vote(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+        vote: Vote,
+    ) -> Result<i64, Error> {
+        let mut tx = match self.connection.begin().await {
+            Ok(tx) => tx,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        let cast = sqlx::query(
+            "INSERT INTO votes (account_id, question_id, value)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (account_id, question_id) DO UPDATE SET value = EXCLUDED.value",
+        )
+        .bind(account_id.0)
+        .bind(question_id)
+        .bind(vote.value())
+        .execute(&mut tx)
+        .await;
+        if let Err(error) = cast {
+            tracing::event!(tracing::Level::ERROR, "{:?}", error);
+            return Err(Error::DatabaseQueryError(error));
+        }
+
+        // Read within the transaction so the score includes this vote
+        let score = sqlx::query(
+            "SELECT COALESCE(SUM(value), 0)::bigint AS score FROM votes WHERE question_id = $1",
+        )
+        .bind(question_id)
+        .map(|row: PgRow| row.get::<i64, _>("score"))
+        .fetch_one(&mut tx)
+        .await;
+
+        match score {
+            Ok(score) => match tx.commit().await {
+                Ok(_) => Ok(score),
+                Err(error) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                    Err(Error::DatabaseQueryError(error))
+                }
+            },
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_votes(
+        &self,
+        account_id: &AccountId,
+        question_ids: &[i32],
+    ) -> Result<HashMap<i32, Vote>, Error> {
+        match sqlx::query(
+            "SELECT question_id, value FROM votes
+            WHERE account_id = $1 AND question_id = ANY($2)",
+        )
+        .bind(account_id.0)
+        .bind(question_ids.to_vec())
+        .map(|row: PgRow| {
+            (
+                row.get::<i32, _>("question_id"),
+                Vote::from_value(row.get("value")),
+            )
+        })
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(votes) => Ok(votes
+                .into_iter()
+                .filter_map(|(question_id, vote)| Some((question_id, vote?)))
+                .collect()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_account_votes(&self, account_id: &AccountId) -> Result<HashMap<i32, Vote>, Error> {
+        match sqlx::query("SELECT question_id, value FROM votes WHERE account_id = $1")
+            .bind(account_id.0)
+            .map(|row: PgRow| {
+                (
+                    row.get::<i32, _>("question_id"),
+                    Vote::from_value(row.get("value")),
+                )
+            })
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(votes) => Ok(votes
+                .into_iter()
+                .filter_map(|(question_id, vote)| Some((question_id, vote?)))
+                .collect()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn add_bookmark(&self, question_id: i32, account_id: AccountId) -> Result<(), Error> {
+        match sqlx::query(
+            "INSERT INTO bookmarks (account_id, question_id) VALUES ($1, $2)
+            ON CONFLICT (account_id, question_id) DO NOTHING",
+        )
+        .bind(account_id.0)
+        .bind(question_id)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn remove_bookmark(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+    ) -> Result<(), Error> {
+        match sqlx::query("DELETE FROM bookmarks WHERE account_id = $1 AND question_id = $2")
+            .bind(account_id.0)
+            .bind(question_id)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_bookmarks(
+        &self,
+        account_id: &AccountId,
+        limit: Option<u32>,
+        offset: u32,
+    ) -> Result<Vec<Question>, Error> {
+        match sqlx::query(
+            "SELECT questions.* FROM bookmarks
+            JOIN questions ON questions.id = bookmarks.question_id
+            WHERE bookmarks.account_id = $1
+            ORDER BY bookmarks.created_on DESC, questions.id DESC
+            LIMIT $2 OFFSET $3",
+        )
+        .bind(account_id.0)
+        .bind(row_limit(limit, self.max_rows))
+        .bind(i64::from(offset))
+        .map(question_from_row)
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(questions) => Ok(cap_rows(questions, self.max_rows, "get_bookmarks")),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn add_account(&self, account: Account) -> Result<bool, Error> {
+        match sqlx::query(
+            "INSERT INTO accounts (email, password, verified)
+            VALUES ($1, $2, $3)",
+        )
+        .bind(account.email)
+        .bind(account.password)
+        .bind(account.verified)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(true),
+            Err(error) => {
+                tracing::event!(
+                    tracing::Level::ERROR,
+                    code = error
+                        .as_database_error()
+                        .unwrap()
+                        .code()
+                        .unwrap()
+                        .parse::<i32>()
+                        .unwrap(),
+                    db_message = error.as_database_error().unwrap().message(),
+                    constraint = error.as_database_error().unwrap().constraint().unwrap()
+                );
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn add_account_capped(
+        &self,
+        account: Account,
+        max_accounts: u64,
+    ) -> Result<bool, Error> {
+        if max_accounts == 0 {
+            return self.add_account(account).await;
+        }
+
+        let mut tx = match self.connection.begin().await {
+            Ok(tx) => tx,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        // Held until the commit, so a concurrent registration waits for this
+        // one to be counted. Reads aren't blocked.
+        if let Err(error) = sqlx::query("LOCK TABLE accounts IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut tx)
+            .await
+        {
+            tracing::event!(tracing::Level::ERROR, "{:?}", error);
+            return Err(Error::DatabaseQueryError(error));
+        }
+
+        let accounts: i64 = match sqlx::query("SELECT COUNT(*) AS accounts FROM accounts")
+            .map(|row: PgRow| row.get("accounts"))
+            .fetch_one(&mut tx)
+            .await
+        {
+            Ok(accounts) => accounts,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+        if accounts >= max_accounts as i64 {
+            return Err(Error::RegistrationClosed);
+        }
+
+        if let Err(error) = sqlx::query(
+            "INSERT INTO accounts (email, password, verified)
+            VALUES ($1, $2, $3)",
+        )
+        .bind(account.email)
+        .bind(account.password)
+        .bind(account.verified)
+        .execute(&mut tx)
+        .await
+        {
+            tracing::event!(tracing::Level::ERROR, "{:?}", error);
+            return Err(Error::DatabaseQueryError(error));
+        }
+
+        match tx.commit().await {
+            Ok(_) => Ok(true),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_stats(&self) -> Result<Stats, Error> {
+        match sqlx::query(
+            "SELECT
+                (SELECT COUNT(*) FROM questions) AS questions,
+                (SELECT COUNT(*) FROM answers) AS answers,
+                (SELECT COUNT(*) FROM accounts) AS accounts",
+        )
+        .map(|row: PgRow| Stats {
+            questions: row.get("questions"),
+            answers: row.get("answers"),
+            accounts: row.get("accounts"),
+        })
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(stats) => Ok(stats),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_account_stats(&self, account_id: &AccountId) -> Result<AccountStats, Error> {
+        match sqlx::query(
+            "SELECT
+                (SELECT COUNT(*) FROM questions
+                    WHERE account_id = accounts.id AND NOT archived) AS questions,
+                (SELECT COUNT(*) FROM answers WHERE account_id = accounts.id) AS answers
+            FROM accounts WHERE id = $1",
+        )
+        .bind(account_id.0)
+        .map(|row: PgRow| AccountStats {
+            account_id: account_id.clone(),
+            questions: row.get("questions"),
+            answers: row.get("answers"),
+        })
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(Some(stats)) => Ok(stats),
+            Ok(None) => Err(Error::AccountNotFound),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_account(&self, email: String) -> Result<Account, Error> {
+        match sqlx::query("SELECT * from accounts where email = $1")
+            .bind(email)
+            .map(|row: PgRow| Account {
+                id: Some(AccountId(row.get("id"))),
+                email: row.get("email"),
+                password: row.get("password"),
+                is_admin: row.get("is_admin"),
+                verified: row.get("verified"),
+            })
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(account) => Ok(account),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_account_profile(&self, account_id: &AccountId) -> Result<AccountProfile, Error> {
+        match sqlx::query(
+            "SELECT id, email, is_admin, verified, last_login_at, last_login_ip, deletion_due_at,
+                suspended_until, notify_on_answer, notify_on_reply
+            FROM accounts WHERE id = $1",
+        )
+        .bind(account_id.0)
+        .map(|row: PgRow| AccountProfile {
+            id: AccountId(row.get("id")),
+            email: row.get("email"),
+            is_admin: row.get("is_admin"),
+            verified: row.get("verified"),
+            last_login_at: row.get("last_login_at"),
+            last_login_ip: row.get("last_login_ip"),
+            deletion_due_at: row.get("deletion_due_at"),
+            suspended_until: row.get("suspended_until"),
+            preferences: NotificationPreferences::from_columns(
+                row.get("notify_on_answer"),
+                row.get("notify_on_reply"),
+            ),
+        })
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(profile) => Ok(profile),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn record_censorship(
+        &self,
+        account_id: &AccountId,
+        resource: &str,
+        preview: &str,
+    ) -> Result<(), Error> {
+        match sqlx::query(
+            "INSERT INTO censorship_audit (account_id, resource, preview) VALUES ($1, $2, $3)",
+        )
+        .bind(account_id.0)
+        .bind(resource)
+        .bind(preview)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_account_questions(&self, account_id: &AccountId) -> Result<Vec<Question>, Error> {
+        match sqlx::query("SELECT * FROM questions WHERE account_id = $1 ORDER BY id")
+            .bind(account_id.0)
+            .map(question_from_row)
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(questions) => Ok(questions),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_account_answers(&self, account_id: &AccountId) -> Result<Vec<Answer>, Error> {
+        match sqlx::query(
+            "SELECT id, content, question_id, parent_answer_id FROM answers
+            WHERE account_id = $1
+            ORDER BY id",
+        )
+        .bind(account_id.0)
+        .map(answer_from_row)
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(mut answers) => {
+                self.load_attachments(&mut answers).await?;
+                Ok(answers)
+            }
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn set_preferences(
+        &self,
+        account_id: &AccountId,
+        preferences: &NotificationPreferences,
+    ) -> Result<(), Error> {
+        match sqlx::query(
+            "UPDATE accounts SET notify_on_answer = $2, notify_on_reply = $3 WHERE id = $1",
+        )
+        .bind(account_id.0)
+        .bind(preferences.notify_on_answer)
+        .bind(preferences.notify_on_reply)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn suspend_account(
+        &self,
+        account_id: &AccountId,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        match sqlx::query("UPDATE accounts SET suspended_until = $2 WHERE id = $1")
+            .bind(account_id.0)
+            .bind(until)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(Error::AccountNotFound),
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_suspension(&self, account_id: &AccountId) -> Result<Option<DateTime<Utc>>, Error> {
+        match sqlx::query("SELECT suspended_until FROM accounts WHERE id = $1")
+            .bind(account_id.0)
+            .map(|row: PgRow| row.get("suspended_until"))
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(until) => Ok(until.flatten()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_account_created_at(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<Option<DateTime<Utc>>, Error> {
+        match sqlx::query("SELECT created_at FROM accounts WHERE id = $1")
+            .bind(account_id.0)
+            .map(|row: PgRow| row.get("created_at"))
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(created_at) => Ok(created_at.flatten()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn record_login(&self, account_id: &AccountId, ip: Option<String>) -> Result<(), Error> {
+        match sqlx::query(
+            "UPDATE accounts SET last_login_at = NOW(), last_login_ip = $2
+            WHERE id = $1",
+        )
+        .bind(account_id.0)
+        .bind(ip)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn schedule_account_deletion(
+        &self,
+        account_id: &AccountId,
+        due_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        match sqlx::query("UPDATE accounts SET deletion_due_at = $2 WHERE id = $1")
+            .bind(account_id.0)
+            .bind(due_at)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn cancel_account_deletion(&self, account_id: &AccountId) -> Result<(), Error> {
+        match sqlx::query(
+            "UPDATE accounts SET deletion_due_at = NULL
+            WHERE id = $1 AND deletion_due_at IS NOT NULL",
+        )
+        .bind(account_id.0)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(Error::DeletionNotScheduled),
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn delete_account(
+        &self,
+        account_id: &AccountId,
+        content: DeletedContent,
+    ) -> Result<(), Error> {
+        let mut tx = match self.connection.begin().await {
+            Ok(tx) => tx,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        match remove_account(&mut tx, account_id.0, content).await {
+            // Dropping the transaction rolls back the content changes
+            Ok(0) => Err(Error::AccountNotFound),
+            Ok(_) => match tx.commit().await {
+                Ok(_) => Ok(()),
+                Err(error) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                    Err(Error::DatabaseQueryError(error))
+                }
+            },
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn delete_due_accounts(
+        &self,
+        now: DateTime<Utc>,
+        content: DeletedContent,
+    ) -> Result<u64, Error> {
+        let due: Vec<i32> = match sqlx::query("SELECT id FROM accounts WHERE deletion_due_at <= $1")
+            .bind(now)
+            .map(|row: PgRow| row.get("id"))
+            .fetch_all(&self.connection)
+            .await
+        {
+            Ok(due) => due,
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+        };
+
+        let mut deleted = 0;
+        for account_id in due {
+            let mut tx = match self.connection.begin().await {
+                Ok(tx) => tx,
+                Err(error) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                    return Err(Error::DatabaseQueryError(error));
+                }
+            };
+
+            let removed = match remove_due_account(&mut tx, account_id, now, content).await {
+                Ok(removed) => removed,
+                Err(error) => {
+                    tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                    return Err(Error::DatabaseQueryError(error));
+                }
+            };
+            if let Err(error) = tx.commit().await {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                return Err(Error::DatabaseQueryError(error));
+            }
+            deleted += removed;
+        }
+
+        Ok(deleted)
+    }
+
+    async fn add_api_token(
+        &self,
+        account_id: &AccountId,
+        name: String,
+        token_hash: String,
+    ) -> Result<ApiToken, Error> {
+        match sqlx::query(
+            "INSERT INTO api_tokens (account_id, name, token_hash)
+            VALUES ($1, $2, $3)
+            RETURNING id, name, created_on",
+        )
+        .bind(account_id.0)
+        .bind(name)
+        .bind(token_hash)
+        .map(api_token_from_row)
+        .fetch_one(&self.connection)
+        .await
+        {
+            Ok(api_token) => Ok(api_token),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_api_tokens(&self, account_id: &AccountId) -> Result<Vec<ApiToken>, Error> {
+        match sqlx::query(
+            "SELECT id, name, created_on FROM api_tokens
+            WHERE account_id = $1
+            ORDER BY id",
+        )
+        .bind(account_id.0)
+        .map(api_token_from_row)
+        .fetch_all(&self.connection)
+        .await
+        {
+            Ok(api_tokens) => Ok(api_tokens),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn delete_api_token(&self, token_id: i32, account_id: &AccountId) -> Result<(), Error> {
+        match sqlx::query("DELETE FROM api_tokens WHERE id = $1 AND account_id = $2")
+            .bind(token_id)
+            .bind(account_id.0)
+            .execute(&self.connection)
+            .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(Error::ApiTokenNotFound),
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_api_token_account(&self, token_hash: &str) -> Result<Option<Account>, Error> {
+        match sqlx::query(
+            "SELECT accounts.* FROM api_tokens
+            JOIN accounts ON accounts.id = api_tokens.account_id
+            WHERE api_tokens.token_hash = $1",
+        )
+        .bind(token_hash)
+        .map(|row: PgRow| Account {
+            id: Some(AccountId(row.get("id"))),
+            email: row.get("email"),
+            password: row.get("password"),
+            is_admin: row.get("is_admin"),
+            verified: row.get("verified"),
+        })
+        .fetch_optional(&self.connection)
+        .await
+        {
+            Ok(account) => Ok(account),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn add_verification_token(
+        &self,
+        account_id: &AccountId,
+        token_hash: &str,
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        match sqlx::query(
+            "INSERT INTO email_verifications (token, account_id, expires_at)
+            VALUES ($1, $2, NOW() + make_interval(secs => $3))",
+        )
+        .bind(token_hash)
+        .bind(account_id.0)
+        .bind(ttl.as_secs_f64())
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn verify_account(&self, token_hash: &str) -> Result<(), Error> {
+        match sqlx::query(
+            "WITH used AS (
+                DELETE FROM email_verifications
+                WHERE token = $1 AND expires_at > NOW()
+                RETURNING account_id
+            )
+            UPDATE accounts SET verified = TRUE
+            WHERE id IN (SELECT account_id FROM used)",
+        )
+        .bind(token_hash)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(Error::InvalidVerificationToken),
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn add_password_reset_token(
+        &self,
+        account_id: &AccountId,
+        token_hash: &str,
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        match sqlx::query(
+            "INSERT INTO password_resets (token_hash, account_id, expires_at)
+            VALUES ($1, $2, NOW() + make_interval(secs => $3))",
+        )
+        .bind(token_hash)
+        .bind(account_id.0)
+        .bind(ttl.as_secs_f64())
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn reset_password(&self, token_hash: &str, password_hash: &str) -> Result<(), Error> {
+        match sqlx::query(
+            "WITH used AS (
+                DELETE FROM password_resets
+                WHERE token_hash = $1 AND expires_at > NOW()
+                RETURNING account_id
+            )
+            UPDATE accounts SET password = $2
+            WHERE id IN (SELECT account_id FROM used)",
+        )
+        .bind(token_hash)
+        .bind(password_hash)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(Error::InvalidResetToken),
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn get_flag(&self, name: &str) -> Result<Option<bool>, Error> {
+        match sqlx::query("SELECT enabled FROM feature_flags WHERE name = $1")
+            .bind(name)
+            .map(|row: PgRow| row.get("enabled"))
+            .fetch_optional(&self.connection)
+            .await
+        {
+            Ok(enabled) => Ok(enabled),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+
+    async fn set_flag(&self, name: &str, enabled: bool) -> Result<(), Error> {
+        match sqlx::query(
+            "INSERT INTO feature_flags (name, enabled) VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET enabled = $2, updated_on = NOW()",
+        )
+        .bind(name)
+        .bind(enabled)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(error) => {
+                tracing::event!(tracing::Level::ERROR, "{:?}", error);
+                Err(Error::DatabaseQueryError(error))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod postgres_tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn pool_timeouts_are_applied() {
+        // A lazy pool doesn't need a running database to be inspected
+        let pool = pool_options::<Postgres>(Some(Duration::from_secs(5)), None, 0)
+            .connect_lazy("postgres://localhost/rustwebdev")
+            .unwrap();
+        let options = format!("{:?}", pool);
+
+        assert!(options.contains("idle_timeout: Some(5s)"));
+        assert!(options.contains("max_lifetime: None"));
+    }
+
+    // The options don't depend on the database, so an in-memory SQLite
+    // database stands in for Postgres
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn warmup_opens_connections_up_front() {
+        let pool = pool_options::<sqlx::Sqlite>(None, None, 3)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        assert_eq!(pool.size(), 3);
+        assert_eq!(pool.num_idle(), 3);
+
+        let pool = pool_options::<sqlx::Sqlite>(None, None, 50)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        assert_eq!(pool.num_idle(), MAX_CONNECTIONS as usize);
+
+        // Without warmup `connect` only opens the one connection it checks
+        // the database with
+        let pool = pool_options::<sqlx::Sqlite>(None, None, 0)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        assert_eq!(pool.size(), 1);
+    }
+
+    #[test]
+    fn statement_cache_capacity_is_applied() {
+        let options = connect_options(
+            "postgres://localhost/rustwebdev",
+            7,
+            DbSslMode::Prefer,
+            None,
+        )
+        .unwrap();
+
+        assert!(format!("{:?}", options).contains("statement_cache_capacity: 7"));
+    }
+
+    #[test]
+    fn require_mode_is_applied_with_the_root_cert() {
+        let cert = std::env::temp_dir().join("rustwebdev-root-cert.pem");
+        std::fs::write(&cert, "").unwrap();
+
+        let options = connect_options(
+            "postgres://localhost/rustwebdev",
+            100,
+            DbSslMode::Require,
+            Some(&cert),
+        )
+        .unwrap();
+        let debug = format!("{:?}", options);
+
+        assert!(debug.contains("ssl_mode: Require"));
+        assert!(debug.contains("rustwebdev-root-cert.pem"));
+    }
+
+    #[test]
+    fn missing_root_certs_are_reported() {
+        let error = connect_options(
+            "postgres://localhost/rustwebdev",
+            100,
+            DbSslMode::VerifyFull,
+            Some(Path::new("/does/not/exist.pem")),
+        )
+        .unwrap_err();
+
+        assert!(error.to_string().contains("/does/not/exist.pem"));
+    }
+}
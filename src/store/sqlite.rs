@@ -0,0 +1,2352 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+use sqlx::Row;
+use sqlx::sqlite::{Sqlite, SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::Transaction;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::{DEFAULT_MAX_ROWS, Store, cap_rows, row_limit};
+use crate::types::{
+    account::{
+        Account, AccountId, AccountProfile, DELETED_ACCOUNT, DeletedContent,
+        NotificationPreferences,
+    },
+    activity::Activity,
+    api_token::{ApiToken, ApiTokenId},
+    answer::{Answer, AnswerId, NewAnswer, normalized_content},
+    question::{NewQuestion, Question, QuestionId},
+    search::SearchTerm,
+    sort::{Sort, SortColumn},
+    stats::{AccountStats, Stats},
+    tag::{TagCount, TagFilter},
+    vote::Vote,
+};
+use handle_errors::Error;
+
+/// Extended result codes SQLite reports for UNIQUE and PRIMARY KEY violations
+const SQLITE_CONSTRAINT_UNIQUE: &str = "2067";
+const SQLITE_CONSTRAINT_PRIMARYKEY: &str = "1555";
+
+fn query_error(error: sqlx::Error) -> Error {
+    tracing::event!(tracing::Level::ERROR, "{:?}", error);
+    Error::DatabaseQueryError(error)
+}
+
+fn is_unique_violation(error: &sqlx::Error) -> bool {
+    error
+        .as_database_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| {
+            code == SQLITE_CONSTRAINT_UNIQUE || code == SQLITE_CONSTRAINT_PRIMARYKEY
+        })
+}
+
+/// Tags live in a TEXT column as a JSON array
+fn encode_tags(tags: &Option<Vec<String>>) -> Option<String> {
+    tags.as_ref()
+        .map(|tags| serde_json::to_string(tags).expect("tags serialize to JSON"))
+}
+
+fn decode_tags(tags: Option<String>) -> Option<Vec<String>> {
+    tags.and_then(|tags| serde_json::from_str(&tags).ok())
+}
+
+/// The statements behind `Store::delete_account`, returning how many
+/// accounts rows went (0 or 1)
+async fn remove_account(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_id: i32,
+    content: DeletedContent,
+) -> Result<u64, sqlx::Error> {
+    let statements = match content {
+        // Replies to these answers follow through parent_answer_id's
+        // ON DELETE CASCADE, and the questions' votes, bookmarks and
+        // tags through question_id's
+        DeletedContent::Delete => [
+            "DELETE FROM answers WHERE account_id = ?1
+            OR question_id IN (SELECT id FROM questions WHERE account_id = ?1)",
+            "DELETE FROM questions WHERE account_id = ?1",
+        ],
+        // Retry keys are only unique per account, so they'd clash with
+        // other deleted accounts' under DELETED_ACCOUNT
+        DeletedContent::Anonymize => [
+            "UPDATE questions SET account_id = ?2 WHERE account_id = ?1",
+            "UPDATE answers SET account_id = ?2, client_id = NULL WHERE account_id = ?1",
+        ],
+    };
+    for statement in statements {
+        sqlx::query(statement)
+            .bind(account_id)
+            .bind(DELETED_ACCOUNT.0)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    // Votes stay, so scores don't shift when an account goes
+    sqlx::query("DELETE FROM bookmarks WHERE account_id = ?")
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+    let result = sqlx::query("DELETE FROM accounts WHERE id = ?")
+        .bind(account_id)
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// `remove_account` for an account whose deletion is still due, returning
+/// 0 without touching anything when it was cancelled in the meantime
+async fn remove_due_account(
+    tx: &mut Transaction<'_, Sqlite>,
+    account_id: i32,
+    now: DateTime<Utc>,
+    content: DeletedContent,
+) -> Result<u64, sqlx::Error> {
+    let due = sqlx::query("SELECT id FROM accounts WHERE id = ? AND deletion_due_at <= ?")
+        .bind(account_id)
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if due.is_none() {
+        return Ok(0);
+    }
+
+    remove_account(tx, account_id, content).await
+}
+
+/// Points the question at its tags in question_tags, replacing any links
+/// it had and creating the tag rows which don't exist yet
+async fn link_tags(
+    tx: &mut Transaction<'_, Sqlite>,
+    question_id: i64,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM question_tags WHERE question_id = ?")
+        .bind(question_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for tag in tags {
+        sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO question_tags (question_id, tag_id)
+            SELECT ?, id FROM tags WHERE name = ?",
+        )
+        .bind(question_id)
+        .bind(tag)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+fn question_from_row(row: SqliteRow) -> Question {
+    Question {
+        id: QuestionId(row.get("id")),
+        title: row.get("title"),
+        content: row.get("content"),
+        tags: decode_tags(row.get("tags")),
+        expires_at: row.get("expires_at"),
+        content_format: row
+            .get::<String, _>("content_format")
+            .parse()
+            .unwrap_or_default(),
+    }
+}
+
+fn answer_from_row(row: SqliteRow) -> Answer {
+    Answer {
+        id: AnswerId(row.get("id")),
+        content: row.get("content"),
+        question_id: QuestionId(row.get("question_id")),
+        parent_answer_id: row
+            .get::<Option<i32>, _>("parent_answer_id")
+            .map(AnswerId),
+        // Kept in answer_attachments, see `SqliteStore::load_attachments`
+        attachments: Vec::new(),
+    }
+}
+
+fn account_from_row(row: SqliteRow) -> Account {
+    Account {
+        id: Some(AccountId(row.get("id"))),
+        email: row.get("email"),
+        password: row.get("password"),
+        is_admin: row.get("is_admin"),
+        verified: row.get("verified"),
+    }
+}
+
+fn api_token_from_row(row: SqliteRow) -> ApiToken {
+    ApiToken {
+        id: ApiTokenId(row.get("id")),
+        name: row.get("name"),
+        created_on: row.get("created_on"),
+    }
+}
+
+/// Lightweight backend for single-node deployments and tests. Mirrors
+/// `PgStore` on top of the schema in `sqlite-migrations`.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    pub connection: SqlitePool,
+    max_rows: usize,
+}
+
+impl SqliteStore {
+    pub async fn new(db_url: &str, statement_cache_capacity: usize) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(db_url)?
+            .statement_cache_capacity(statement_cache_capacity);
+        // SQLite takes one writer at a time, and every connection to an
+        // in-memory database would open a database of its own
+        let db_pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+
+        Ok(SqliteStore {
+            connection: db_pool,
+            max_rows: DEFAULT_MAX_ROWS,
+        })
+    }
+
+    /// Caps what list methods return, see `cap_rows`
+    pub fn with_max_rows(self, max_rows: usize) -> Self {
+        SqliteStore { max_rows, ..self }
+    }
+
+    /// Fill in the attachments of `answers` from answer_attachments
+    async fn load_attachments(&self, answers: &mut [Answer]) -> Result<(), Error> {
+        if answers.is_empty() {
+            return Ok(());
+        }
+
+        let placeholders = vec!["?"; answers.len()].join(", ");
+        let query = format!(
+            "SELECT answer_id, url FROM answer_attachments
+            WHERE answer_id IN ({})
+            ORDER BY answer_id, position",
+            placeholders
+        );
+        let mut query = sqlx::query(&query);
+        for answer in answers.iter() {
+            query = query.bind(answer.id.0);
+        }
+
+        let attachments = query
+            .map(|row: SqliteRow| (row.get::<i32, _>("answer_id"), row.get::<String, _>("url")))
+            .fetch_all(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        for (answer_id, url) in attachments {
+            if let Some(answer) = answers.iter_mut().find(|answer| answer.id.0 == answer_id) {
+                answer.attachments.push(url);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    async fn is_question_owner(
+        &self,
+        question_id: i32,
+        account_id: &AccountId,
+    ) -> Result<bool, Error> {
+        sqlx::query("SELECT id FROM questions WHERE id = ? AND account_id = ?")
+            .bind(question_id)
+            .bind(account_id.0)
+            .fetch_optional(&self.connection)
+            .await
+            .map(|question| question.is_some())
+            .map_err(query_error)
+    }
+
+    async fn question_created_on(&self, question_id: i32) -> Result<DateTime<Utc>, Error> {
+        sqlx::query("SELECT created_on FROM questions WHERE id = ?")
+            .bind(question_id)
+            .map(|row: SqliteRow| row.get("created_on"))
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(query_error)?
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn question_modified_on(&self, question_id: i32) -> Result<DateTime<Utc>, Error> {
+        sqlx::query(
+            "SELECT COALESCE(updated_on, created_on) AS modified_on FROM questions WHERE id = ?",
+        )
+        .bind(question_id)
+        .map(|row: SqliteRow| row.get("modified_on"))
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(query_error)?
+        .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn get_questions(
+        &self,
+        limit: Option<u32>,
+        offset: u32,
+        sort: Sort,
+        include_archived: bool,
+        tags: Option<TagFilter>,
+    ) -> Result<Vec<Question>, Error> {
+        // Column and direction come from a closed set of enum variants,
+        // so formatting them into the statement is safe
+        let source = match sort.column {
+            SortColumn::Answers => {
+                "questions LEFT JOIN (SELECT question_id AS question, COUNT(*) AS answer_count \
+                 FROM answers GROUP BY question_id) AS counts ON counts.question = questions.id"
+            }
+            SortColumn::Top => {
+                "questions LEFT JOIN (SELECT question_id AS question, SUM(value) AS score \
+                 FROM votes GROUP BY question_id) AS scores ON scores.question = questions.id"
+            }
+            _ => "questions",
+        };
+        let placeholders = vec!["?"; tags.as_ref().map_or(0, |tags| tags.tags().len())].join(", ");
+        let tag_condition = match &tags {
+            None => String::new(),
+            Some(TagFilter::Any(_)) => format!(
+                " AND questions.id IN (SELECT question_id FROM question_tags
+                    JOIN tags ON tags.id = question_tags.tag_id WHERE tags.name IN ({}))",
+                placeholders
+            ),
+            Some(TagFilter::All(tags)) => format!(
+                " AND questions.id IN (SELECT question_id FROM question_tags
+                    JOIN tags ON tags.id = question_tags.tag_id WHERE tags.name IN ({})
+                    GROUP BY question_id HAVING COUNT(*) = {})",
+                placeholders,
+                tags.len()
+            ),
+        };
+        let query = format!(
+            "SELECT * FROM {} WHERE (? OR NOT archived){}
+            ORDER BY is_pinned DESC, {} LIMIT ? OFFSET ?",
+            source,
+            tag_condition,
+            sort.order_by()
+        );
+
+        let mut query = sqlx::query(&query).bind(include_archived);
+        for tag in tags.as_ref().map_or(&[][..], TagFilter::tags) {
+            query = query.bind(tag);
+        }
+        let questions = query
+            .bind(row_limit(limit, self.max_rows))
+            .bind(offset)
+            .map(question_from_row)
+            .fetch_all(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        Ok(cap_rows(questions, self.max_rows, "get_questions"))
+    }
+
+    async fn get_question(&self, question_id: i32) -> Result<Question, Error> {
+        sqlx::query("SELECT * FROM questions WHERE id = ?")
+            .bind(question_id)
+            .map(question_from_row)
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(query_error)?
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn random_question(&self) -> Result<Question, Error> {
+        sqlx::query("SELECT * FROM questions WHERE NOT archived ORDER BY random() LIMIT 1")
+            .map(question_from_row)
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(query_error)?
+            .ok_or(Error::QuestionNotFound)
+    }
+
+    async fn count_search_results(&self, term: &SearchTerm) -> Result<i64, Error> {
+        // LIKE ignores case here too, though only for ASCII letters
+        sqlx::query(
+            "SELECT COUNT(*) AS count FROM questions
+            WHERE NOT archived AND (title LIKE ?1 ESCAPE '\\' OR content LIKE ?1 ESCAPE '\\')",
+        )
+        .bind(term.like_pattern())
+        .map(|row: SqliteRow| row.get("count"))
+        .fetch_one(&self.connection)
+        .await
+        .map_err(query_error)
+    }
+
+    async fn stream_questions(
+        &self,
+        limit: Option<u32>,
+    ) -> Result<BoxStream<'static, Result<Question, Error>>, Error> {
+        // SQLite databases are small enough to read in one go
+        let questions = sqlx::query("SELECT * FROM questions WHERE NOT archived ORDER BY id LIMIT ?")
+            .bind(limit.map_or(-1, i64::from))
+            .map(question_from_row)
+            .fetch_all(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        Ok(stream::iter(questions.into_iter().map(Ok)).boxed())
+    }
+
+    async fn add_question(
+        &self,
+        new_question: NewQuestion,
+        account_id: AccountId,
+    ) -> Result<Question, Error> {
+        let content_format = new_question.format();
+        let mut tx = self.connection.begin().await.map_err(query_error)?;
+        let result = sqlx::query(
+            "INSERT INTO questions
+                (title, content, tags, created_on, account_id, expires_at, content_format)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&new_question.title)
+        .bind(&new_question.content)
+        .bind(encode_tags(&new_question.tags))
+        .bind(Utc::now())
+        .bind(account_id.0)
+        .bind(new_question.expires_at)
+        .bind(content_format.as_str())
+        .execute(&mut tx)
+        .await
+        .map_err(query_error)?;
+
+        let question_id = result.last_insert_rowid();
+        link_tags(&mut tx, question_id, new_question.tags.as_deref().unwrap_or_default())
+            .await
+            .map_err(query_error)?;
+        tx.commit().await.map_err(query_error)?;
+
+        Ok(Question {
+            id: QuestionId(question_id as i32),
+            title: new_question.title,
+            content: new_question.content,
+            tags: new_question.tags,
+            expires_at: new_question.expires_at,
+            content_format,
+        })
+    }
+
+    async fn update_question(
+        &self,
+        question: Question,
+        question_id: i32,
+        account_id: AccountId,
+    ) -> Result<Question, Error> {
+        let mut tx = self.connection.begin().await.map_err(query_error)?;
+        let result = sqlx::query(
+            "UPDATE questions SET title = ?, content = ?, tags = ?, content_format = ?,
+                updated_on = ?
+            WHERE id = ? AND account_id = ?",
+        )
+        .bind(&question.title)
+        .bind(&question.content)
+        .bind(encode_tags(&question.tags))
+        .bind(question.content_format.as_str())
+        .bind(Utc::now())
+        .bind(question_id)
+        .bind(account_id.0)
+        .execute(&mut tx)
+        .await
+        .map_err(query_error)?;
+
+        // Same outcome as Postgres' UPDATE ... RETURNING matching no row
+        if result.rows_affected() == 0 {
+            return Err(query_error(sqlx::Error::RowNotFound));
+        }
+        link_tags(&mut tx, i64::from(question_id), question.tags.as_deref().unwrap_or_default())
+            .await
+            .map_err(query_error)?;
+        tx.commit().await.map_err(query_error)?;
+
+        self.get_question(question_id).await
+    }
+
+    async fn archive_expired_questions(&self, now: DateTime<Utc>) -> Result<u64, Error> {
+        sqlx::query("UPDATE questions SET archived = 1 WHERE expires_at <= ? AND NOT archived")
+            .bind(now)
+            .execute(&self.connection)
+            .await
+            .map(|result| result.rows_affected())
+            .map_err(query_error)
+    }
+
+    async fn delete_question(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+    ) -> Result<(), Error> {
+        sqlx::query("DELETE FROM questions WHERE id = ? AND account_id = ?")
+            .bind(question_id)
+            .bind(account_id.0)
+            .execute(&self.connection)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn remove_question(&self, question_id: i32) -> Result<(), Error> {
+        let result = sqlx::query("DELETE FROM questions WHERE id = ?")
+            .bind(question_id)
+            .execute(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::QuestionNotFound);
+        }
+        Ok(())
+    }
+
+    async fn set_pinned(&self, question_id: i32, pinned: bool) -> Result<(), Error> {
+        let result = sqlx::query("UPDATE questions SET is_pinned = ? WHERE id = ?")
+            .bind(pinned)
+            .bind(question_id)
+            .execute(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::QuestionNotFound);
+        }
+        Ok(())
+    }
+
+    async fn add_answer(
+        &self,
+        new_answer: NewAnswer,
+        account_id: AccountId,
+    ) -> Result<Answer, Error> {
+        // The pool holds a single connection, so the transaction has to be
+        // over before the answer is read back
+        let mut tx = self.connection.begin().await.map_err(query_error)?;
+
+        // A client_id the account already used means this is a retry of an
+        // insert that went through, so the stored answer is handed back
+        let result = sqlx::query(
+            "INSERT INTO answers
+                (content, content_key, created_on, question_id, parent_answer_id, account_id, client_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT (account_id, client_id) DO NOTHING",
+        )
+        .bind(&new_answer.content)
+        .bind(normalized_content(&new_answer.content))
+        .bind(Utc::now())
+        .bind(new_answer.question_id.0)
+        .bind(new_answer.parent_answer_id.map(|parent| parent.0))
+        .bind(account_id.0)
+        .bind(&new_answer.client_id)
+        .execute(&mut tx)
+        .await;
+
+        let inserted = match result {
+            Ok(result) if result.rows_affected() > 0 => Some(result.last_insert_rowid()),
+            Ok(_) => None,
+            Err(error) if is_unique_violation(&error) => return Err(Error::DuplicateAnswer),
+            Err(error) => return Err(query_error(error)),
+        };
+
+        if let Some(answer_id) = inserted {
+            for (position, url) in new_answer.attachments.iter().enumerate() {
+                sqlx::query(
+                    "INSERT INTO answer_attachments (answer_id, position, url)
+                    VALUES (?, ?, ?)",
+                )
+                .bind(answer_id)
+                .bind(position as i64)
+                .bind(url)
+                .execute(&mut tx)
+                .await
+                .map_err(query_error)?;
+            }
+        }
+        tx.commit().await.map_err(query_error)?;
+
+        let lookup = match inserted {
+            Some(answer_id) => sqlx::query(
+                "SELECT id, content, question_id, parent_answer_id FROM answers
+                WHERE id = ?",
+            )
+            .bind(answer_id),
+            None => sqlx::query(
+                "SELECT id, content, question_id, parent_answer_id FROM answers
+                WHERE account_id = ? AND client_id = ?",
+            )
+            .bind(account_id.0)
+            .bind(new_answer.client_id),
+        };
+        let mut answer = lookup
+            .map(answer_from_row)
+            .fetch_one(&self.connection)
+            .await
+            .map_err(query_error)?;
+        self.load_attachments(std::slice::from_mut(&mut answer)).await?;
+
+        Ok(answer)
+    }
+
+    async fn get_answer(&self, answer_id: i32) -> Result<Answer, Error> {
+        let mut answers = sqlx::query(
+            "SELECT id, content, question_id, parent_answer_id FROM answers WHERE id = ?",
+        )
+        .bind(answer_id)
+        .map(answer_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(query_error)?;
+        self.load_attachments(&mut answers).await?;
+
+        answers.pop().ok_or(Error::AnswerNotFound)
+    }
+
+    async fn get_answers(&self, question_id: i32) -> Result<Vec<Answer>, Error> {
+        let mut answers = sqlx::query(
+            "SELECT id, content, question_id, parent_answer_id FROM answers
+            WHERE question_id = ?
+            ORDER BY id",
+        )
+        .bind(question_id)
+        .map(answer_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(query_error)?;
+        self.load_attachments(&mut answers).await?;
+
+        Ok(answers)
+    }
+
+    async fn get_answers_for_questions(
+        &self,
+        question_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<Answer>>, Error> {
+        let mut grouped: HashMap<i32, Vec<Answer>> =
+            question_ids.iter().map(|id| (*id, Vec::new())).collect();
+        if question_ids.is_empty() {
+            return Ok(grouped);
+        }
+
+        // No array parameters in SQLite, so one placeholder per id
+        let placeholders = vec!["?"; question_ids.len()].join(", ");
+        let query = format!(
+            "SELECT id, content, question_id, parent_answer_id FROM answers
+            WHERE question_id IN ({})
+            ORDER BY id",
+            placeholders
+        );
+        let mut query = sqlx::query(&query);
+        for question_id in question_ids {
+            query = query.bind(*question_id);
+        }
+        let mut answers = query
+            .map(answer_from_row)
+            .fetch_all(&self.connection)
+            .await
+            .map_err(query_error)?;
+        self.load_attachments(&mut answers).await?;
+
+        for answer in answers {
+            grouped.entry(answer.question_id.0).or_default().push(answer);
+        }
+        Ok(grouped)
+    }
+
+    async fn expire_client_ids(&self, before: DateTime<Utc>) -> Result<u64, Error> {
+        sqlx::query(
+            "UPDATE answers SET client_id = NULL WHERE client_id IS NOT NULL AND created_on < ?",
+        )
+        .bind(before)
+        .execute(&self.connection)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(query_error)
+    }
+
+    async fn count_answers(&self, question_id: i32) -> Result<i64, Error> {
+        sqlx::query("SELECT COUNT(*) AS count FROM answers WHERE question_id = ?")
+            .bind(question_id)
+            .map(|row: SqliteRow| row.get("count"))
+            .fetch_one(&self.connection)
+            .await
+            .map_err(query_error)
+    }
+
+    async fn get_question_activity(
+        &self,
+        question_id: i32,
+        limit: Option<u32>,
+        offset: u32,
+    ) -> Result<Vec<Activity>, Error> {
+        let activity = sqlx::query(
+            "SELECT kind, at, answer_id FROM (
+                SELECT 'created' AS kind, created_on AS at, NULL AS answer_id
+                FROM questions WHERE id = ?1
+                UNION ALL
+                SELECT 'edited', updated_on, NULL FROM questions
+                WHERE id = ?1 AND updated_on IS NOT NULL
+                UNION ALL
+                SELECT 'answered', created_on, id FROM answers WHERE question_id = ?1
+                UNION ALL
+                SELECT 'voted', created_on, NULL FROM votes
+                WHERE question_id = ?1 AND created_on IS NOT NULL
+            )
+            ORDER BY at, answer_id, kind
+            LIMIT ?2 OFFSET ?3",
+        )
+        .bind(question_id)
+        .bind(row_limit(limit, self.max_rows))
+        .bind(i64::from(offset))
+        .map(|row: SqliteRow| Activity {
+            kind: row.get::<String, _>("kind").parse().unwrap_or_default(),
+            at: row.get("at"),
+            answer_id: row.get::<Option<i32>, _>("answer_id").map(AnswerId),
+        })
+        .fetch_all(&self.connection)
+        .await
+        .map_err(query_error)?;
+
+        Ok(cap_rows(activity, self.max_rows, "get_question_activity"))
+    }
+
+    async fn move_answer(&self, answer_id: i32, question_id: i32) -> Result<Answer, Error> {
+        let mut tx = self.connection.begin().await.map_err(query_error)?;
+
+        let target = sqlx::query("SELECT id FROM questions WHERE id = ?")
+            .bind(question_id)
+            .fetch_optional(&mut tx)
+            .await
+            .map_err(query_error)?;
+        if target.is_none() {
+            return Err(Error::QuestionNotFound);
+        }
+
+        let result = sqlx::query(
+            "WITH RECURSIVE thread AS (
+                SELECT id FROM answers WHERE id = ?1
+                UNION ALL
+                SELECT answers.id FROM answers JOIN thread ON answers.parent_answer_id = thread.id
+            )
+            UPDATE answers SET
+                question_id = ?2,
+                parent_answer_id = CASE WHEN id = ?1 THEN NULL ELSE parent_answer_id END
+            WHERE id IN (SELECT id FROM thread)",
+        )
+        .bind(answer_id)
+        .bind(question_id)
+        .execute(&mut tx)
+        .await;
+
+        match result {
+            Ok(result) if result.rows_affected() == 0 => return Err(Error::AnswerNotFound),
+            Ok(_) => (),
+            Err(error) if is_unique_violation(&error) => return Err(Error::DuplicateAnswer),
+            Err(error) => return Err(query_error(error)),
+        }
+        tx.commit().await.map_err(query_error)?;
+
+        let mut answers = sqlx::query(
+            "SELECT id, content, question_id, parent_answer_id FROM answers
+            WHERE id = ?",
+        )
+        .bind(answer_id)
+        .map(answer_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(query_error)?;
+        self.load_attachments(&mut answers).await?;
+
+        answers.pop().ok_or(Error::AnswerNotFound)
+    }
+
+    async fn get_tags(&self, limit: Option<u32>, offset: u32) -> Result<Vec<TagCount>, Error> {
+        let tags = sqlx::query(
+            "SELECT tags.name, COUNT(*) AS count
+            FROM question_tags JOIN tags ON tags.id = question_tags.tag_id
+            GROUP BY tags.name
+            ORDER BY count DESC, name ASC
+            LIMIT ? OFFSET ?",
+        )
+        .bind(row_limit(limit, self.max_rows))
+        .bind(i64::from(offset))
+        .map(|row: SqliteRow| TagCount {
+            name: row.get("name"),
+            count: row.get("count"),
+        })
+        .fetch_all(&self.connection)
+        .await
+        .map_err(query_error)?;
+
+        Ok(cap_rows(tags, self.max_rows, "get_tags"))
+    }
+
+    async fn cast_vote(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+        vote: Vote,
+    ) -> Result<i64, Error> {
+        let mut tx = self.connection.begin().await.map_err(query_error)?;
+        sqlx::query(
+            "INSERT INTO votes (account_id, question_id, value, created_on)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (account_id, question_id) DO UPDATE SET value = excluded.value",
+        )
+        .bind(account_id.0)
+        .bind(question_id)
+        .bind(vote.value())
+        .bind(Utc::now())
+        .execute(&mut tx)
+        .await
+        .map_err(query_error)?;
+
+        let score = sqlx::query(
+            "SELECT COALESCE(SUM(value), 0) AS score FROM votes WHERE question_id = ?",
+        )
+        .bind(question_id)
+        .map(|row: SqliteRow| row.get::<i64, _>("score"))
+        .fetch_one(&mut tx)
+        .await
+        .map_err(query_error)?;
+        tx.commit().await.map_err(query_error)?;
+
+        Ok(score)
+    }
+
+    async fn get_votes(
+        &self,
+        account_id: &AccountId,
+        question_ids: &[i32],
+    ) -> Result<HashMap<i32, Vote>, Error> {
+        if question_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        // No array parameters in SQLite, so one placeholder per id
+        let placeholders = vec!["?"; question_ids.len()].join(", ");
+        let query = format!(
+            "SELECT question_id, value FROM votes
+            WHERE account_id = ? AND question_id IN ({})",
+            placeholders
+        );
+
+        let mut query = sqlx::query(&query).bind(account_id.0);
+        for question_id in question_ids {
+            query = query.bind(*question_id);
+        }
+
+        let votes = query
+            .map(|row: SqliteRow| {
+                (
+                    row.get::<i32, _>("question_id"),
+                    Vote::from_value(row.get("value")),
+                )
+            })
+            .fetch_all(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        Ok(votes
+            .into_iter()
+            .filter_map(|(question_id, vote)| Some((question_id, vote?)))
+            .collect())
+    }
+
+    async fn get_account_votes(&self, account_id: &AccountId) -> Result<HashMap<i32, Vote>, Error> {
+        let votes = sqlx::query("SELECT question_id, value FROM votes WHERE account_id = ?")
+            .bind(account_id.0)
+            .map(|row: SqliteRow| {
+                (
+                    row.get::<i32, _>("question_id"),
+                    Vote::from_value(row.get("value")),
+                )
+            })
+            .fetch_all(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        Ok(votes
+            .into_iter()
+            .filter_map(|(question_id, vote)| Some((question_id, vote?)))
+            .collect())
+    }
+
+    async fn add_bookmark(&self, question_id: i32, account_id: AccountId) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO bookmarks (account_id, question_id, created_on) VALUES (?, ?, ?)
+            ON CONFLICT (account_id, question_id) DO NOTHING",
+        )
+        .bind(account_id.0)
+        .bind(question_id)
+        .bind(Utc::now())
+        .execute(&self.connection)
+        .await
+        .map_err(query_error)?;
+
+        Ok(())
+    }
+
+    async fn remove_bookmark(
+        &self,
+        question_id: i32,
+        account_id: AccountId,
+    ) -> Result<(), Error> {
+        sqlx::query("DELETE FROM bookmarks WHERE account_id = ? AND question_id = ?")
+            .bind(account_id.0)
+            .bind(question_id)
+            .execute(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        Ok(())
+    }
+
+    async fn get_bookmarks(
+        &self,
+        account_id: &AccountId,
+        limit: Option<u32>,
+        offset: u32,
+    ) -> Result<Vec<Question>, Error> {
+        let questions = sqlx::query(
+            "SELECT questions.* FROM bookmarks
+            JOIN questions ON questions.id = bookmarks.question_id
+            WHERE bookmarks.account_id = ?
+            ORDER BY bookmarks.created_on DESC, questions.id DESC
+            LIMIT ? OFFSET ?",
+        )
+        .bind(account_id.0)
+        .bind(row_limit(limit, self.max_rows))
+        .bind(offset)
+        .map(question_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(query_error)?;
+
+        Ok(cap_rows(questions, self.max_rows, "get_bookmarks"))
+    }
+
+    async fn add_account(&self, account: Account) -> Result<bool, Error> {
+        self.add_account_capped(account, 0).await
+    }
+
+    async fn add_account_capped(
+        &self,
+        account: Account,
+        max_accounts: u64,
+    ) -> Result<bool, Error> {
+        // A single statement, so the count can't go stale before the insert
+        match sqlx::query(
+            "INSERT INTO accounts (email, password, is_admin, verified, created_at)
+            SELECT ?1, ?2, ?3, ?4, ?5
+            WHERE ?6 = 0 OR (SELECT COUNT(*) FROM accounts) < ?6",
+        )
+        .bind(account.email)
+        .bind(account.password)
+        .bind(account.is_admin)
+        .bind(account.verified)
+        .bind(Utc::now())
+        .bind(max_accounts as i64)
+        .execute(&self.connection)
+        .await
+        {
+            Ok(result) if result.rows_affected() == 0 => Err(Error::RegistrationClosed),
+            Ok(_) => Ok(true),
+            Err(error) if is_unique_violation(&error) => Err(Error::DuplicateAccount),
+            Err(error) => Err(query_error(error)),
+        }
+    }
+
+    async fn get_stats(&self) -> Result<Stats, Error> {
+        sqlx::query(
+            "SELECT
+                (SELECT COUNT(*) FROM questions) AS questions,
+                (SELECT COUNT(*) FROM answers) AS answers,
+                (SELECT COUNT(*) FROM accounts) AS accounts",
+        )
+        .map(|row: SqliteRow| Stats {
+            questions: row.get("questions"),
+            answers: row.get("answers"),
+            accounts: row.get("accounts"),
+        })
+        .fetch_one(&self.connection)
+        .await
+        .map_err(query_error)
+    }
+
+    async fn get_account_stats(&self, account_id: &AccountId) -> Result<AccountStats, Error> {
+        sqlx::query(
+            "SELECT
+                (SELECT COUNT(*) FROM questions
+                    WHERE account_id = accounts.id AND NOT archived) AS questions,
+                (SELECT COUNT(*) FROM answers WHERE account_id = accounts.id) AS answers
+            FROM accounts WHERE id = ?",
+        )
+        .bind(account_id.0)
+        .map(|row: SqliteRow| AccountStats {
+            account_id: account_id.clone(),
+            questions: row.get("questions"),
+            answers: row.get("answers"),
+        })
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(query_error)?
+        .ok_or(Error::AccountNotFound)
+    }
+
+    async fn get_account(&self, email: String) -> Result<Account, Error> {
+        sqlx::query("SELECT * FROM accounts WHERE email = ?")
+            .bind(email)
+            .map(account_from_row)
+            .fetch_one(&self.connection)
+            .await
+            .map_err(query_error)
+    }
+
+    async fn get_account_profile(&self, account_id: &AccountId) -> Result<AccountProfile, Error> {
+        sqlx::query(
+            "SELECT id, email, is_admin, verified, last_login_at, last_login_ip, deletion_due_at,
+                suspended_until, notify_on_answer, notify_on_reply
+            FROM accounts WHERE id = ?",
+        )
+        .bind(account_id.0)
+        .map(|row: SqliteRow| AccountProfile {
+            id: AccountId(row.get("id")),
+            email: row.get("email"),
+            is_admin: row.get("is_admin"),
+            verified: row.get("verified"),
+            last_login_at: row.get("last_login_at"),
+            last_login_ip: row.get("last_login_ip"),
+            deletion_due_at: row.get("deletion_due_at"),
+            suspended_until: row.get("suspended_until"),
+            preferences: NotificationPreferences::from_columns(
+                row.get("notify_on_answer"),
+                row.get("notify_on_reply"),
+            ),
+        })
+        .fetch_one(&self.connection)
+        .await
+        .map_err(query_error)
+    }
+
+    async fn record_censorship(
+        &self,
+        account_id: &AccountId,
+        resource: &str,
+        preview: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO censorship_audit (account_id, resource, preview, created_on)
+            VALUES (?, ?, ?, ?)",
+        )
+        .bind(account_id.0)
+        .bind(resource)
+        .bind(preview)
+        .bind(Utc::now())
+        .execute(&self.connection)
+        .await
+        .map(|_| ())
+        .map_err(query_error)
+    }
+
+    async fn get_account_questions(&self, account_id: &AccountId) -> Result<Vec<Question>, Error> {
+        sqlx::query("SELECT * FROM questions WHERE account_id = ? ORDER BY id")
+            .bind(account_id.0)
+            .map(question_from_row)
+            .fetch_all(&self.connection)
+            .await
+            .map_err(query_error)
+    }
+
+    async fn get_account_answers(&self, account_id: &AccountId) -> Result<Vec<Answer>, Error> {
+        let mut answers = sqlx::query(
+            "SELECT id, content, question_id, parent_answer_id FROM answers
+            WHERE account_id = ?
+            ORDER BY id",
+        )
+        .bind(account_id.0)
+        .map(answer_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(query_error)?;
+        self.load_attachments(&mut answers).await?;
+
+        Ok(answers)
+    }
+
+    async fn set_preferences(
+        &self,
+        account_id: &AccountId,
+        preferences: &NotificationPreferences,
+    ) -> Result<(), Error> {
+        sqlx::query("UPDATE accounts SET notify_on_answer = ?, notify_on_reply = ? WHERE id = ?")
+            .bind(preferences.notify_on_answer)
+            .bind(preferences.notify_on_reply)
+            .bind(account_id.0)
+            .execute(&self.connection)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn suspend_account(
+        &self,
+        account_id: &AccountId,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<(), Error> {
+        let result = sqlx::query("UPDATE accounts SET suspended_until = ? WHERE id = ?")
+            .bind(until)
+            .bind(account_id.0)
+            .execute(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::AccountNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn get_suspension(&self, account_id: &AccountId) -> Result<Option<DateTime<Utc>>, Error> {
+        sqlx::query("SELECT suspended_until FROM accounts WHERE id = ?")
+            .bind(account_id.0)
+            .map(|row: SqliteRow| row.get("suspended_until"))
+            .fetch_optional(&self.connection)
+            .await
+            .map(Option::flatten)
+            .map_err(query_error)
+    }
+
+    async fn get_account_created_at(
+        &self,
+        account_id: &AccountId,
+    ) -> Result<Option<DateTime<Utc>>, Error> {
+        sqlx::query("SELECT created_at FROM accounts WHERE id = ?")
+            .bind(account_id.0)
+            .map(|row: SqliteRow| row.get("created_at"))
+            .fetch_optional(&self.connection)
+            .await
+            .map(Option::flatten)
+            .map_err(query_error)
+    }
+
+    async fn record_login(&self, account_id: &AccountId, ip: Option<String>) -> Result<(), Error> {
+        sqlx::query("UPDATE accounts SET last_login_at = ?, last_login_ip = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(ip)
+            .bind(account_id.0)
+            .execute(&self.connection)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn schedule_account_deletion(
+        &self,
+        account_id: &AccountId,
+        due_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        sqlx::query("UPDATE accounts SET deletion_due_at = ? WHERE id = ?")
+            .bind(due_at)
+            .bind(account_id.0)
+            .execute(&self.connection)
+            .await
+            .map(|_| ())
+            .map_err(query_error)
+    }
+
+    async fn cancel_account_deletion(&self, account_id: &AccountId) -> Result<(), Error> {
+        let result = sqlx::query(
+            "UPDATE accounts SET deletion_due_at = NULL
+            WHERE id = ? AND deletion_due_at IS NOT NULL",
+        )
+        .bind(account_id.0)
+        .execute(&self.connection)
+        .await
+        .map_err(query_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::DeletionNotScheduled);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_account(
+        &self,
+        account_id: &AccountId,
+        content: DeletedContent,
+    ) -> Result<(), Error> {
+        let mut tx = self.connection.begin().await.map_err(query_error)?;
+        let removed = remove_account(&mut tx, account_id.0, content)
+            .await
+            .map_err(query_error)?;
+        if removed == 0 {
+            // Dropping the transaction rolls back the content changes
+            return Err(Error::AccountNotFound);
+        }
+        tx.commit().await.map_err(query_error)?;
+
+        Ok(())
+    }
+
+    async fn delete_due_accounts(
+        &self,
+        now: DateTime<Utc>,
+        content: DeletedContent,
+    ) -> Result<u64, Error> {
+        // Timestamps are stored in one format, so comparing the text
+        // orders them chronologically
+        let due: Vec<i32> = sqlx::query("SELECT id FROM accounts WHERE deletion_due_at <= ?")
+            .bind(now)
+            .map(|row: SqliteRow| row.get("id"))
+            .fetch_all(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        let mut deleted = 0;
+        for account_id in due {
+            let mut tx = self.connection.begin().await.map_err(query_error)?;
+            deleted += remove_due_account(&mut tx, account_id, now, content)
+                .await
+                .map_err(query_error)?;
+            tx.commit().await.map_err(query_error)?;
+        }
+
+        Ok(deleted)
+    }
+
+    async fn add_api_token(
+        &self,
+        account_id: &AccountId,
+        name: String,
+        token_hash: String,
+    ) -> Result<ApiToken, Error> {
+        let created_on = Utc::now();
+        let result = sqlx::query(
+            "INSERT INTO api_tokens (account_id, name, token_hash, created_on)
+            VALUES (?, ?, ?, ?)",
+        )
+        .bind(account_id.0)
+        .bind(&name)
+        .bind(token_hash)
+        .bind(created_on)
+        .execute(&self.connection)
+        .await
+        .map_err(query_error)?;
+
+        Ok(ApiToken {
+            id: ApiTokenId(result.last_insert_rowid() as i32),
+            name,
+            created_on,
+        })
+    }
+
+    async fn get_api_tokens(&self, account_id: &AccountId) -> Result<Vec<ApiToken>, Error> {
+        sqlx::query(
+            "SELECT id, name, created_on FROM api_tokens
+            WHERE account_id = ?
+            ORDER BY id",
+        )
+        .bind(account_id.0)
+        .map(api_token_from_row)
+        .fetch_all(&self.connection)
+        .await
+        .map_err(query_error)
+    }
+
+    async fn delete_api_token(&self, token_id: i32, account_id: &AccountId) -> Result<(), Error> {
+        let result = sqlx::query("DELETE FROM api_tokens WHERE id = ? AND account_id = ?")
+            .bind(token_id)
+            .bind(account_id.0)
+            .execute(&self.connection)
+            .await
+            .map_err(query_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(Error::ApiTokenNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn get_api_token_account(&self, token_hash: &str) -> Result<Option<Account>, Error> {
+        sqlx::query(
+            "SELECT accounts.* FROM api_tokens
+            JOIN accounts ON accounts.id = api_tokens.account_id
+            WHERE api_tokens.token_hash = ?",
+        )
+        .bind(token_hash)
+        .map(account_from_row)
+        .fetch_optional(&self.connection)
+        .await
+        .map_err(query_error)
+    }
+
+    async fn add_verification_token(
+        &self,
+        account_id: &AccountId,
+        token_hash: &str,
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        let ttl = chrono::Duration::seconds(ttl.as_secs() as i64);
+
+        sqlx::query(
+            "INSERT INTO email_verifications (token, account_id, expires_at)
+            VALUES (?, ?, ?)",
+        )
+        .bind(token_hash)
+        .bind(account_id.0)
+        .bind(Utc::now() + ttl)
+        .execute(&self.connection)
+        .await
+        .map(|_| ())
+        .map_err(query_error)
+    }
+
+    async fn verify_account(&self, token_hash: &str) -> Result<(), Error> {
+        // No data-modifying CTEs in SQLite, so the lookup, the delete and
+        // the update share a transaction instead
+        let mut tx = self.connection.begin().await.map_err(query_error)?;
+
+        let verification = sqlx::query(
+            "SELECT account_id, expires_at FROM email_verifications WHERE token = ?",
+        )
+        .bind(token_hash)
+        .map(|row: SqliteRow| {
+            (
+                row.get::<i32, _>("account_id"),
+                row.get::<DateTime<Utc>, _>("expires_at"),
+            )
+        })
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(query_error)?;
+
+        let account_id = match verification {
+            Some((account_id, expires_at)) if expires_at > Utc::now() => account_id,
+            _ => return Err(Error::InvalidVerificationToken),
+        };
+
+        sqlx::query("DELETE FROM email_verifications WHERE token = ?")
+            .bind(token_hash)
+            .execute(&mut tx)
+            .await
+            .map_err(query_error)?;
+
+        sqlx::query("UPDATE accounts SET verified = TRUE WHERE id = ?")
+            .bind(account_id)
+            .execute(&mut tx)
+            .await
+            .map_err(query_error)?;
+
+        tx.commit().await.map_err(query_error)
+    }
+
+    async fn add_password_reset_token(
+        &self,
+        account_id: &AccountId,
+        token_hash: &str,
+        ttl: Duration,
+    ) -> Result<(), Error> {
+        let ttl = chrono::Duration::seconds(ttl.as_secs() as i64);
+
+        sqlx::query(
+            "INSERT INTO password_resets (token_hash, account_id, expires_at)
+            VALUES (?, ?, ?)",
+        )
+        .bind(token_hash)
+        .bind(account_id.0)
+        .bind(Utc::now() + ttl)
+        .execute(&self.connection)
+        .await
+        .map(|_| ())
+        .map_err(query_error)
+    }
+
+    async fn reset_password(&self, token_hash: &str, password_hash: &str) -> Result<(), Error> {
+        let mut tx = self.connection.begin().await.map_err(query_error)?;
+
+        let reset = sqlx::query(
+            "SELECT account_id, expires_at FROM password_resets WHERE token_hash = ?",
+        )
+        .bind(token_hash)
+        .map(|row: SqliteRow| {
+            (
+                row.get::<i32, _>("account_id"),
+                row.get::<DateTime<Utc>, _>("expires_at"),
+            )
+        })
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(query_error)?;
+
+        let account_id = match reset {
+            Some((account_id, expires_at)) if expires_at > Utc::now() => account_id,
+            _ => return Err(Error::InvalidResetToken),
+        };
+
+        sqlx::query("DELETE FROM password_resets WHERE token_hash = ?")
+            .bind(token_hash)
+            .execute(&mut tx)
+            .await
+            .map_err(query_error)?;
+
+        sqlx::query("UPDATE accounts SET password = ? WHERE id = ?")
+            .bind(password_hash)
+            .bind(account_id)
+            .execute(&mut tx)
+            .await
+            .map_err(query_error)?;
+
+        tx.commit().await.map_err(query_error)
+    }
+
+    async fn get_flag(&self, name: &str) -> Result<Option<bool>, Error> {
+        sqlx::query("SELECT enabled FROM feature_flags WHERE name = ?")
+            .bind(name)
+            .map(|row: SqliteRow| row.get("enabled"))
+            .fetch_optional(&self.connection)
+            .await
+            .map_err(query_error)
+    }
+
+    async fn set_flag(&self, name: &str, enabled: bool) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO feature_flags (name, enabled, updated_on) VALUES (?, ?, ?)
+            ON CONFLICT (name) DO UPDATE SET enabled = excluded.enabled,
+                updated_on = excluded.updated_on",
+        )
+        .bind(name)
+        .bind(enabled)
+        .bind(Utc::now())
+        .execute(&self.connection)
+        .await
+        .map_err(query_error)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod sqlite_tests {
+    use super::*;
+    use futures::future::join_all;
+    use crate::types::sort::SortDirection;
+
+    async fn store() -> SqliteStore {
+        store_with_cache(100).await
+    }
+
+    async fn store_with_cache(statement_cache_capacity: usize) -> SqliteStore {
+        let store = SqliteStore::new("sqlite::memory:", statement_cache_capacity)
+            .await
+            .unwrap();
+        sqlx::migrate!("./sqlite-migrations")
+            .run(&store.connection)
+            .await
+            .unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn questions_round_trip() {
+        let store = store().await;
+
+        let created = store
+            .add_question(
+                NewQuestion {
+                    title: "First question".to_string(),
+                    content: "How do tags survive SQLite?".to_string(),
+                    tags: Some(vec!["sqlite".to_string(), "rust".to_string()]),
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+
+        let fetched = store.get_question(created.id.0).await.unwrap();
+        assert_eq!(fetched.title, "First question");
+        assert_eq!(
+            fetched.tags,
+            Some(vec!["sqlite".to_string(), "rust".to_string()])
+        );
+        assert!(store.is_question_owner(created.id.0, &AccountId(1)).await.unwrap());
+        assert!(matches!(
+            store.get_question(created.id.0 + 1).await,
+            Err(Error::QuestionNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn queries_work_with_a_tiny_statement_cache() {
+        // Every new statement evicts the one before it
+        let store = store_with_cache(1).await;
+
+        for title in ["first", "second"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: Some(vec!["cache".to_string()]),
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(store.get_question(1).await.unwrap().title, "first");
+        assert_eq!(store.get_question(2).await.unwrap().title, "second");
+        let tags = store.get_tags(None, 0).await.unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].count, 2);
+    }
+
+    #[tokio::test]
+    async fn question_activity_is_merged_in_order() {
+        let store = store().await;
+        let question = store
+            .add_question(
+                NewQuestion {
+                    title: "Busy question".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        store.cast_vote(1, AccountId(2), Vote::Up).await.unwrap();
+        store
+            .add_answer(
+                NewAnswer {
+                    content: "An answer".to_string(),
+                    question_id: QuestionId(1),
+                    parent_answer_id: None,
+                    client_id: None,
+                    attachments: Vec::new(),
+                },
+                AccountId(3),
+            )
+            .await
+            .unwrap();
+        store.update_question(question, 1, AccountId(1)).await.unwrap();
+
+        let activity = store.get_question_activity(1, None, 0).await.unwrap();
+        let kinds: Vec<&str> = activity.iter().map(|event| event.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["created", "voted", "answered", "edited"]);
+        assert_eq!(activity[2].answer_id, Some(AnswerId(1)));
+
+        let page = store.get_question_activity(1, Some(1), 3).await.unwrap();
+        assert_eq!(page, activity[3..].to_vec());
+    }
+
+    #[tokio::test]
+    async fn questions_filter_by_all_or_any_tags() {
+        let store = store().await;
+        for tags in [vec!["rust", "warp"], vec!["go"], vec!["rust"]] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: "Tagged".to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: Some(tags.into_iter().map(String::from).collect()),
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        let sort = Sort {
+            column: SortColumn::Id,
+            direction: SortDirection::Asc,
+        };
+        let ids = |filter: TagFilter| {
+            let store = store.clone();
+            async move {
+                store
+                    .get_questions(None, 0, sort, false, Some(filter))
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|question| question.id.0)
+                    .collect::<Vec<i32>>()
+            }
+        };
+        let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect();
+
+        assert_eq!(ids(TagFilter::Any(tags(&["warp", "go"]))).await, vec![1, 2]);
+        assert_eq!(ids(TagFilter::All(tags(&["rust", "warp"]))).await, vec![1]);
+        assert_eq!(ids(TagFilter::All(tags(&["rust"]))).await, vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn questions_by_score_put_the_best_voted_first() {
+        let store = store().await;
+        for title in ["no votes", "liked", "disliked", "liked, newer"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        for (question_id, account_id, vote) in [
+            (2, 1, Vote::Up),
+            (2, 2, Vote::Up),
+            (2, 3, Vote::Down),
+            (3, 1, Vote::Down),
+            (4, 1, Vote::Up),
+        ] {
+            store
+                .cast_vote(question_id, AccountId(account_id), vote)
+                .await
+                .unwrap();
+        }
+
+        let questions = store.get_questions_by_score(None, 0, false, None).await.unwrap();
+        let ids: Vec<i32> = questions.iter().map(|question| question.id.0).collect();
+        assert_eq!(ids, vec![4, 2, 1, 3]);
+    }
+
+    #[tokio::test]
+    async fn pages_of_tied_titles_have_no_gaps_or_repeats() {
+        let store = store().await;
+        for _ in 0..5 {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: "Same title".to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+
+        for direction in [SortDirection::Asc, SortDirection::Desc] {
+            let sort = Sort {
+                column: SortColumn::Title,
+                direction,
+            };
+            let mut ids = Vec::new();
+            for offset in [0, 2, 4] {
+                let page = store
+                    .get_questions(Some(2), offset, sort, false, None)
+                    .await
+                    .unwrap();
+                ids.extend(page.into_iter().map(|question| question.id.0));
+            }
+
+            let mut expected = vec![1, 2, 3, 4, 5];
+            if direction == SortDirection::Desc {
+                expected.reverse();
+            }
+            assert_eq!(ids, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn random_question_skips_archived_ones() {
+        let store = store().await;
+        assert!(matches!(store.random_question().await, Err(Error::QuestionNotFound)));
+
+        for (title, expires_at) in [("Archived", Some(Utc::now())), ("Listed", None)] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        store.archive_expired_questions(Utc::now()).await.unwrap();
+
+        for _ in 0..5 {
+            assert_eq!(store.random_question().await.unwrap().title, "Listed");
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_skips_archived_questions() {
+        let store = store().await;
+        for (title, expires_at) in [("Archived", Some(Utc::now())), ("Listed", None)] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        store.archive_expired_questions(Utc::now()).await.unwrap();
+
+        let streamed: Vec<_> = store.stream_questions(None).await.unwrap().collect().await;
+        assert_eq!(streamed.len(), 1);
+        assert_eq!(streamed[0].as_ref().unwrap().title, "Listed");
+    }
+
+    #[tokio::test]
+    async fn list_methods_stop_at_the_row_cap() {
+        let store = store().await.with_max_rows(2);
+        for title in ["first", "second", "third"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: Some(vec![title.to_string()]),
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+
+        let sort = Sort {
+            column: SortColumn::Id,
+            direction: SortDirection::Asc,
+        };
+        let questions = store.get_questions(None, 0, sort, false, None).await.unwrap();
+        assert_eq!(questions.len(), 2);
+        assert_eq!(store.get_tags(Some(10), 0).await.unwrap().len(), 2);
+        assert_eq!(store.get_tags(Some(1), 0).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn feature_flags_are_stored_and_overwritten() {
+        let store = store().await;
+        assert_eq!(store.get_flag("maintenance_mode").await.unwrap(), None);
+
+        for enabled in [true, false] {
+            store.set_flag("maintenance_mode", enabled).await.unwrap();
+            assert_eq!(store.get_flag("maintenance_mode").await.unwrap(), Some(enabled));
+        }
+    }
+
+    #[tokio::test]
+    async fn pinned_questions_come_first_whatever_the_sort() {
+        let store = store().await;
+        for title in ["first", "second"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        let listed = || async {
+            let questions = store.get_questions_by_score(None, 0, false, None).await.unwrap();
+            questions.into_iter().map(|q| q.id.0).collect::<Vec<i32>>()
+        };
+
+        assert_eq!(listed().await, vec![2, 1]);
+        store.set_pinned(1, true).await.unwrap();
+        assert_eq!(listed().await, vec![1, 2]);
+        assert!(matches!(store.set_pinned(9, true).await, Err(Error::QuestionNotFound)));
+    }
+
+    #[tokio::test]
+    async fn bookmarks_are_idempotent_and_follow_their_question() {
+        let store = store().await;
+        // Bookmarks reference their account
+        for email in ["one@email.com", "two@email.com", "three@email.com"] {
+            let account = Account {
+                id: None,
+                email: email.to_string(),
+                password: "hash".to_string(),
+                is_admin: false,
+                verified: true,
+            };
+            store.add_account(account).await.unwrap();
+        }
+        for title in ["first", "second"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        for id in [1, 2, 2] {
+            store.add_bookmark(id, AccountId(2)).await.unwrap();
+        }
+        store.add_bookmark(1, AccountId(3)).await.unwrap();
+        let bookmarked = |limit, offset| {
+            let store = store.clone();
+            async move {
+                let questions = store.get_bookmarks(&AccountId(2), limit, offset).await.unwrap();
+                questions.into_iter().map(|q| q.id.0).collect::<Vec<i32>>()
+            }
+        };
+
+        assert_eq!(bookmarked(None, 0).await, vec![2, 1]);
+        assert_eq!(bookmarked(Some(1), 1).await, vec![1]);
+
+        store.remove_bookmark(2, AccountId(2)).await.unwrap();
+        store.remove_bookmark(2, AccountId(2)).await.unwrap();
+        assert_eq!(bookmarked(None, 0).await, vec![1]);
+
+        store.remove_question(1).await.unwrap();
+        assert!(bookmarked(None, 0).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_client_ids_can_be_reused() {
+        let store = store().await;
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Retries".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let answer = |content: &str| NewAnswer {
+            content: content.to_string(),
+            question_id: QuestionId(1),
+            parent_answer_id: None,
+            client_id: Some("6f1c2b3a-7d4e-4f50-9a61-0b2c3d4e5f60".to_string()),
+            attachments: Vec::new(),
+        };
+        let first = store.add_answer(answer("First"), AccountId(2)).await.unwrap();
+
+        let an_hour_ago = Utc::now() - chrono::Duration::hours(1);
+        assert_eq!(store.expire_client_ids(an_hour_ago).await.unwrap(), 0);
+        let retry = store.add_answer(answer("First"), AccountId(2)).await.unwrap();
+        assert_eq!(retry.id, first.id);
+
+        assert_eq!(store.expire_client_ids(Utc::now()).await.unwrap(), 1);
+        let second = store.add_answer(answer("Second"), AccountId(2)).await.unwrap();
+        assert_ne!(second.id, first.id);
+    }
+
+    #[tokio::test]
+    async fn client_ids_only_dedupe_within_one_account() {
+        let store = store().await;
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Retries".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let answer = |content: &str| NewAnswer {
+            content: content.to_string(),
+            question_id: QuestionId(1),
+            parent_answer_id: None,
+            client_id: Some("6f1c2b3a-7d4e-4f50-9a61-0b2c3d4e5f60".to_string()),
+            attachments: Vec::new(),
+        };
+        let mine = store.add_answer(answer("Mine"), AccountId(2)).await.unwrap();
+        let theirs = store.add_answer(answer("Theirs"), AccountId(3)).await.unwrap();
+
+        assert_ne!(mine.id, theirs.id);
+        assert_eq!(theirs.content, "Theirs");
+        assert_eq!(store.get_answers(1).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn answers_are_fetched_for_several_questions_at_once() {
+        let store = store().await;
+        for title in ["two answers", "no answers", "one answer", "not asked for"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        for (question_id, content) in [(1, "first"), (3, "second"), (1, "third"), (4, "fourth")] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(question_id),
+                        parent_answer_id: None,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(2),
+                )
+                .await
+                .unwrap();
+        }
+
+        let grouped = store.get_answers_for_questions(&[1, 2, 3]).await.unwrap();
+        let contents = |question_id: i32| -> Vec<&str> {
+            grouped[&question_id].iter().map(|answer| answer.content.as_str()).collect()
+        };
+
+        assert_eq!(grouped.len(), 3);
+        assert_eq!(contents(1), vec!["first", "third"]);
+        assert!(contents(2).is_empty());
+        assert_eq!(contents(3), vec!["second"]);
+    }
+
+    #[tokio::test]
+    async fn search_results_are_counted() {
+        let store = store().await;
+        for title in ["Warp filters", "Tokio runtimes", "100% of WARP"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        let count = |q: &str| {
+            let params = HashMap::from([("q".to_string(), q.to_string())]);
+            let term = crate::types::search::extract_search_term(&params).unwrap();
+            let store = store.clone();
+            async move { store.count_search_results(&term).await.unwrap() }
+        };
+
+        assert_eq!(count("warp").await, 2);
+        assert_eq!(count("content").await, 3);
+        // The wildcard is matched literally
+        assert_eq!(count("0%").await, 1);
+        assert_eq!(count("%%").await, 0);
+    }
+
+    #[tokio::test]
+    async fn questions_sort_by_answer_count() {
+        let store = store().await;
+        for title in ["one answer", "two answers", "no answers"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        for (question_id, content) in [(1, "first"), (2, "second"), (2, "third")] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(question_id),
+                        parent_answer_id: None,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+
+        let sort = Sort {
+            column: SortColumn::Answers,
+            direction: SortDirection::Desc,
+        };
+        let ids: Vec<i32> = store
+            .get_questions(None, 0, sort, false, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|question| question.id.0)
+            .collect();
+        assert_eq!(ids, vec![2, 1, 3]);
+    }
+
+    #[tokio::test]
+    async fn shared_tags_are_stored_once() {
+        let store = store().await;
+        for title in ["First", "Second"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: Some(vec!["rust".to_string()]),
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+
+        let tag_rows: i64 = sqlx::query("SELECT COUNT(*) FROM tags WHERE name = 'rust'")
+            .map(|row: SqliteRow| row.get(0))
+            .fetch_one(&store.connection)
+            .await
+            .unwrap();
+        assert_eq!(tag_rows, 1);
+        let tags = store.get_tags(None, 0).await.unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].count, 2);
+    }
+
+    #[tokio::test]
+    async fn answers_move_with_their_replies() {
+        let store = store().await;
+        for title in ["Misplaced", "Right place"] {
+            store
+                .add_question(
+                    NewQuestion {
+                        title: title.to_string(),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+        for (content, parent) in [("Top", None), ("Reply", Some(AnswerId(1))), ("Nested", Some(AnswerId(2)))] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: content.to_string(),
+                        question_id: QuestionId(1),
+                        parent_answer_id: parent,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(1),
+                )
+                .await
+                .unwrap();
+        }
+
+        let moved = store.move_answer(2, 2).await.unwrap();
+        assert_eq!(moved.parent_answer_id, None);
+        assert_eq!(store.get_answers(1).await.unwrap().len(), 1);
+        assert_eq!(store.get_answers(2).await.unwrap().len(), 2);
+        assert!(matches!(store.move_answer(9, 2).await, Err(Error::AnswerNotFound)));
+        assert!(matches!(store.move_answer(1, 9).await, Err(Error::QuestionNotFound)));
+    }
+
+    /// Accounts 1 and 2 each ask a question and answer the other's, and
+    /// account 2 replies to account 1's answer
+    async fn store_with_two_posters() -> SqliteStore {
+        let store = store().await;
+        for (email, asker) in [("one@email.com", 1), ("two@email.com", 2)] {
+            let account = Account {
+                id: None,
+                email: email.to_string(),
+                password: "hash".to_string(),
+                is_admin: false,
+                verified: true,
+            };
+            store.add_account(account).await.unwrap();
+            store
+                .add_question(
+                    NewQuestion {
+                        title: format!("Asked by {}", email),
+                        content: "Content of the question".to_string(),
+                        tags: None,
+                        expires_at: None,
+                        content_format: None,
+                    },
+                    AccountId(asker),
+                )
+                .await
+                .unwrap();
+        }
+        for (question, parent, author) in [(1, None, 2), (2, None, 1), (2, Some(AnswerId(2)), 2)] {
+            store
+                .add_answer(
+                    NewAnswer {
+                        content: "Content of the answer".to_string(),
+                        question_id: QuestionId(question),
+                        parent_answer_id: parent,
+                        client_id: None,
+                        attachments: Vec::new(),
+                    },
+                    AccountId(author),
+                )
+                .await
+                .unwrap();
+        }
+        store.add_bookmark(1, AccountId(2)).await.unwrap();
+        store.add_bookmark(2, AccountId(1)).await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn deleted_accounts_take_their_content_with_them() {
+        let store = store_with_two_posters().await;
+
+        store
+            .delete_account(&AccountId(1), DeletedContent::Delete)
+            .await
+            .unwrap();
+
+        assert!(store.get_account("one@email.com".to_string()).await.is_err());
+        // Question 1 went with the other account's answer to it, and the
+        // reply to the deleted answer went along with that answer
+        assert!(matches!(store.get_question(1).await, Err(Error::QuestionNotFound)));
+        assert!(store.get_answers(2).await.unwrap().is_empty());
+        assert!(store.get_account_answers(&AccountId(2)).await.unwrap().is_empty());
+        assert!(store
+            .get_bookmarks(&AccountId(2), None, 0)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            store.delete_account(&AccountId(1), DeletedContent::Delete).await,
+            Err(Error::AccountNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn anonymized_accounts_leave_their_content_to_the_deleted_user() {
+        let store = store_with_two_posters().await;
+
+        store
+            .delete_account(&AccountId(1), DeletedContent::Anonymize)
+            .await
+            .unwrap();
+
+        assert!(store.get_account("one@email.com".to_string()).await.is_err());
+        let questions = store.get_account_questions(&DELETED_ACCOUNT).await.unwrap();
+        assert_eq!(questions.iter().map(|q| q.id.0).collect::<Vec<_>>(), vec![1]);
+        let answers = store.get_account_answers(&DELETED_ACCOUNT).await.unwrap();
+        assert_eq!(answers.iter().map(|a| a.id.0).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(store.get_answers(1).await.unwrap().len(), 1);
+        assert_eq!(store.get_answers(2).await.unwrap().len(), 2);
+        let bookmarks = store.get_bookmarks(&AccountId(2), None, 0).await.unwrap();
+        assert_eq!(bookmarks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn expired_questions_are_archived() {
+        let store = store().await;
+        let expires_at = Utc::now() - chrono::Duration::minutes(5);
+        store
+            .add_question(
+                NewQuestion {
+                    title: "Expiring".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: Some(expires_at),
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let sort = Sort {
+            column: SortColumn::Id,
+            direction: SortDirection::Asc,
+        };
+
+        assert_eq!(store.archive_expired_questions(Utc::now()).await.unwrap(), 1);
+        assert!(store.get_questions(None, 0, sort, false, None).await.unwrap().is_empty());
+        let archived = store.get_questions(None, 0, sort, true, None).await.unwrap();
+        assert_eq!(archived[0].expires_at, Some(expires_at));
+    }
+
+    #[tokio::test]
+    async fn duplicate_accounts_and_answers_are_reported() {
+        let store = store().await;
+        let account = Account {
+            id: None,
+            email: "test@email.com".to_string(),
+            password: "hash".to_string(),
+            is_admin: false,
+            verified: true,
+        };
+        assert!(store.add_account(account.clone()).await.unwrap());
+        assert!(matches!(
+            store.add_account(account).await,
+            Err(Error::DuplicateAccount)
+        ));
+
+        let question = store
+            .add_question(
+                NewQuestion {
+                    title: "Question".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let answer = |content: &str| NewAnswer {
+            content: content.to_string(),
+            question_id: question.id.clone(),
+            parent_answer_id: None,
+            client_id: None,
+            attachments: Vec::new(),
+        };
+
+        store.add_answer(answer("An answer"), AccountId(1)).await.unwrap();
+        assert!(matches!(
+            store.add_answer(answer("  an   ANSWER "), AccountId(1)).await,
+            Err(Error::DuplicateAnswer)
+        ));
+        assert_eq!(store.get_answers(question.id.0).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn capped_registration_stops_at_the_limit() {
+        let store = store().await;
+        let account = |email: &str| Account {
+            id: None,
+            email: email.to_string(),
+            password: "hash".to_string(),
+            is_admin: false,
+            verified: false,
+        };
+
+        let added = join_all(
+            ["a@email.com", "b@email.com", "c@email.com"]
+                .map(|email| store.add_account_capped(account(email), 2)),
+        )
+        .await;
+        assert_eq!(added.iter().filter(|added| added.is_ok()).count(), 2);
+        assert!(
+            added
+                .iter()
+                .all(|added| matches!(added, Ok(true) | Err(Error::RegistrationClosed)))
+        );
+        assert_eq!(store.get_stats().await.unwrap().accounts, 2);
+
+        // Without a cap the count doesn't matter
+        assert!(store.add_account_capped(account("d@email.com"), 0).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn answer_attachments_round_trip() {
+        let store = store().await;
+        let question = store
+            .add_question(
+                NewQuestion {
+                    title: "Question".to_string(),
+                    content: "Content of the question".to_string(),
+                    tags: None,
+                    expires_at: None,
+                    content_format: None,
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        let attachments = vec![
+            "https://example.com/b.png".to_string(),
+            "https://example.com/a.png".to_string(),
+        ];
+
+        let answer = store
+            .add_answer(
+                NewAnswer {
+                    content: "See attached".to_string(),
+                    question_id: question.id.clone(),
+                    parent_answer_id: None,
+                    client_id: None,
+                    attachments: attachments.clone(),
+                },
+                AccountId(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(answer.attachments, attachments);
+        assert_eq!(
+            store.get_answers(question.id.0).await.unwrap()[0].attachments,
+            attachments
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use tracing::subscriber::DefaultGuard;
+use warp::Filter;
+
+/// In-memory sink for formatted tracing output
+#[derive(Clone, Default)]
+pub struct CapturedLogs(Arc<Mutex<Vec<u8>>>);
+
+impl CapturedLogs {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl io::Write for CapturedLogs {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Routes every event on the current thread into the returned buffer
+/// until the guard is dropped
+pub fn capture_logs() -> (CapturedLogs, DefaultGuard) {
+    let logs = CapturedLogs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_max_level(tracing::Level::TRACE)
+        .with_ansi(false)
+        .finish();
+
+    (logs, tracing::subscriber::set_default(subscriber))
+}
+
+/// Serves a bad words API censoring "shitty", returning its base URL
+pub fn censoring_api() -> String {
+    let api = warp::post()
+        .and(warp::path("bad_words"))
+        .and(warp::body::bytes())
+        .map(|body: warp::hyper::body::Bytes| {
+            let content = String::from_utf8_lossy(&body).to_string();
+            warp::reply::json(&serde_json::json!({
+                "content": content,
+                "bad_words_total": 0,
+                "bad_words_list": [],
+                "censored_content": content.replace("shitty", "******"),
+            }))
+        });
+    let (addr, server) = warp::serve(api).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    format!("http://{}", addr)
+}
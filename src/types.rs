@@ -1,4 +1,13 @@
 pub mod account;
+pub mod activity;
+pub mod api_token;
 pub mod answer;
+pub mod feature_flag;
 pub mod pagination;
 pub mod question;
+pub mod search;
+pub mod service;
+pub mod sort;
+pub mod stats;
+pub mod tag;
+pub mod vote;
@@ -1,10 +1,21 @@
 use chrono::prelude::*;
+use handle_errors::{Error, FieldError};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::types::answer::Answer;
+use crate::types::question::Question;
+use crate::types::vote::Vote;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Session {
     pub exp: DateTime<Utc>,
     pub account_id: AccountId,
+    pub is_admin: bool,
+    pub email: String,
     pub nbf: DateTime<Utc>,
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -12,13 +23,239 @@ pub struct Account {
     pub id: Option<AccountId>,
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub is_admin: bool,
+    #[serde(default)]
+    pub verified: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct AccountId(pub i32);
 
+/// Owner of the questions and answers kept from deleted accounts. Account
+/// ids start at 1, so it never names a real account.
+pub const DELETED_ACCOUNT: AccountId = AccountId(0);
+
+/// What happens to an account's questions and answers once it is deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DeletedContent {
+    /// Removed, along with the answers and replies they drew
+    Delete,
+    /// Kept, and handed over to `DELETED_ACCOUNT`
+    Anonymize,
+}
+
+impl FromStr for DeletedContent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "delete" => Ok(DeletedContent::Delete),
+            "anonymize" => Ok(DeletedContent::Anonymize),
+            _ => Err(format!(
+                "unknown deleted account content `{}`, expected delete or anonymize",
+                s
+            )),
+        }
+    }
+}
+
+/// What `/accounts/me` shows about the logged in account
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountProfile {
+    pub id: AccountId,
+    pub email: String,
+    pub is_admin: bool,
+    pub verified: bool,
+    pub last_login_at: Option<DateTime<Utc>>,
+    pub last_login_ip: Option<String>,
+    /// When a requested deletion goes through, unless cancelled before
+    pub deletion_due_at: Option<DateTime<Utc>>,
+    /// Writes are refused until then
+    pub suspended_until: Option<DateTime<Utc>>,
+    pub preferences: NotificationPreferences,
+}
+
+/// Which emails the account wants. Set through
+/// `PUT /accounts/me/preferences`; flags left out of the body fall back to
+/// their defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct NotificationPreferences {
+    /// Email when someone answers one of the account's questions
+    pub notify_on_answer: bool,
+    /// Email when someone replies to one of the account's answers
+    pub notify_on_reply: bool,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        NotificationPreferences {
+            notify_on_answer: true,
+            notify_on_reply: true,
+        }
+    }
+}
+
+impl NotificationPreferences {
+    /// From the accounts table, where flags never set are NULL
+    pub fn from_columns(notify_on_answer: Option<bool>, notify_on_reply: Option<bool>) -> Self {
+        let defaults = NotificationPreferences::default();
+        NotificationPreferences {
+            notify_on_answer: notify_on_answer.unwrap_or(defaults.notify_on_answer),
+            notify_on_reply: notify_on_reply.unwrap_or(defaults.notify_on_reply),
+        }
+    }
+}
+
+/// Everything stored about an account, as handed out by
+/// `GET /accounts/me/export`. Leaves out the password hash.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountExport {
+    pub profile: AccountProfile,
+    /// Archived questions included
+    pub questions: Vec<Question>,
+    pub answers: Vec<Answer>,
+    /// Keyed by the id of the question voted on
+    pub votes: HashMap<i32, Vote>,
+    /// Bookmarked questions, most recently bookmarked first
+    pub bookmarks: Vec<Question>,
+}
+
+/// What `GET /whoami` tells about a valid token
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WhoAmI {
+    pub account_id: AccountId,
+    pub is_admin: bool,
+}
+
+/// Body of `POST /admin/accounts/:id/suspend`. A `null` or past `until`
+/// lifts the suspension.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Suspension {
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Body of `POST /password-reset/request`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+/// Body of `POST /password-reset/confirm`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PasswordResetConfirm {
+    pub token: String,
+    pub password: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NewAccount {
     pub email: String,
     pub password: String,
 }
+
+
+/// Check the credentials of a new account, reporting every problem at once
+pub fn validate_account(account: &Account) -> Result<(), Error> {
+    let mut errors = Vec::new();
+
+    let valid_email = match account.email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty() && domain.contains('.') && !domain.contains('@')
+        }
+        None => false,
+    };
+    if !valid_email {
+        errors.push(FieldError::new("email", "must be a valid email address"));
+    }
+
+    errors.extend(password_error(&account.password));
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+/// Refuse posts from accounts created less than `min_age_minutes` before
+/// `now`. A minimum of 0, or an account with no recorded creation time,
+/// lets the post through.
+pub fn check_account_age(
+    created_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    min_age_minutes: u64,
+) -> Result<(), Error> {
+    match created_at {
+        Some(created_at)
+            if min_age_minutes > 0
+                && now - created_at < chrono::Duration::minutes(min_age_minutes as i64) =>
+        {
+            Err(Error::AccountTooNew)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The password policy on its own, for when only the password changes
+pub fn validate_password(password: &str) -> Result<(), Error> {
+    match password_error(password) {
+        Some(error) => Err(Error::Validation(vec![error])),
+        None => Ok(()),
+    }
+}
+
+fn password_error(password: &str) -> Option<FieldError> {
+    (password.chars().count() < MIN_PASSWORD_LENGTH).then(|| {
+        FieldError::new(
+            "password",
+            format!("must be at least {} characters long", MIN_PASSWORD_LENGTH),
+        )
+    })
+}
+
+#[cfg(test)]
+mod account_tests {
+    use super::{Account, Error, check_account_age, validate_account};
+    use chrono::{Duration, Utc};
+
+    fn account(email: &str, password: &str) -> Account {
+        Account {
+            id: None,
+            email: email.to_string(),
+            password: password.to_string(),
+            is_admin: false,
+            verified: false,
+        }
+    }
+
+    #[test]
+    fn valid_account_passes() {
+        assert!(validate_account(&account("test@email.com", "password")).is_ok());
+    }
+
+    #[test]
+    fn accounts_post_once_old_enough() {
+        let now = Utc::now();
+
+        assert!(matches!(
+            check_account_age(Some(now - Duration::minutes(9)), now, 10),
+            Err(Error::AccountTooNew)
+        ));
+        assert!(check_account_age(Some(now - Duration::minutes(10)), now, 10).is_ok());
+        assert!(check_account_age(Some(now), now, 0).is_ok());
+        assert!(check_account_age(None, now, 10).is_ok());
+    }
+
+    #[test]
+    fn all_field_errors_are_reported_together() {
+        match validate_account(&account("not-an-email", "short")) {
+            Err(Error::Validation(errors)) => {
+                let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+                assert_eq!(fields, vec!["email", "password"]);
+            }
+            other => panic!("expected validation errors, got {:?}", other),
+        }
+    }
+}
\ No newline at end of file
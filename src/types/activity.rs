@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::types::answer::AnswerId;
+
+/// What happened to a question
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityKind {
+    #[default]
+    Created,
+    /// Only the latest edit is kept
+    Edited,
+    Answered,
+    /// An account's first vote; changing it later isn't an event
+    Voted,
+}
+
+impl ActivityKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityKind::Created => "created",
+            ActivityKind::Edited => "edited",
+            ActivityKind::Answered => "answered",
+            ActivityKind::Voted => "voted",
+        }
+    }
+}
+
+impl FromStr for ActivityKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(ActivityKind::Created),
+            "edited" => Ok(ActivityKind::Edited),
+            "answered" => Ok(ActivityKind::Answered),
+            "voted" => Ok(ActivityKind::Voted),
+            _ => Err(format!("unknown activity `{}`", s)),
+        }
+    }
+}
+
+/// An entry of `GET /questions/:id/activity`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Activity {
+    pub kind: ActivityKind,
+    pub at: DateTime<Utc>,
+    /// The answer, for `answered` events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub answer_id: Option<AnswerId>,
+}
@@ -1,17 +1,248 @@
 use crate::types::question::QuestionId;
-use serde::{Deserialize, Serialize};
+use handle_errors::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{HashMap, HashSet};
+use url::Url;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Answer {
     pub id: AnswerId,
     pub content: String,
     pub question_id: QuestionId,
+    pub parent_answer_id: Option<AnswerId>,
+    /// URLs of external files the answer refers to
+    #[serde(default)]
+    pub attachments: Vec<String>,
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NewAnswer {
     pub content: String,
     pub question_id: QuestionId,
+    pub parent_answer_id: Option<AnswerId>,
+    /// UUID picked by the client so a retried post doesn't create the
+    /// answer twice
+    #[serde(default)]
+    pub client_id: Option<String>,
+    /// A JSON array, or in a form a whitespace separated list
+    #[serde(default, deserialize_with = "url_list")]
+    pub attachments: Vec<String>,
+}
+
+/// Body of `POST /answers/:id/move`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MoveAnswer {
+    pub question_id: QuestionId,
+}
+
+/// Forms can't carry arrays, so a single string is split on whitespace,
+/// which well-formed URLs never contain
+fn url_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum UrlList {
+        Joined(String),
+        List(Vec<String>),
+    }
+
+    Ok(match UrlList::deserialize(deserializer)? {
+        UrlList::Joined(urls) => urls.split_whitespace().map(str::to_string).collect(),
+        UrlList::List(urls) => urls,
+    })
+}
+
+/// Accept at most `max` attachments, each an absolute http(s) URL
+pub fn validate_attachments(attachments: &[String], max: usize) -> Result<(), Error> {
+    if attachments.len() > max {
+        return Err(Error::InvalidParameter(format!(
+            "at most {} attachments are allowed",
+            max
+        )));
+    }
+
+    for attachment in attachments {
+        let valid = Url::parse(attachment).is_ok_and(|url| {
+            matches!(url.scheme(), "http" | "https") && url.host().is_some()
+        });
+        if !valid {
+            return Err(Error::InvalidParameter(format!(
+                "attachment `{}` is not an http(s) URL",
+                attachment
+            )));
+        }
+    }
+
+    Ok(())
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct AnswerId(pub i32);
+
+/// An answer together with the replies posted to it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnswerThread {
+    #[serde(flatten)]
+    pub answer: Answer,
+    pub replies: Vec<AnswerThread>,
+}
+
+/// Normalized form used to detect the same answer posted twice: surrounding
+/// and repeated whitespace collapsed, case folded. Matches the expression
+/// behind the `answers_unique_content` index.
+pub fn normalized_content(content: &str) -> String {
+    content
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Replies nested deeper than this are listed flat under their ancestor at
+/// this depth, so a long reply chain can't exhaust the stack
+const MAX_REPLY_DEPTH: usize = 32;
+
+/// Arrange a question's answers into reply trees, keeping the incoming
+/// order among siblings. Replies to an answer that isn't in the list are
+/// treated as top-level answers.
+pub fn nest_answers(answers: Vec<Answer>) -> Vec<AnswerThread> {
+    let ids: HashSet<i32> = answers.iter().map(|answer| answer.id.0).collect();
+    let mut children: HashMap<Option<i32>, Vec<Answer>> = HashMap::new();
+
+    for answer in answers {
+        let parent = answer
+            .parent_answer_id
+            .as_ref()
+            .map(|parent| parent.0)
+            .filter(|parent| ids.contains(parent));
+        children.entry(parent).or_default().push(answer);
+    }
+
+    let mut threads = replies_to(None, &mut children, 0);
+
+    // Whatever is left is part of a cycle and can't be reached from a
+    // top-level answer; surface it instead of dropping it. Each parent's
+    // replies are taken out of the map once, so the walk always ends.
+    while let Some(parent) = children.keys().next().copied() {
+        for answer in children.remove(&parent).unwrap_or_default() {
+            let replies = replies_to(Some(answer.id.0), &mut children, 1);
+            threads.push(AnswerThread { answer, replies });
+        }
+    }
+
+    threads
+}
+
+fn replies_to(
+    parent: Option<i32>,
+    children: &mut HashMap<Option<i32>, Vec<Answer>>,
+    depth: usize,
+) -> Vec<AnswerThread> {
+    let answers = children.remove(&parent).unwrap_or_default();
+    if depth >= MAX_REPLY_DEPTH {
+        return flattened(answers, children);
+    }
+
+    let mut threads = Vec::new();
+    for answer in answers {
+        let replies = replies_to(Some(answer.id.0), children, depth + 1);
+        threads.push(AnswerThread { answer, replies });
+    }
+
+    threads
+}
+
+/// `answers` followed by everything replying to them, depth first, with
+/// no nesting
+fn flattened(
+    answers: Vec<Answer>,
+    children: &mut HashMap<Option<i32>, Vec<Answer>>,
+) -> Vec<AnswerThread> {
+    let mut threads = Vec::new();
+    let mut pending: Vec<Answer> = answers.into_iter().rev().collect();
+    while let Some(answer) = pending.pop() {
+        let replies = children.remove(&Some(answer.id.0)).unwrap_or_default();
+        pending.extend(replies.into_iter().rev());
+        threads.push(AnswerThread {
+            answer,
+            replies: Vec::new(),
+        });
+    }
+
+    threads
+}
+
+#[cfg(test)]
+mod answer_tests {
+    use super::{Answer, AnswerId, MAX_REPLY_DEPTH, nest_answers, validate_attachments};
+    use crate::types::question::QuestionId;
+
+    fn answer(id: i32, parent: Option<i32>) -> Answer {
+        Answer {
+            id: AnswerId(id),
+            content: format!("answer {}", id),
+            question_id: QuestionId(1),
+            parent_answer_id: parent.map(AnswerId),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn replies_are_nested_under_their_parent() {
+        let threads = nest_answers(vec![
+            answer(1, None),
+            answer(2, Some(1)),
+            answer(3, None),
+            answer(4, Some(2)),
+        ]);
+
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].answer.id, AnswerId(1));
+        assert_eq!(threads[0].replies[0].answer.id, AnswerId(2));
+        assert_eq!(threads[0].replies[0].replies[0].answer.id, AnswerId(4));
+        assert_eq!(threads[1].answer.id, AnswerId(3));
+        assert!(threads[1].replies.is_empty());
+    }
+
+    #[test]
+    fn cycles_terminate_without_losing_answers() {
+        let threads = nest_answers(vec![answer(1, Some(2)), answer(2, Some(1))]);
+
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].replies.len(), 1);
+        assert!(threads[0].replies[0].replies.is_empty());
+    }
+
+    #[test]
+    fn long_reply_chains_are_flattened_past_the_depth_limit() {
+        let chain = (1..=100_000)
+            .map(|id| answer(id, (id > 1).then_some(id - 1)))
+            .collect();
+        let threads = nest_answers(chain);
+
+        // Nested as usual down to the limit...
+        let mut thread = &threads[0];
+        for depth in 1..MAX_REPLY_DEPTH {
+            thread = &thread.replies[0];
+            assert_eq!(thread.answer.id.0, depth as i32 + 1);
+        }
+        // ...below which the rest of the chain is listed flat, in order
+        let flat = &thread.replies;
+        assert_eq!(flat.len(), 100_000 - MAX_REPLY_DEPTH);
+        assert!(flat.iter().all(|reply| reply.replies.is_empty()));
+        assert_eq!(flat[0].answer.id.0, MAX_REPLY_DEPTH as i32 + 1);
+        assert_eq!(flat.last().unwrap().answer.id, AnswerId(100_000));
+    }
+
+    #[test]
+    fn attachments_must_be_http_urls_within_the_limit() {
+        let valid = vec![
+            "https://example.com/diagram.png".to_string(),
+            "http://example.com/notes.txt".to_string(),
+        ];
+        assert!(validate_attachments(&valid, 2).is_ok());
+        assert!(validate_attachments(&valid, 1).is_err());
+
+        for invalid in ["not a url", "ftp://example.com/file", "https://"] {
+            assert!(validate_attachments(&[invalid.to_string()], 5).is_err());
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ApiTokenId(pub i32);
+
+/// A long-lived token for programmatic access. Only a hash of the token
+/// itself is stored, so it can't be shown again after minting.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiToken {
+    pub id: ApiTokenId,
+    pub name: String,
+    pub created_on: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewApiToken {
+    pub name: String,
+}
+
+/// Reply to minting a token, the one time the token itself is returned
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MintedApiToken {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    pub token: String,
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Runtime toggles kept in the `feature_flags` table, so they can be
+/// flipped through `/admin/flags` without a redeploy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureFlag {
+    /// `POST /registration` takes new accounts
+    RegistrationOpen,
+    /// Writes to questions and answers are refused, as with `--read-only`
+    MaintenanceMode,
+}
+
+impl FeatureFlag {
+    pub const ALL: [FeatureFlag; 2] = [
+        FeatureFlag::RegistrationOpen,
+        FeatureFlag::MaintenanceMode,
+    ];
+
+    /// Value of the `feature_flags.name` column
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeatureFlag::RegistrationOpen => "registration_open",
+            FeatureFlag::MaintenanceMode => "maintenance_mode",
+        }
+    }
+
+    /// Value of a flag that was never set
+    pub fn default_value(&self) -> bool {
+        match self {
+            FeatureFlag::RegistrationOpen => true,
+            FeatureFlag::MaintenanceMode => false,
+        }
+    }
+}
+
+impl FromStr for FeatureFlag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FeatureFlag::ALL
+            .into_iter()
+            .find(|flag| flag.name() == s)
+            .ok_or_else(|| format!("unknown feature flag `{}`", s))
+    }
+}
+
+/// A flag as listed by `GET /admin/flags`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FlagStatus {
+    pub name: String,
+    pub enabled: bool,
+}
+
+/// Body of `PUT /admin/flags/:name`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FlagUpdate {
+    pub enabled: bool,
+}
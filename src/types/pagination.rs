@@ -1,5 +1,6 @@
 use handle_errors::Error;
 use std::collections::HashMap;
+use warp::http::{HeaderMap, HeaderValue};
 
 /// Pagination struct that is getting extracted
 /// from query params
@@ -10,12 +11,38 @@ pub struct Pagination {
     /// The index of the last item that has to be returned
     pub offset: u32,
 }
+
+impl Pagination {
+    /// Cap the page at `max` items, also when no limit was asked for. A
+    /// `max` of 0 leaves the pagination as it is.
+    pub fn clamp(self, max: u32) -> Self {
+        if max == 0 {
+            return self;
+        }
+        Pagination {
+            limit: Some(self.limit.map_or(max, |limit| limit.min(max))),
+            offset: self.offset,
+        }
+    }
+
+    /// Echo the limit and offset that were applied as `X-Pagination-Limit`
+    /// and `X-Pagination-Offset`, so clients can tell when theirs were
+    /// adjusted. There is no limit header for unlimited pages.
+    pub fn add_headers(&self, headers: &mut HeaderMap) {
+        if let Some(limit) = self.limit {
+            headers.insert("x-pagination-limit", HeaderValue::from(limit));
+        }
+        headers.insert("x-pagination-offset", HeaderValue::from(self.offset));
+    }
+}
 /// Extract query parameters from the `/questions` route
 /// # Example query
 /// GET requests to this route can have a pagination attached so we just
 /// return the questions we need
 /// # Example usage
 /// ```rust
+/// # use std::collections::HashMap;
+/// # use server::types;
 /// let mut query = HashMap::new();
 /// query.insert("limit".to_string(), "1".to_string());
 /// query.insert("offset".to_string(), "10".to_string());
@@ -48,8 +75,6 @@ pub fn extract_pagination(params: HashMap<String, String>) -> Result<Pagination,
 
 #[cfg(test)]
 mod pagination_tests {
-    use openssl::pkey::Params;
-
     use super::{Error, HashMap, Pagination, extract_pagination};
 
     #[test]
@@ -65,6 +90,21 @@ mod pagination_tests {
         assert_eq!(pagination_result.limit, expected.limit);
     }
 
+    #[test]
+    fn clamp_caps_the_limit() {
+        let clamped = Pagination {
+            limit: Some(500),
+            offset: 3,
+        }
+        .clamp(100);
+        assert_eq!(clamped.limit, Some(100));
+        assert_eq!(clamped.offset, 3);
+
+        assert_eq!(Pagination::default().clamp(100).limit, Some(100));
+        assert_eq!(Pagination::default().clamp(0).limit, None);
+    }
+
+    #[test]
     fn missing_offset_parameter() {
         let mut params = HashMap::new();
         params.insert(String::from("limit"), String::from("1"));
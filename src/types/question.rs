@@ -1,10 +1,20 @@
+use chrono::{DateTime, Duration, Utc};
+use handle_errors::{Error, FieldError};
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use crate::types::answer::Answer;
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Question {
     pub id: QuestionId,
     pub title: String,
     pub content: String,
     pub tags: Option<Vec<String>>,
+    /// Once passed the question is archived and drops out of listings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub content_format: ContentFormat,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -12,6 +22,263 @@ pub struct NewQuestion {
     pub title: String,
     pub content: String,
     pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// `plain` or `markdown`, checked by `parse_content_format` so an
+    /// unknown value is a 400 rather than a body deserialize error
+    #[serde(default)]
+    pub content_format: Option<String>,
+}
+
+impl NewQuestion {
+    /// The requested format, plain when none or an unknown one was given
+    pub fn format(&self) -> ContentFormat {
+        parse_content_format(self.content_format.as_deref()).unwrap_or_default()
+    }
+}
+
+/// Body of `PUT /questions/:id`, shaped like `Question`. A missing
+/// `content_format` keeps the stored one; a given one is checked by
+/// `parse_content_format` as for `NewQuestion`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QuestionUpdate {
+    pub id: QuestionId,
+    pub title: String,
+    pub content: String,
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub content_format: Option<String>,
+}
+
+/// How the question content should be rendered. The server only stores it.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentFormat {
+    #[default]
+    Plain,
+    Markdown,
+}
+
+impl ContentFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentFormat::Plain => "plain",
+            ContentFormat::Markdown => "markdown",
+        }
+    }
 }
+
+impl FromStr for ContentFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(ContentFormat::Plain),
+            "markdown" => Ok(ContentFormat::Markdown),
+            _ => Err(format!(
+                "unknown content_format `{}`, expected plain or markdown",
+                s
+            )),
+        }
+    }
+}
+
+/// Check a submitted `content_format`, defaulting to plain
+pub fn parse_content_format(format: Option<&str>) -> Result<ContentFormat, Error> {
+    match format {
+        Some(format) => format.parse().map_err(Error::InvalidParameter),
+        None => Ok(ContentFormat::Plain),
+    }
+}
+/// Ids start at 1, so smaller ones are refused when parsed from a path or
+/// deserialized from a body
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "i32")]
 pub struct QuestionId(pub i32);
+
+impl TryFrom<i32> for QuestionId {
+    type Error = String;
+
+    fn try_from(id: i32) -> Result<Self, Self::Error> {
+        if id > 0 {
+            Ok(QuestionId(id))
+        } else {
+            Err(format!("question id must be positive, got {}", id))
+        }
+    }
+}
+
+impl FromStr for QuestionId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let id = s
+            .parse::<i32>()
+            .map_err(|_| format!("question id `{}` is not a number", s))?;
+        QuestionId::try_from(id)
+    }
+}
+
+
+/// `GET /questions/:id` without `?include`: the question and how many
+/// answers, replies included, it has
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QuestionWithAnswerCount {
+    #[serde(flatten)]
+    pub question: Question,
+    pub answer_count: i64,
+}
+
+/// A question bundled with its answers for `?include=answers`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct QuestionWithAnswers {
+    pub question: Question,
+    pub answers: Vec<Answer>,
+}
+
+/// Check a submitted question, reporting every problem at once
+pub fn validate_question(
+    title: &str,
+    content: &str,
+    tags: &Option<Vec<String>>,
+) -> Result<(), Error> {
+    let mut errors = Vec::new();
+
+    if title.trim().is_empty() {
+        errors.push(FieldError::new("title", "must not be empty"));
+    }
+    if content.trim().is_empty() {
+        errors.push(FieldError::new("content", "must not be empty"));
+    }
+    if let Some(tags) = tags
+        && tags.iter().any(|tag| tag.trim().is_empty())
+    {
+        errors.push(FieldError::new("tags", "must not contain empty tags"));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(errors))
+    }
+}
+
+/// Refuse question content shorter than `min_length` characters, not
+/// counting surrounding whitespace
+pub fn check_content_length(content: &str, min_length: usize) -> Result<(), Error> {
+    if content.trim().chars().count() < min_length {
+        return Err(Error::ContentTooShort(min_length));
+    }
+
+    Ok(())
+}
+
+/// Refuse edits more than `window_minutes` after the question was created,
+/// unless the window is 0 or the editor is an admin
+pub fn check_edit_window(
+    created_on: DateTime<Utc>,
+    now: DateTime<Utc>,
+    window_minutes: u64,
+    is_admin: bool,
+) -> Result<(), Error> {
+    if window_minutes == 0 || is_admin {
+        return Ok(());
+    }
+    if now - created_on > Duration::minutes(window_minutes as i64) {
+        return Err(Error::EditWindowExpired);
+    }
+
+    Ok(())
+}
+
+/// Refuse an update when the question was modified after the
+/// `If-Unmodified-Since` date. HTTP dates only go down to the second, so
+/// the modification time is compared truncated to it. A header which isn't
+/// a valid date is ignored, as RFC 7232 asks.
+pub fn check_unmodified_since(
+    modified_on: DateTime<Utc>,
+    if_unmodified_since: Option<&str>,
+) -> Result<(), Error> {
+    let Some(since) = if_unmodified_since.and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+    else {
+        return Ok(());
+    };
+    if modified_on.timestamp() > since.timestamp() {
+        return Err(Error::PreconditionFailed);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod question_tests {
+    use chrono::{Duration, Utc};
+
+    use super::{
+        ContentFormat, Error, check_content_length, check_edit_window, check_unmodified_since,
+        parse_content_format,
+    };
+
+    #[test]
+    fn content_format_defaults_to_plain() {
+        assert_eq!(parse_content_format(None).unwrap(), ContentFormat::Plain);
+        assert_eq!(
+            parse_content_format(Some("markdown")).unwrap(),
+            ContentFormat::Markdown
+        );
+        assert!(matches!(
+            parse_content_format(Some("html")),
+            Err(Error::InvalidParameter(_))
+        ));
+    }
+
+    #[test]
+    fn content_length_is_checked_after_trimming() {
+        assert!(matches!(
+            check_content_length("  too short  ", 10),
+            Err(Error::ContentTooShort(10))
+        ));
+        assert!(check_content_length("  exactly10!  ", 10).is_ok());
+        assert!(check_content_length("comfortably long enough", 10).is_ok());
+    }
+
+    #[test]
+    fn edits_inside_the_window_are_allowed() {
+        let now = Utc::now();
+        assert!(check_edit_window(now - Duration::minutes(5), now, 10, false).is_ok());
+    }
+
+    #[test]
+    fn edits_after_the_window_are_rejected() {
+        let now = Utc::now();
+        assert!(matches!(
+            check_edit_window(now - Duration::minutes(11), now, 10, false),
+            Err(Error::EditWindowExpired)
+        ));
+    }
+
+    #[test]
+    fn admins_and_a_zero_window_bypass_the_check() {
+        let now = Utc::now();
+        let created_on = now - Duration::days(30);
+        assert!(check_edit_window(created_on, now, 10, true).is_ok());
+        assert!(check_edit_window(created_on, now, 0, false).is_ok());
+    }
+
+    #[test]
+    fn unmodified_since_compares_whole_seconds() {
+        let modified_on = Utc::now();
+        let header = modified_on.to_rfc2822();
+        assert!(check_unmodified_since(modified_on, Some(&header)).is_ok());
+        assert!(check_unmodified_since(modified_on, None).is_ok());
+        assert!(check_unmodified_since(modified_on, Some("yesterday")).is_ok());
+
+        let stale = (modified_on - Duration::seconds(1)).to_rfc2822();
+        assert!(matches!(
+            check_unmodified_since(modified_on, Some(&stale)),
+            Err(Error::PreconditionFailed)
+        ));
+    }
+}
@@ -0,0 +1,89 @@
+use handle_errors::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Shortest and longest accepted search term, in characters
+const TERM_LENGTH: std::ops::RangeInclusive<usize> = 2..=100;
+
+/// A checked `q` parameter of the question search routes. Matches
+/// questions whose title or content contains it, ignoring case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchTerm(String);
+
+impl SearchTerm {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// `%term%` for `LIKE`/`ILIKE`, with `\` escaping the term's own
+    /// wildcards
+    pub fn like_pattern(&self) -> String {
+        let mut pattern = String::from("%");
+        for c in self.0.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('%');
+        pattern
+    }
+}
+
+/// Extract and check the `q` query parameter, trimmed
+/// # Example query
+/// `/questions/search/count?q=lifetimes`
+pub fn extract_search_term(params: &HashMap<String, String>) -> Result<SearchTerm, Error> {
+    let term = params
+        .get("q")
+        .map(|term| term.trim())
+        .ok_or(Error::MissingParameters)?;
+
+    if !TERM_LENGTH.contains(&term.chars().count()) {
+        return Err(Error::InvalidParameter(format!(
+            "q must be {} to {} characters long",
+            TERM_LENGTH.start(),
+            TERM_LENGTH.end()
+        )));
+    }
+
+    Ok(SearchTerm(term.to_string()))
+}
+
+/// Body of `GET /questions/search/count`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SearchCount {
+    pub count: i64,
+}
+
+#[cfg(test)]
+mod search_tests {
+    use super::{Error, HashMap, extract_search_term};
+
+    fn params(q: &str) -> HashMap<String, String> {
+        HashMap::from([("q".to_string(), q.to_string())])
+    }
+
+    #[test]
+    fn terms_are_trimmed_and_length_checked() {
+        assert_eq!(extract_search_term(&params("  warp  ")).unwrap().as_str(), "warp");
+        assert!(matches!(
+            extract_search_term(&params(" a ")),
+            Err(Error::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            extract_search_term(&params(&"x".repeat(101))),
+            Err(Error::InvalidParameter(_))
+        ));
+        assert!(matches!(
+            extract_search_term(&HashMap::new()),
+            Err(Error::MissingParameters)
+        ));
+    }
+
+    #[test]
+    fn wildcards_in_terms_are_escaped() {
+        let term = extract_search_term(&params("100%_sure")).unwrap();
+        assert_eq!(term.like_pattern(), "%100\\%\\_sure%");
+    }
+}
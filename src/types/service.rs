@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What `GET /` answers with: who is running and where to go next
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServiceDescriptor {
+    pub name: String,
+    pub version: String,
+    pub links: BTreeMap<String, String>,
+}
@@ -0,0 +1,186 @@
+use handle_errors::Error;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Column the question listing is ordered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortColumn {
+    Id,
+    CreatedOn,
+    Title,
+    /// Number of answers posted to the question
+    Answers,
+    /// Net vote score, upvotes minus downvotes
+    Top,
+}
+
+impl SortColumn {
+    /// Column name as used in the `ORDER BY` clause
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SortColumn::Id => "id",
+            SortColumn::CreatedOn => "created_on",
+            SortColumn::Title => "title",
+            // Questions without answers have no row in the joined counts
+            SortColumn::Answers => "COALESCE(answer_count, 0)",
+            SortColumn::Top => "COALESCE(score, 0)",
+        }
+    }
+}
+
+impl FromStr for SortColumn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(SortColumn::Id),
+            "created_on" => Ok(SortColumn::CreatedOn),
+            "title" => Ok(SortColumn::Title),
+            "answers" => Ok(SortColumn::Answers),
+            "top" => Ok(SortColumn::Top),
+            _ => Err(format!("unknown sort column `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl SortDirection {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SortDirection::Asc => "ASC",
+            SortDirection::Desc => "DESC",
+        }
+    }
+}
+
+impl FromStr for SortDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "asc" => Ok(SortDirection::Asc),
+            "desc" => Ok(SortDirection::Desc),
+            _ => Err(format!("unknown sort order `{}`", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub column: SortColumn,
+    pub direction: SortDirection,
+}
+
+impl Sort {
+    /// Body of the `ORDER BY` clause. Answer counts and scores tie often,
+    /// so those orderings fall back to the newest question first. Every ordering
+    /// ends with the unique `id`, so rows with equal sort keys come in the
+    /// same order on every page.
+    pub fn order_by(&self) -> String {
+        match self.column {
+            SortColumn::Id => format!("id {}", self.direction.as_sql()),
+            SortColumn::Answers | SortColumn::Top => format!(
+                "{} {}, created_on DESC, id DESC",
+                self.column.as_sql(),
+                self.direction.as_sql()
+            ),
+            column => format!(
+                "{} {}, id {}",
+                column.as_sql(),
+                self.direction.as_sql(),
+                self.direction.as_sql()
+            ),
+        }
+    }
+}
+
+/// Extract the `sort` and `order` query parameters, falling back to the
+/// configured default for whichever one is missing. `sort=answers` lists
+/// the most discussed questions first and `sort=top` the best voted ones,
+/// unless an `order` is given.
+/// # Example query
+/// `/questions?sort=created_on&order=desc`
+pub fn extract_sort(params: &HashMap<String, String>, default: Sort) -> Result<Sort, Error> {
+    let column = match params.get("sort") {
+        Some(column) => column.parse().map_err(Error::InvalidParameter)?,
+        None => default.column,
+    };
+
+    let direction = match params.get("order") {
+        Some(direction) => direction.parse().map_err(Error::InvalidParameter)?,
+        None if matches!(column, SortColumn::Answers | SortColumn::Top) => SortDirection::Desc,
+        None => default.direction,
+    };
+
+    Ok(Sort { column, direction })
+}
+
+#[cfg(test)]
+mod sort_tests {
+    use super::{HashMap, Sort, SortColumn, SortDirection, extract_sort};
+
+    const DEFAULT: Sort = Sort {
+        column: SortColumn::CreatedOn,
+        direction: SortDirection::Desc,
+    };
+
+    #[test]
+    fn default_applies_without_params() {
+        let sort = extract_sort(&HashMap::new(), DEFAULT).unwrap();
+        assert_eq!(sort, DEFAULT);
+    }
+
+    #[test]
+    fn params_override_default() {
+        let mut params = HashMap::new();
+        params.insert(String::from("sort"), String::from("title"));
+        params.insert(String::from("order"), String::from("asc"));
+
+        let sort = extract_sort(&params, DEFAULT).unwrap();
+        assert_eq!(sort.column, SortColumn::Title);
+        assert_eq!(sort.direction, SortDirection::Asc);
+    }
+
+    #[test]
+    fn answers_sort_defaults_to_descending() {
+        let mut params = HashMap::new();
+        params.insert(String::from("sort"), String::from("answers"));
+
+        let sort = extract_sort(&params, DEFAULT).unwrap();
+        assert_eq!(sort.column, SortColumn::Answers);
+        assert_eq!(sort.direction, SortDirection::Desc);
+    }
+
+    #[test]
+    fn ties_are_broken_by_id() {
+        let sort = |column, direction| Sort { column, direction }.order_by();
+
+        assert_eq!(sort(SortColumn::Id, SortDirection::Asc), "id ASC");
+        assert_eq!(sort(SortColumn::Title, SortDirection::Asc), "title ASC, id ASC");
+        assert_eq!(
+            sort(SortColumn::CreatedOn, SortDirection::Desc),
+            "created_on DESC, id DESC"
+        );
+        assert!(sort(SortColumn::Answers, SortDirection::Asc).ends_with(", id DESC"));
+        assert_eq!(
+            sort(SortColumn::Top, SortDirection::Desc),
+            "COALESCE(score, 0) DESC, created_on DESC, id DESC"
+        );
+    }
+
+    #[test]
+    fn unknown_column_is_rejected() {
+        let mut params = HashMap::new();
+        params.insert(String::from("sort"), String::from("password"));
+
+        assert!(extract_sort(&params, DEFAULT).is_err());
+    }
+}
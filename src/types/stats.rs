@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::account::AccountId;
+
+/// Row counts across the whole system, for the admin dashboard
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub questions: i64,
+    pub answers: i64,
+    pub accounts: i64,
+}
+
+/// What an account has posted, shown on its public profile
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AccountStats {
+    pub account_id: AccountId,
+    pub questions: i64,
+    pub answers: i64,
+}
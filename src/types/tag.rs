@@ -0,0 +1,78 @@
+use handle_errors::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A tag and the number of questions carrying it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TagCount {
+    pub name: String,
+    pub count: i64,
+}
+
+/// Narrows `/questions` down by tag: `?tags=a,b` keeps questions carrying
+/// all of the tags, `?tags_any=a,b` those carrying at least one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagFilter {
+    All(Vec<String>),
+    Any(Vec<String>),
+}
+
+impl TagFilter {
+    /// The tags asked for, without duplicates
+    pub fn tags(&self) -> &[String] {
+        match self {
+            TagFilter::All(tags) | TagFilter::Any(tags) => tags,
+        }
+    }
+
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            TagFilter::All(wanted) => wanted.iter().all(|tag| tags.contains(tag)),
+            TagFilter::Any(wanted) => wanted.iter().any(|tag| tags.contains(tag)),
+        }
+    }
+}
+
+/// A question's tags with repeats dropped, ignoring case. The first
+/// spelling of each tag is the one kept, in the order sent.
+pub fn dedupe_tags(tags: Option<Vec<String>>) -> Option<Vec<String>> {
+    tags.map(|tags| {
+        let mut seen: Vec<String> = Vec::new();
+        let mut unique = Vec::new();
+        for tag in tags {
+            let lowercase = tag.to_lowercase();
+            if !seen.contains(&lowercase) {
+                seen.push(lowercase);
+                unique.push(tag);
+            }
+        }
+        unique
+    })
+}
+
+/// Extract the `tags` or `tags_any` query parameter, comma separated. The
+/// two can't be combined. An empty list filters nothing.
+pub fn extract_tag_filter(params: &HashMap<String, String>) -> Result<Option<TagFilter>, Error> {
+    let split = |list: &String| {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in list.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+            if !tags.iter().any(|seen| seen == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+        tags
+    };
+
+    let filter = match (params.get("tags"), params.get("tags_any")) {
+        (Some(_), Some(_)) => {
+            return Err(Error::InvalidParameter(
+                "tags and tags_any can't be combined".to_string(),
+            ));
+        }
+        (Some(all), None) => TagFilter::All(split(all)),
+        (None, Some(any)) => TagFilter::Any(split(any)),
+        (None, None) => return Ok(None),
+    };
+
+    Ok((!filter.tags().is_empty()).then_some(filter))
+}
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+use crate::types::question::Question;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Vote {
+    Up,
+    Down,
+}
+
+impl Vote {
+    /// Value stored in the `votes.value` column
+    pub fn value(&self) -> i16 {
+        match self {
+            Vote::Up => 1,
+            Vote::Down => -1,
+        }
+    }
+
+    pub fn from_value(value: i16) -> Option<Vote> {
+        match value {
+            1 => Some(Vote::Up),
+            -1 => Some(Vote::Down),
+            _ => None,
+        }
+    }
+}
+
+/// How the requesting account voted on a question
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VoteStatus {
+    Up,
+    Down,
+    None,
+}
+
+impl From<Option<Vote>> for VoteStatus {
+    fn from(vote: Option<Vote>) -> Self {
+        match vote {
+            Some(Vote::Up) => VoteStatus::Up,
+            Some(Vote::Down) => VoteStatus::Down,
+            None => VoteStatus::None,
+        }
+    }
+}
+
+/// Body of `POST /questions/:id/vote`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewVote {
+    pub vote: Vote,
+}
+
+/// A question's score right after the requesting account voted on it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VoteScore {
+    pub score: i64,
+    pub my_vote: VoteStatus,
+}
+
+/// A listed question as seen by an authenticated account
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuestionWithVote {
+    #[serde(flatten)]
+    pub question: Question,
+    pub my_vote: VoteStatus,
+}